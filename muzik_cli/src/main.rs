@@ -10,7 +10,10 @@ use tracing_subscriber::{
 use youtube_dl::{SearchOptions, YoutubeDl};
 
 use muzik_common::{
-    database::{self, AppSong},
+    database::{self, error::DatabaseError, AppSong, DEFAULT_SEARCH_LIMIT},
+    entities::{change_set::ChangeOrigin, song_relationship::RelationshipKind},
+    export::LibraryExport,
+    lock::MusicDirLock,
     tags,
 };
 
@@ -21,6 +24,9 @@ mod config;
 #[derive(Debug, Parser)]
 #[command(name = "muzik")]
 struct Cli {
+    /// Library from the config to use
+    #[arg(short, long, global = true)]
+    library: Option<String>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -33,14 +39,132 @@ enum Commands {
         query: Vec<String>,
     },
     List,
+    /// Search the library by title, artist, album or genre
+    #[command(arg_required_else_help = true)]
+    Search {
+        /// Show at most this many songs
+        #[arg(short = 'n', long, default_value_t = DEFAULT_SEARCH_LIMIT)]
+        limit: u64,
+        #[arg(num_args = .., trailing_var_arg = true)]
+        query: Vec<String>,
+    },
     Delete,
+    /// Remove artists, albums, genres and playlist ids no song uses anymore
+    Clean {
+        /// Only show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Revert a recent metadata change
+    Undo,
+    /// Export the library as JSON, or as CSV if the file ends in .csv
+    #[command(arg_required_else_help = true)]
+    Export {
+        path: PathBuf,
+    },
+    /// Import a JSON export, songs already in the library are kept as they are
+    #[command(arg_required_else_help = true)]
+    Import {
+        path: PathBuf,
+    },
+    /// Rate a song from 0 to 5 stars, found by title, artist, album or genre
+    #[command(arg_required_else_help = true)]
+    Rate {
+        #[arg(value_parser = clap::value_parser!(i32).range(0..=5))]
+        rating: i32,
+        #[arg(num_args = .., trailing_var_arg = true)]
+        query: Vec<String>,
+    },
+    /// Move a song, found by title, artist, album or genre, into another library
+    #[command(arg_required_else_help = true)]
+    Move {
+        /// Name of the library to move the song to
+        to: String,
+        #[arg(num_args = .., trailing_var_arg = true)]
+        query: Vec<String>,
+    },
+    /// Mark a song as a cover, remix, live version or alternate upload of another song,
+    /// both found by title, artist, album or genre
+    #[command(arg_required_else_help = true)]
+    Link {
+        /// cover, remix, live or alternate
+        kind: RelationshipKind,
+        /// The cover, remix, live version or alternate upload
+        song: String,
+        /// The song it is a version of
+        original: String,
+    },
+    /// Remove a link between two songs, found by title, artist, album or genre
+    #[command(arg_required_else_help = true)]
+    Unlink {
+        #[arg(num_args = .., trailing_var_arg = true)]
+        query: Vec<String>,
+    },
+    /// Create, fill, reorder and export local playlists
+    #[command(subcommand)]
+    Playlist(PlaylistCommand),
+    /// Merge duplicate artists and list their aliases
+    #[command(subcommand)]
+    Artist(ArtistCommand),
+    /// Replace the database with a backup taken before a migration
+    Restore {
+        /// Backup file to restore, picked from the backups when not given
+        backup: Option<PathBuf>,
+    },
     DbTest,
 }
 
+#[derive(Debug, Subcommand)]
+enum PlaylistCommand {
+    #[command(arg_required_else_help = true)]
+    Create {
+        name: String,
+        #[arg(short, long)]
+        description: Option<String>,
+    },
+    /// Append a song, found by title, artist, album or genre
+    #[command(arg_required_else_help = true)]
+    Add {
+        playlist: String,
+        #[arg(num_args = .., trailing_var_arg = true)]
+        query: Vec<String>,
+    },
+    /// Show the songs of a playlist with their positions
+    #[command(arg_required_else_help = true)]
+    Show { playlist: String },
+    /// Move the song at position `from` to position `to`, counting from 1
+    #[command(arg_required_else_help = true)]
+    Reorder {
+        playlist: String,
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+        from: u32,
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+        to: u32,
+    },
+    /// Write the playlist as M3U8 to the music dir
+    #[command(arg_required_else_help = true)]
+    Export { playlist: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum ArtistCommand {
+    /// Move the songs of the duplicates to the artist, keeping their names as aliases
+    #[command(arg_required_else_help = true)]
+    Merge {
+        artist: String,
+        #[arg(required = true)]
+        duplicates: Vec<String>,
+    },
+    /// Show the other spellings that resolve to the artist
+    #[command(arg_required_else_help = true)]
+    Aliases { artist: String },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     info!("log started");
     let args = Cli::parse();
+    let library = args.library;
 
     if let Some(command) = args.command {
         match command {
@@ -50,12 +174,32 @@ async fn main() -> Result<()> {
                 // use that subscriber to process traces emitted after this point
                 tracing::subscriber::set_global_default(subscriber)?;
                 // TODO: switch to new backend
-                download_command(query).await.unwrap();
+                download_command(library, query).await.unwrap();
             }
             // TODO: switch to new backend
-            Commands::List => list_command().await.unwrap(),
+            Commands::List => list_command(library).await.unwrap(),
+            Commands::Search { limit, query } => {
+                search_command(library, query.join(" "), limit).await?
+            }
             // TODO: switch to new backend
             Commands::Delete => delete_command().await.unwrap(),
+            Commands::Clean { dry_run } => clean_command(library, dry_run).await?,
+            Commands::Undo => undo_command(library).await?,
+            Commands::Export { path } => export_command(library, path).await?,
+            Commands::Import { path } => import_command(library, path).await?,
+            Commands::Rate { rating, query } => {
+                rate_command(library, rating, query.join(" ")).await?
+            }
+            Commands::Move { to, query } => move_command(library, to, query.join(" ")).await?,
+            Commands::Link {
+                kind,
+                song,
+                original,
+            } => link_command(library, kind, song, original).await?,
+            Commands::Unlink { query } => unlink_command(library, query.join(" ")).await?,
+            Commands::Playlist(command) => playlist_command(library, command).await?,
+            Commands::Artist(command) => artist_command(library, command).await?,
+            Commands::Restore { backup } => restore_command(library, backup).await?,
             Commands::DbTest => {
                 // construct a subscriber that prints formatted traces to stdout
                 let _subscriber = tracing_subscriber::registry().with(
@@ -87,8 +231,8 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn download_command(query: Vec<String>) -> Result<()> {
-    let config = ReadConfig::read_config(None).await?;
+async fn download_command(library: Option<String>, query: Vec<String>) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
     debug!("music dir is : {}", config.music_dir.display());
     let name: String = query.join(" ");
 
@@ -146,6 +290,8 @@ async fn download_command(query: Vec<String>) -> Result<()> {
                     let filename_format = format!("{} - {}.%(ext)s", title.clone(), artist.clone());
                     let filename = format!("{} - {}.opus", title.clone(), artist.clone());
                     let filename = config.get_music_dir().join(filename);
+                    // held until the file is tagged or moved away
+                    let _lock = MusicDirLock::try_acquire(&config.get_music_dir(), "downloading")?;
                     let _youtube = YoutubeDl::new(id.clone())
                         .youtube_dl_path("yt-dlp")
                         .extra_arg("--audio-format")
@@ -182,8 +328,13 @@ async fn download_command(query: Vec<String>) -> Result<()> {
                         .compute_new_filename();
 
                     match tags::write_tags_async(filename.clone(), &song).await {
-                        Ok(_) => {
-                            config.db_new.insert_from_app_song(song).await?;
+                        Ok(audio_properties) => {
+                            config
+                                .db_new
+                                .insert_from_app_song(
+                                    song.with_audio_properties(Some(audio_properties)),
+                                )
+                                .await?;
                             info!("database updated")
                         }
                         Err(e) => {
@@ -208,8 +359,93 @@ async fn download_command(query: Vec<String>) -> Result<()> {
     }
 }
 
-async fn list_command() -> Result<()> {
-    let _config = ReadConfig::read_config(None).await?;
+async fn clean_command(library: Option<String>, dry_run: bool) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let report = config.db_new.clean_orphans(dry_run).await?;
+    if report.is_empty() {
+        println!("{}", report.summary());
+    } else if dry_run {
+        println!("would remove:\n{}", report.summary());
+    } else {
+        println!("removed:\n{}", report.summary());
+    }
+    Ok(())
+}
+
+async fn export_command(library: Option<String>, path: PathBuf) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let export = config.db_new.export_library().await?;
+    export.write_to_path(&path)?;
+    println!(
+        "exported {} songs and {} playlists to {}",
+        export.songs.len(),
+        export.playlists.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+async fn import_command(library: Option<String>, path: PathBuf) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let export = LibraryExport::read_from_path(&path)?;
+    let report = config
+        .db_new
+        .import_library(&export, config.get_music_dir())
+        .await?;
+    println!("{}", report.summary());
+    Ok(())
+}
+
+async fn undo_command(library: Option<String>) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let change_sets = config
+        .db_new
+        .get_change_sets(20)
+        .await?
+        .into_iter()
+        .filter(|(change_set, _)| !change_set.undone)
+        .collect::<Vec<_>>();
+    if change_sets.is_empty() {
+        println!("nothing to undo");
+        return Ok(());
+    }
+
+    let items = change_sets
+        .iter()
+        .map(|(change_set, changes)| {
+            let changes = changes
+                .iter()
+                .map(|change| {
+                    format!(
+                        "{}: {} -> {}",
+                        change.field,
+                        change.old_value.as_deref().unwrap_or("None"),
+                        change.new_value.as_deref().unwrap_or("None")
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("[{}] {} | {}", change_set.id, change_set.origin, changes)
+        })
+        .collect::<Vec<_>>();
+    let selection = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .items(&items)
+        .default(0)
+        .interact_opt()?;
+
+    if let Some(index) = selection {
+        let (change_set, _) = &change_sets[index];
+        config
+            .db_new
+            .undo_change_set(change_set.id, config.get_music_dir())
+            .await?;
+        println!("reverted: {}", items[index]);
+    }
+    Ok(())
+}
+
+async fn list_command(library: Option<String>) -> Result<()> {
+    let _config = ReadConfig::read_config(None, library).await?;
     // TODO: implement new db
     // let e = db.get_all("/home/luqman/Music".into())?;
     //
@@ -227,6 +463,345 @@ async fn list_command() -> Result<()> {
     Ok(())
 }
 
+async fn search_command(library: Option<String>, query: String, limit: u64) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let songs = config
+        .db_new
+        .search(&query, Some(limit), config.get_music_dir())
+        .await?;
+
+    if songs.is_empty() {
+        println!("no songs matching \"{query}\"");
+    } else {
+        for s in songs {
+            println!("{} - {}", s.get_title_string(), s.get_artists_string());
+        }
+    }
+    Ok(())
+}
+
+async fn rate_command(library: Option<String>, rating: i32, query: String) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let songs = config
+        .db_new
+        .search(&query, Some(DEFAULT_SEARCH_LIMIT), config.get_music_dir())
+        .await?;
+    let Some(song) = select_song(&songs, &query)? else {
+        return Ok(());
+    };
+
+    if let Some(song_id) = song.id {
+        config
+            .db_new
+            .set_rating(song_id, Some(rating), config.get_music_dir())
+            .await?;
+        println!(
+            "rated {} - {}: {}",
+            song.get_title_string(),
+            song.get_artists_string(),
+            rating
+        );
+    }
+    Ok(())
+}
+
+async fn move_command(library: Option<String>, to: String, query: String) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let target = config.get_library(&to).map_err(|e| eyre!("{e}"))?;
+    if target.name == config.library {
+        return Err(eyre!("The song is already in library {to}"));
+    }
+    let songs = config
+        .db_new
+        .search(&query, Some(DEFAULT_SEARCH_LIMIT), config.get_music_dir())
+        .await?;
+    let Some(song) = select_song(&songs, &query)? else {
+        return Ok(());
+    };
+
+    if let Some(song_id) = song.id {
+        let target_db = target.connect().await.map_err(|e| eyre!("{e}"))?;
+        config
+            .db_new
+            .move_song_to_library(
+                song_id,
+                config.get_music_dir(),
+                &target_db,
+                target.music_dir.clone(),
+            )
+            .await?;
+        println!(
+            "moved {} - {} to {}",
+            song.get_title_string(),
+            song.get_artists_string(),
+            target.name
+        );
+    }
+    Ok(())
+}
+
+async fn link_command(
+    library: Option<String>,
+    kind: RelationshipKind,
+    song_query: String,
+    original_query: String,
+) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let songs = config
+        .db_new
+        .search(
+            &song_query,
+            Some(DEFAULT_SEARCH_LIMIT),
+            config.get_music_dir(),
+        )
+        .await?;
+    let Some(song) = select_song(&songs, &song_query)? else {
+        return Ok(());
+    };
+    let originals = config
+        .db_new
+        .search(
+            &original_query,
+            Some(DEFAULT_SEARCH_LIMIT),
+            config.get_music_dir(),
+        )
+        .await?;
+    let Some(original) = select_song(&originals, &original_query)? else {
+        return Ok(());
+    };
+
+    if let (Some(song_id), Some(original_id)) = (song.id, original.id) {
+        config.db_new.link_songs(song_id, original_id, kind).await?;
+        println!(
+            "{} is a {kind} of {}",
+            song.get_title_string(),
+            original.get_title_string()
+        );
+    }
+    Ok(())
+}
+
+async fn unlink_command(library: Option<String>, query: String) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let songs = config
+        .db_new
+        .search(&query, Some(DEFAULT_SEARCH_LIMIT), config.get_music_dir())
+        .await?;
+    let Some(song) = select_song(&songs, &query)? else {
+        return Ok(());
+    };
+    let Some(song_id) = song.id else {
+        return Ok(());
+    };
+
+    let related = config.db_new.get_song_relationships(song_id).await?;
+    if related.is_empty() {
+        println!("{} isn't linked to any song", song.get_title_string());
+        return Ok(());
+    }
+    let items = related.iter().map(|r| r.describe()).collect::<Vec<_>>();
+    let Some(index) = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .items(&items)
+        .default(0)
+        .interact_opt()?
+    else {
+        return Ok(());
+    };
+
+    config
+        .db_new
+        .unlink_songs(song_id, related[index].song_id)
+        .await?;
+    println!("unlinked {}", items[index]);
+    Ok(())
+}
+
+async fn playlist_command(library: Option<String>, command: PlaylistCommand) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let db = &config.db_new;
+    let find_playlist = |name: String| async move {
+        db.get_all_playlists()
+            .await?
+            .into_iter()
+            .find(|playlist| playlist.name == name)
+            .ok_or_else(|| eyre!("No playlist named {name}"))
+    };
+
+    match command {
+        PlaylistCommand::Create { name, description } => {
+            db.create_playlist(name.clone(), description).await?;
+            println!("created playlist {name}");
+        }
+        PlaylistCommand::Add { playlist, query } => {
+            let playlist = find_playlist(playlist).await?;
+            let query = query.join(" ");
+            let songs = db
+                .search(&query, Some(DEFAULT_SEARCH_LIMIT), config.get_music_dir())
+                .await?;
+            let Some(song) = select_song(&songs, &query)? else {
+                return Ok(());
+            };
+            if let Some(song_id) = song.id {
+                db.add_song_to_playlist(playlist.id, song_id).await?;
+                println!(
+                    "added {} - {} to {}",
+                    song.get_title_string(),
+                    song.get_artists_string(),
+                    playlist.name
+                );
+            }
+        }
+        PlaylistCommand::Show { playlist } => {
+            let playlist = find_playlist(playlist).await?;
+            let songs = db.get_playlist_songs(playlist.id).await?;
+            if songs.is_empty() {
+                println!("playlist {} is empty", playlist.name);
+            }
+            for (position, song) in songs.iter().enumerate() {
+                println!("{:>3}. {}", position + 1, song.title);
+            }
+        }
+        PlaylistCommand::Reorder { playlist, from, to } => {
+            let playlist = find_playlist(playlist).await?;
+            match db
+                .move_playlist_song(playlist.id, from as usize - 1, to as usize - 1)
+                .await
+            {
+                Err(DatabaseError::NoSongFound) => {
+                    return Err(eyre!(
+                        "Playlist {} has no song at {from} or {to}",
+                        playlist.name
+                    ))
+                }
+                result => result?,
+            }
+            println!("moved song {from} of {} to {to}", playlist.name);
+        }
+        PlaylistCommand::Export { playlist } => {
+            let playlist = find_playlist(playlist).await?;
+            let export = db
+                .export_playlist_m3u8(playlist.id, config.get_music_dir())
+                .await?;
+            println!("exported {} to {}", playlist.name, export.path.display());
+            for song in &export.skipped {
+                println!("left out {}, it has no file", song.title);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn artist_command(library: Option<String>, command: ArtistCommand) -> Result<()> {
+    let config = ReadConfig::read_config(None, library).await?;
+    let db = &config.db_new;
+    let find_artist = |name: String| async move {
+        db.find_artist(&name)
+            .await?
+            .ok_or_else(|| eyre!("No artist named {name}"))
+    };
+
+    match command {
+        ArtistCommand::Merge { artist, duplicates } => {
+            let artist = find_artist(artist).await?;
+            let mut duplicate_ids = vec![];
+            for duplicate in duplicates {
+                duplicate_ids.push(find_artist(duplicate).await?.id);
+            }
+            // the tags of the merged songs are rewritten
+            let _lock = MusicDirLock::try_acquire(&config.get_music_dir(), "merging artists")?;
+            let songs = db
+                .merge_artists(
+                    artist.id,
+                    duplicate_ids,
+                    ChangeOrigin::Cli,
+                    config.get_music_dir(),
+                )
+                .await?;
+            println!("merged {} songs into {}", songs.len(), artist.name);
+        }
+        ArtistCommand::Aliases { artist } => {
+            let artist = find_artist(artist).await?;
+            let aliases = db.get_artist_aliases(artist.id).await?;
+            if aliases.is_empty() {
+                println!("{} has no aliases", artist.name);
+            }
+            for alias in aliases {
+                println!("{alias}");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn restore_command(library: Option<String>, backup: Option<PathBuf>) -> Result<()> {
+    // the database is left closed, it may be the reason for restoring
+    let config = ReadConfig::read_config_without_database(None, library)?;
+    let library = config
+        .get_library(&config.library)
+        .map_err(|e| eyre!("{e}"))?;
+    let backup = match backup {
+        Some(backup) => backup,
+        None => {
+            let backups = database::DbConnection::list_backups(&library.database_path)?;
+            if backups.is_empty() {
+                println!("no backups of library {}", library.name);
+                return Ok(());
+            }
+            let items = backups
+                .iter()
+                .map(|backup| backup.display().to_string())
+                .collect::<Vec<_>>();
+            let Some(index) = FuzzySelect::with_theme(&ColorfulTheme::default())
+                .with_prompt("Backup to restore")
+                .items(&items)
+                .default(0)
+                .interact_opt()?
+            else {
+                return Ok(());
+            };
+            backups[index].clone()
+        }
+    };
+
+    let previous =
+        database::DbConnection::restore(&library.database_path, &backup, library.keep_backups)
+            .await?;
+    println!(
+        "restored library {} from {}",
+        library.name,
+        backup.display()
+    );
+    if let Some(previous) = previous {
+        println!(
+            "the replaced database was backed up to {}",
+            previous.display()
+        );
+    }
+    Ok(())
+}
+
+/// Picks one of the songs found for `query`, asking only when there is more than one
+fn select_song<'a>(songs: &'a [AppSong], query: &str) -> Result<Option<&'a AppSong>> {
+    match songs.len() {
+        0 => {
+            println!("no songs matching \"{query}\"");
+            Ok(None)
+        }
+        1 => Ok(Some(&songs[0])),
+        _ => {
+            let items = songs
+                .iter()
+                .map(|s| format!("{} - {}", s.get_title_string(), s.get_artists_string()))
+                .collect::<Vec<_>>();
+            Ok(FuzzySelect::with_theme(&ColorfulTheme::default())
+                .items(&items)
+                .default(0)
+                .interact_opt()?
+                .map(|index| &songs[index]))
+        }
+    }
+}
+
 async fn delete_command() -> Result<()> {
     // let db = Database::new("/home/luqman/Music/database.sqlite".into())?;
     // TODO: implement new db
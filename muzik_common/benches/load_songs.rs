@@ -0,0 +1,58 @@
+//! Loads whole libraries of different sizes. Relations are fetched in batches, so
+//! the time per song, shown as throughput, should stay the same as the library grows.
+
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use muzik_common::{
+    database::{AppSong, DbConnection},
+    migrator::Migrator,
+};
+use sea_orm_migration::MigratorTrait;
+use tokio::runtime::Runtime;
+
+const LIBRARY_SIZES: [usize; 3] = [100, 1_000, 5_000];
+
+/// A library of `songs` songs, each with two artists, an album, a genre and a playlist
+async fn library(songs: usize) -> DbConnection {
+    let db = DbConnection::open_in_memory().await;
+    Migrator::up(db.ref_db().unwrap(), None).await.unwrap();
+    db.transaction(|db| async move {
+        for index in 0..songs {
+            let song = AppSong::new()
+                .with_title(Some(format!("Song {index}")))
+                .with_artists_string(format!("Artist {}; Guest {}", index % 50, index % 7))
+                .with_albums(format!("Album {}", index % 200))
+                .with_genre(format!("Genre {}", index % 10))
+                .with_yt_id(Some(format!("video{index}")))
+                .with_yt_playlist_id(Some(format!("playlist{}", index % 5)));
+            db.insert_from_app_song(song).await?;
+        }
+        Ok(())
+    })
+    .await
+    .unwrap();
+    db
+}
+
+fn load_songs(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("load_songs");
+    group.sample_size(10);
+    for songs in LIBRARY_SIZES {
+        let db = runtime.block_on(library(songs));
+        group.throughput(Throughput::Elements(songs as u64));
+        group.bench_with_input(BenchmarkId::new("get_all_songs", songs), &db, |b, db| {
+            b.iter(|| runtime.block_on(db.get_all_songs(PathBuf::new())).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("get_all_songs_gui", songs),
+            &db,
+            |b, db| b.iter(|| runtime.block_on(db.get_all_songs_gui(PathBuf::new()))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, load_songs);
+criterion_main!(benches);
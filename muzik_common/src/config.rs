@@ -1,21 +1,58 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
-use super::database::DbConnection;
+use super::database::{DbConnection, DEFAULT_KEEP_BACKUPS};
 use etcetera::{choose_app_strategy, AppStrategy, AppStrategyArgs};
-use miette::{IntoDiagnostic, Result};
+use miette::{miette, IntoDiagnostic, Result};
 use serde::Deserialize;
 
 #[derive(Deserialize)]
 pub struct ReadConfig {
     version: usize,
     music_dir: Option<PathBuf>,
+    /// defaults to `database.sqlite` in the data dir, `database` is read as well
+    #[serde(alias = "database")]
+    database_path: Option<PathBuf>,
     cookies: Option<PathBuf>,
     yt_playlist_sync: Option<Vec<String>>,
+    /// remove artists, albums, genres and playlist ids without songs after deleting songs
+    auto_clean_orphans: Option<bool>,
+    /// also write song labels to the `MUZIK_LABELS` tag of the files
+    label_tags: Option<bool>,
+    /// database backups kept from before migrations, 0 turns them off
+    backups: Option<usize>,
+    /// library opened when none is selected, defaults to `default`
+    library: Option<String>,
+    /// named libraries besides the top level `music_dir`, which is the `default` one
+    libraries: Option<BTreeMap<String, ReadLibrary>>,
+}
+
+#[derive(Deserialize)]
+struct ReadLibrary {
+    music_dir: PathBuf,
+    /// defaults to `libraries/<name>.sqlite` in the data dir, see `library_file_name`
+    #[serde(alias = "database")]
+    database_path: Option<PathBuf>,
+    yt_playlist_sync: Option<Vec<String>>,
 }
 
 impl ReadConfig {
-    /// Read config from provided path
-    pub async fn read_config(path: Option<PathBuf>) -> Result<Config> {
+    /// Read config from provided path and open the given library. Without one the
+    /// `MUZIK_LIBRARY` environment variable is used, then the `library` key.
+    pub async fn read_config(path: Option<PathBuf>, library: Option<String>) -> Result<Config> {
+        let mut config = Self::read_config_without_database(path, library)?;
+        let active = config.get_library(&config.library)?.clone();
+        config.db_new = active
+            .connect()
+            .await?
+            .with_auto_clean_orphans(config.auto_clean_orphans);
+        Ok(config)
+    }
+
+    /// Like `read_config`, leaving the database closed
+    pub fn read_config_without_database(
+        path: Option<PathBuf>,
+        library: Option<String>,
+    ) -> Result<Config> {
         let strategy = choose_app_strategy(AppStrategyArgs {
             top_level_domain: "".to_string(),
             author: "".to_string(),
@@ -58,7 +95,59 @@ impl ReadConfig {
             }
         };
 
-        let db_new = DbConnection::m_new(music_dir.join("database.sqlite")).await?;
+        let data_dir = strategy.data_dir();
+        let keep_backups = conf.backups.unwrap_or(DEFAULT_KEEP_BACKUPS);
+        let mut libraries = vec![Library {
+            name: DEFAULT_LIBRARY.to_string(),
+            database_path: conf
+                .database_path
+                .unwrap_or_else(|| data_dir.join("database.sqlite")),
+            music_dir,
+            yt_playlist_sync: conf.yt_playlist_sync,
+            keep_backups,
+        }];
+        for (name, library) in conf.libraries.unwrap_or_default() {
+            let library = Library {
+                database_path: library.database_path.unwrap_or_else(|| {
+                    data_dir
+                        .join("libraries")
+                        .join(format!("{}.sqlite", library_file_name(&name)))
+                }),
+                name,
+                music_dir: library.music_dir,
+                yt_playlist_sync: library.yt_playlist_sync,
+                keep_backups,
+            };
+            // a `default` entry replaces the top level music dir
+            match libraries.iter_mut().find(|old| old.name == library.name) {
+                Some(old) => *old = library,
+                None => libraries.push(library),
+            }
+        }
+
+        for (index, library) in libraries.iter().enumerate() {
+            if let Some(other) = libraries[..index]
+                .iter()
+                .find(|other| other.database_path == library.database_path)
+            {
+                return Err(miette!(
+                    "Libraries {} and {} both use the database {}, set `database_path` for one",
+                    other.name,
+                    library.name,
+                    library.database_path.display()
+                ));
+            }
+        }
+
+        let library = library
+            .or_else(|| std::env::var("MUZIK_LIBRARY").ok())
+            .or(conf.library)
+            .unwrap_or_else(|| DEFAULT_LIBRARY.to_string());
+        let active = libraries
+            .iter()
+            .find(|entry| entry.name == library)
+            .cloned()
+            .ok_or_else(|| miette!("No library named {library} in the config"))?;
 
         let cookies = {
             if let Some(cookies_path) = conf.cookies {
@@ -68,30 +157,86 @@ impl ReadConfig {
             }
         };
         Ok(Config {
-            music_dir,
-            db_new,
+            music_dir: active.music_dir,
+            db_new: DbConnection::default(),
+            library,
+            libraries,
             cookies: if cookies.exists() {
                 Some(cookies)
             } else {
                 None
             },
-            yt_playlist_sync: conf.yt_playlist_sync,
+            yt_playlist_sync: active.yt_playlist_sync,
+            auto_clean_orphans: conf.auto_clean_orphans.unwrap_or(false),
+            label_tags: conf.label_tags.unwrap_or(false),
         })
     }
 }
 
+/// Name of the library made from the top level `music_dir`
+pub const DEFAULT_LIBRARY: &str = "default";
+
+/// `name` with everything but letters, digits, `-` and `_` replaced by `_`, so a library
+/// name can't point its database outside the `libraries` dir
+pub fn library_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// A music dir with its own database and playlists to sync
+#[derive(Clone, Debug)]
+pub struct Library {
+    pub name: String,
+    pub music_dir: PathBuf,
+    pub database_path: PathBuf,
+    pub yt_playlist_sync: Option<Vec<String>>,
+    /// backups kept from before migrations
+    pub keep_backups: usize,
+}
+
+impl Library {
+    /// Opens the database, first moving it out of the music dir where it used to live
+    pub async fn connect(&self) -> Result<DbConnection> {
+        DbConnection::relocate(&self.music_dir.join("database.sqlite"), &self.database_path)?;
+        if let Some(parent) = self.database_path.parent() {
+            std::fs::create_dir_all(parent).into_diagnostic()?;
+        }
+        Ok(DbConnection::with_backups(self.database_path.clone(), self.keep_backups).await?)
+    }
+}
+
 #[derive(Clone)]
 pub struct Config {
+    /// music dir of the active library
     pub music_dir: PathBuf,
     pub db_new: DbConnection,
+    /// name of the active library
+    pub library: String,
+    pub libraries: Vec<Library>,
     pub cookies: Option<PathBuf>,
     pub yt_playlist_sync: Option<Vec<String>>,
+    pub auto_clean_orphans: bool,
+    pub label_tags: bool,
 }
 
 impl Config {
     pub fn get_music_dir(&self) -> PathBuf {
         self.music_dir.clone()
     }
+
+    pub fn get_library(&self, name: &str) -> Result<&Library> {
+        self.libraries
+            .iter()
+            .find(|library| library.name == name)
+            .ok_or_else(|| miette!("No library named {name} in the config"))
+    }
 }
 
 impl Default for Config {
@@ -99,8 +244,67 @@ impl Default for Config {
         Self {
             music_dir: Default::default(),
             db_new: DbConnection::default(),
+            library: DEFAULT_LIBRARY.to_string(),
+            libraries: Default::default(),
             cookies: Default::default(),
             yt_playlist_sync: Default::default(),
+            auto_clean_orphans: Default::default(),
+            label_tags: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(config: &str) -> Result<Config> {
+        let dir = tempfile::tempdir().into_diagnostic()?;
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, config).into_diagnostic()?;
+        ReadConfig::read_config_without_database(Some(path), None)
+    }
+
+    #[test]
+    fn library_databases_are_namespaced() -> Result<()> {
+        let config = read(
+            r#"
+            version = 1
+            music_dir = "/music"
+            database = "/data/main.sqlite"
+
+            [libraries."../vtubers"]
+            music_dir = "/vtubers"
+
+            [libraries.covers]
+            music_dir = "/covers"
+            database_path = "/data/covers.sqlite"
+            "#,
+        )?;
+        let database = |name: &str| config.get_library(name).unwrap().database_path.clone();
+        assert_eq!(
+            database(DEFAULT_LIBRARY),
+            PathBuf::from("/data/main.sqlite")
+        );
+        assert!(database("../vtubers").ends_with("libraries/___vtubers.sqlite"));
+        assert_eq!(database("covers"), PathBuf::from("/data/covers.sqlite"));
+        Ok(())
+    }
+
+    #[test]
+    fn libraries_cant_share_a_database() {
+        let config = read(
+            r#"
+            version = 1
+            music_dir = "/music"
+
+            [libraries."a b"]
+            music_dir = "/a"
+
+            [libraries.a_b]
+            music_dir = "/b"
+            "#,
+        );
+        assert!(config.is_err());
+    }
+}
@@ -1,25 +1,312 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use strum::Display;
-use tracing::debug;
+use tracing::{debug, info, warn};
 
 use crate::{
     database::DbConnection,
     entities::{
-        album::AlbumModel, artist::ArtistModel, genre::GenreModel,
+        album::AlbumModel, artist::ArtistModel, genre::GenreModel, label::LabelModel,
+        song::SongModel, song_download::SongDownloadModel,
         youtube_playlist_id::YoutubePlaylistIdModel,
     },
     tags,
 };
 
-/// Source of the song file. Other than local is supported download source
-#[derive(Debug, Clone, Default, PartialEq, Eq, Display)]
-pub enum Source {
-    /// Download from Youtube / Youtube Music
-    Youtube,
-    /// Local file
-    #[default]
-    Local,
+pub use crate::entities::song::Source;
+
+/// Audio properties of a song file, as reported by lofty
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioProperties {
+    /// Duration in milliseconds
+    pub duration_ms: Option<i64>,
+    /// Overall bitrate in kbps
+    pub bitrate: Option<i32>,
+    /// Codec / container of the file, e.g. Opus
+    pub codec: Option<String>,
+    /// Sample rate in Hz
+    pub sample_rate: Option<i32>,
+    /// File size in bytes
+    pub file_size: Option<i64>,
+}
+
+impl AudioProperties {
+    /// Reads the audio properties stored in the database, `None` if none were recorded
+    pub fn from_model(model: &SongModel) -> Option<Self> {
+        let properties = Self {
+            duration_ms: model.duration_ms,
+            bitrate: model.bitrate,
+            codec: model.codec.clone(),
+            sample_rate: model.sample_rate,
+            file_size: model.file_size,
+        };
+        if properties == Self::default() {
+            None
+        } else {
+            Some(properties)
+        }
+    }
+
+    /// Duration formatted as `m:ss`
+    pub fn get_duration_string(&self) -> String {
+        if let Some(duration_ms) = self.duration_ms {
+            let seconds = duration_ms / 1000;
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        } else {
+            "Unknown".to_string()
+        }
+    }
+
+    /// One line summary for display, e.g. `3:45 | Opus | 160 kbps | 48000 Hz | 4.3 MiB`
+    pub fn summary(&self) -> String {
+        let mut parts = vec![self.get_duration_string()];
+        if let Some(codec) = self.codec.as_ref() {
+            parts.push(codec.clone());
+        }
+        if let Some(bitrate) = self.bitrate {
+            parts.push(format!("{} kbps", bitrate));
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            parts.push(format!("{} Hz", sample_rate));
+        }
+        if let Some(file_size) = self.file_size {
+            parts.push(format!("{:.1} MiB", file_size as f64 / (1024.0 * 1024.0)));
+        }
+        parts.join(" | ")
+    }
+}
+
+/// Size and modification time of a file. Its content hash is only computed again once
+/// either changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    /// File size in bytes
+    pub size: i64,
+    /// Unix timestamp in milliseconds
+    pub modified_ms: i64,
+}
+
+impl FileStamp {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let modified_ms = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |modified| modified.as_millis() as i64);
+        Ok(Self {
+            size: metadata.len() as i64,
+            modified_ms,
+        })
+    }
+}
+
+/// Rating, favourite flag and play count of a song, also stored in its tags
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayStats {
+    /// 0 to 5 stars, `None` if unrated
+    pub rating: Option<i32>,
+    pub favourite: bool,
+    pub play_count: i32,
+    /// Unix timestamp in seconds, only kept in the database
+    pub last_played: Option<i64>,
+}
+
+impl PlayStats {
+    pub fn from_model(model: &SongModel) -> Self {
+        Self {
+            rating: model.rating,
+            favourite: model.favourite,
+            play_count: model.play_count,
+            last_played: model.last_played,
+        }
+    }
+
+    /// Rating as stars, e.g. `★★★☆☆`
+    pub fn get_rating_string(&self) -> String {
+        if let Some(rating) = self.rating {
+            let rating = rating.clamp(0, 5) as usize;
+            format!("{}{}", "★".repeat(rating), "☆".repeat(5 - rating))
+        } else {
+            "Unrated".to_string()
+        }
+    }
+}
+
+/// Where and how a song was downloaded, to audit the file and download it again at the
+/// same quality
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Provenance {
+    /// Page the file was downloaded from
+    pub url: Option<String>,
+    /// Channel id, or the uploader id where the site has no channels
+    pub uploader_id: Option<String>,
+    /// As reported by yt-dlp, e.g. 20231029
+    pub upload_date: Option<String>,
+    /// yt-dlp format the file was downloaded in
+    pub format_id: Option<String>,
+    /// yt-dlp extractor that handled the url, e.g. Youtube
+    pub extractor: Option<String>,
+    pub ytdlp_version: Option<String>,
+    /// Unix timestamp in seconds
+    pub downloaded_at: i64,
+}
+
+impl Provenance {
+    pub fn from_model(model: SongDownloadModel) -> Self {
+        Self {
+            url: model.url,
+            uploader_id: model.uploader_id,
+            upload_date: model.upload_date,
+            format_id: model.format_id,
+            extractor: model.extractor,
+            ytdlp_version: model.ytdlp_version,
+            downloaded_at: model.downloaded_at,
+        }
+    }
+}
+
+/// One line of time synced lyrics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricLine {
+    /// Time from the start of the song in milliseconds
+    pub time_ms: u32,
+    pub text: String,
+}
+
+/// Lyrics of a song, with time synced lines if known
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lyrics {
+    pub plain: String,
+    pub synced: Option<Vec<LyricLine>>,
+}
+
+impl Lyrics {
+    /// Lyrics without timing information
+    pub fn from_plain(plain: String) -> Self {
+        Self {
+            plain,
+            synced: None,
+        }
+    }
+
+    /// Parses LRC lyrics, e.g. `[01:02.50]text`. Lines can have several timestamps,
+    /// ID tags like `[ar:Artist]` are skipped. Lines without a timestamp stay in the
+    /// plain lyrics after the line before them, text without any timestamp is kept
+    /// as it is.
+    pub fn from_lrc(lrc: &str) -> Self {
+        let mut lines = vec![];
+        // untimed lines, sorted after the timed line before them
+        let mut plain = vec![];
+        let mut previous_ms = None;
+        for line in lrc.lines() {
+            let mut rest = line.trim();
+            let mut times = vec![];
+            while let Some((time, after)) = rest
+                .strip_prefix('[')
+                .and_then(|tag| tag.split_once(']'))
+                .and_then(|(tag, after)| Some((parse_lrc_time(tag)?, after)))
+            {
+                times.push(time);
+                rest = after;
+            }
+            if times.is_empty() {
+                if !is_lrc_id_tag(rest) {
+                    plain.push(((previous_ms, true), rest.to_string()));
+                }
+                continue;
+            }
+            for time_ms in times {
+                lines.push(LyricLine {
+                    time_ms,
+                    text: rest.trim().to_string(),
+                });
+                previous_ms = Some(time_ms);
+            }
+        }
+
+        if lines.is_empty() {
+            return Self::from_plain(lrc.trim().to_string());
+        }
+        lines.sort_by_key(|line| line.time_ms);
+        plain.extend(
+            lines
+                .iter()
+                .map(|line| ((Some(line.time_ms), false), line.text.clone())),
+        );
+        // stable, so untimed lines after the same timed line keep their order
+        plain.sort_by_key(|(key, _)| *key);
+        Self {
+            plain: plain
+                .into_iter()
+                .map(|(_, text)| text)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .trim()
+                .to_string(),
+            synced: Some(lines),
+        }
+    }
+
+    /// Fills in the plain text or synced lines these lyrics are missing from `other`
+    pub fn fill_from(mut self, other: Lyrics) -> Self {
+        if self.plain.is_empty() {
+            self.plain = other.plain;
+        }
+        if self.synced.is_none() {
+            self.synced = other.synced;
+        }
+        self
+    }
+
+    /// The synced lines in LRC format, `None` if there are none
+    pub fn to_lrc(&self) -> Option<String> {
+        self.synced.as_ref().map(|lines| {
+            lines
+                .iter()
+                .map(|line| {
+                    let centis = line.time_ms / 10;
+                    format!(
+                        "[{:02}:{:02}.{:02}]{}",
+                        centis / 6000,
+                        centis / 100 % 60,
+                        centis % 100,
+                        line.text
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+}
+
+/// An LRC ID tag like `[ar:Artist]` or `[offset:+100]`
+fn is_lrc_id_tag(line: &str) -> bool {
+    line.strip_prefix('[')
+        .and_then(|tag| tag.strip_suffix(']'))
+        .and_then(|tag| tag.split_once(':'))
+        .is_some_and(|(key, _)| {
+            matches!(
+                key,
+                "ar" | "al" | "ti" | "au" | "by" | "length" | "offset" | "re" | "tool" | "ve" | "#"
+            )
+        })
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` to milliseconds
+fn parse_lrc_time(time: &str) -> Option<u32> {
+    let (minutes, seconds) = time.split_once(':')?;
+    let minutes = minutes.parse::<u32>().ok()?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let seconds = seconds.parse::<u32>().ok()?;
+    let fraction_ms = match fraction.len() {
+        0 => 0,
+        1 => fraction.parse::<u32>().ok()? * 100,
+        2 => fraction.parse::<u32>().ok()? * 10,
+        _ => fraction.get(..3)?.parse::<u32>().ok()?,
+    };
+    Some((minutes * 60 + seconds) * 1000 + fraction_ms)
 }
 
 /// Song data. Use setters to set data
@@ -38,6 +325,33 @@ pub struct Song {
     pub albums: Option<Vec<AlbumModel>>,
     /// List of genres for the song
     pub genres: Option<Vec<GenreModel>>,
+    /// User labels like moods or occasions
+    pub labels: Option<Vec<LabelModel>>,
+    /// Position of the song in its album
+    pub track_number: Option<i32>,
+    /// Number of tracks in the album
+    pub track_total: Option<i32>,
+    /// Disc of the album the song is on
+    pub disc_number: Option<i32>,
+    /// Release year
+    pub year: Option<i32>,
+    /// Full release date if known, e.g. 2023-10-17
+    pub release_date: Option<String>,
+    /// Duration, bitrate, codec etc. of the file
+    pub audio_properties: Option<AudioProperties>,
+    /// Hash of the audio stream, ignoring tags
+    pub content_hash: Option<String>,
+    /// Size and modification time of the file when `content_hash` was computed
+    pub content_hash_stamp: Option<FileStamp>,
+    /// Rating, favourite and play count, `None` if the file has none
+    pub play_stats: Option<PlayStats>,
+    /// Embedded lyrics, or the ones from a `.lrc` file next to the song
+    pub lyrics: Option<Lyrics>,
+    /// When the song was inserted into the database, as a unix timestamp in seconds
+    pub added_at: Option<i64>,
+    /// How the file was downloaded, `None` for local files and songs downloaded before
+    /// it was recorded
+    pub provenance: Option<Provenance>,
 
     /// youtube id of song from youtube
     pub youtube_id: Option<String>,
@@ -49,6 +363,10 @@ pub struct Song {
     pub thumbnail: Option<Vec<u8>>,
     /// Source of the file
     pub source: Source,
+    /// Page the file was downloaded from
+    pub source_url: Option<String>,
+    /// Id of the song on the source site
+    pub source_id: Option<String>,
     pub update_required: bool,
     pub in_database: bool,
 }
@@ -96,6 +414,91 @@ impl Song {
         self.clone()
     }
 
+    pub fn set_labels(&mut self, labels: Vec<LabelModel>) -> Self {
+        if !labels.is_empty() {
+            self.labels = Some(labels);
+        }
+        self.clone()
+    }
+
+    pub fn set_track_number(&mut self, track_number: i32) -> Self {
+        self.track_number = Some(track_number);
+        self.clone()
+    }
+
+    pub fn set_track_total(&mut self, track_total: i32) -> Self {
+        self.track_total = Some(track_total);
+        self.clone()
+    }
+
+    pub fn set_disc_number(&mut self, disc_number: i32) -> Self {
+        self.disc_number = Some(disc_number);
+        self.clone()
+    }
+
+    pub fn set_year(&mut self, year: i32) -> Self {
+        self.year = Some(year);
+        self.clone()
+    }
+
+    /// Sets the release date. The year is filled in from the date if it is not known yet
+    pub fn set_release_date(&mut self, release_date: String) -> Self {
+        if !release_date.is_empty() {
+            if self.year.is_none() {
+                self.year = release_date
+                    .get(..4)
+                    .and_then(|year| year.parse::<i32>().ok());
+            }
+            self.release_date = Some(release_date);
+        }
+        self.clone()
+    }
+
+    /// Copies the track information stored in the database
+    pub fn set_track_info(&mut self, model: &SongModel) -> Self {
+        self.track_number = model.track_number;
+        self.track_total = model.track_total;
+        self.disc_number = model.disc_number;
+        self.year = model.year;
+        self.release_date = model.release_date.clone();
+        self.clone()
+    }
+
+    pub fn set_audio_properties(&mut self, audio_properties: Option<AudioProperties>) -> Self {
+        self.audio_properties = audio_properties;
+        self.clone()
+    }
+
+    pub fn set_play_stats(&mut self, play_stats: Option<PlayStats>) -> Self {
+        self.play_stats = play_stats;
+        self.clone()
+    }
+
+    pub fn set_lyrics(&mut self, lyrics: Option<Lyrics>) -> Self {
+        self.lyrics = lyrics;
+        self.clone()
+    }
+
+    pub fn set_provenance(&mut self, provenance: Option<Provenance>) -> Self {
+        self.provenance = provenance;
+        self.clone()
+    }
+
+    pub fn set_added_at(&mut self, added_at: Option<i64>) -> Self {
+        self.added_at = added_at;
+        self.clone()
+    }
+
+    pub fn set_content_hash(&mut self, content_hash: String) -> Self {
+        self.content_hash = Some(content_hash);
+        self.clone()
+    }
+
+    pub fn set_content_hash_stamp(&mut self, stamp: Option<FileStamp>) -> Self {
+        self.content_hash_stamp = stamp;
+        self.clone()
+    }
+
     pub fn set_youtube_id(&mut self, youtube_id: String) -> Self {
         if !youtube_id.is_empty() {
             self.youtube_id = Some(youtube_id);
@@ -120,11 +523,45 @@ impl Song {
         self.clone()
     }
 
+    /// Sets the local thumbnail, written to the file as front cover
+    pub fn set_thumbnail(&mut self, thumbnail: Vec<u8>) -> Self {
+        if !thumbnail.is_empty() {
+            self.thumbnail = Some(thumbnail);
+        }
+        self.clone()
+    }
+
     pub fn set_source(&mut self, source: Source) -> Self {
         self.source = source;
         self.clone()
     }
 
+    pub fn set_source_url(&mut self, source_url: Option<String>) -> Self {
+        self.source_url = source_url;
+        self.clone()
+    }
+
+    pub fn set_source_id(&mut self, source_id: Option<String>) -> Self {
+        self.source_id = source_id;
+        self.clone()
+    }
+
+    /// Id on the source site, youtube songs fall back to their youtube id
+    pub fn get_source_id(&self) -> Option<String> {
+        match self.source {
+            Source::Youtube => self.source_id.clone().or_else(|| self.youtube_id.clone()),
+            _ => self.source_id.clone(),
+        }
+    }
+
+    /// Page on the source site, built from the id where the source allows it
+    pub fn get_source_url(&self) -> Option<String> {
+        self.source_url.clone().or_else(|| {
+            self.get_source_id()
+                .and_then(|source_id| self.source.url_for(&source_id))
+        })
+    }
+
     /// Returns database id if in database
     pub fn identify(&self) -> String {
         if let Some(path) = self.path.as_ref() {
@@ -191,6 +628,10 @@ impl Song {
         }
     }
 
+    pub fn get_youtube_playlists(&self) -> Vec<YoutubePlaylistIdModel> {
+        self.youtube_playlists.clone().unwrap_or_default()
+    }
+
     pub fn get_genres_vec(&self) -> Vec<String> {
         if let Some(genres) = self.genres.as_ref() {
             genres.iter().map(|a| a.genre.clone()).collect()
@@ -199,6 +640,15 @@ impl Song {
         }
     }
 
+    /// Label names, empty when the song has none
+    pub fn get_labels_vec(&self) -> Vec<String> {
+        self.labels
+            .iter()
+            .flatten()
+            .map(|label| label.name.clone())
+            .collect()
+    }
+
     pub fn is_database_only(&self) -> bool {
         if let Some(path) = self.path.as_ref() {
             if path.exists() {
@@ -251,13 +701,71 @@ pub async fn load_songs(music_dir: PathBuf, db: Arc<DbConnection>) -> Vec<Song>
         let mut svec = vec![];
         let mut id_present = vec![];
         for file in files {
-            let mut song = tags::read_tags_to_gui_song(file)
+            let mut song = tags::read_tags_to_gui_song(file.clone())
                 .await
                 .expect("can read tags");
+            // a sidecar only fills in what the embedded lyrics are missing
+            if song
+                .lyrics
+                .as_ref()
+                .is_none_or(|l| l.plain.is_empty() || l.synced.is_none())
+            {
+                match tags::read_lrc_sidecar(&file).await {
+                    Ok(Some(sidecar)) => {
+                        let lyrics = match song.lyrics.take() {
+                            Some(embedded) => embedded.fill_from(sidecar),
+                            None => sidecar,
+                        };
+                        song.set_lyrics(Some(lyrics));
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("unable to read lyrics of {}: {}", file.display(), e),
+                }
+            }
             song.music_dir = music_dir.clone();
+            // the file is only hashed again once it changed
+            let stamp = FileStamp::read(&file).ok();
+            let known_hash = match stamp {
+                Some(stamp) => db
+                    .get_unchanged_content_hash(&song.get_database_path(), stamp)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!("unable to look up the hash of {}: {}", file.display(), e);
+                        None
+                    }),
+                None => None,
+            };
+            let content_hash = match known_hash {
+                Some(content_hash) => Ok(content_hash),
+                None => tags::read_content_hash(file.clone()).await,
+            };
+            song.set_content_hash_stamp(stamp);
+            match content_hash {
+                Ok(content_hash) => {
+                    song.set_content_hash(content_hash);
+                }
+                Err(e) => warn!("unable to hash {}: {}", song.get_database_path(), e),
+            }
 
             // check if song is in database
-            let in_database = db.check_song_in_database(&song).await;
+            let mut in_database = db.check_song_in_database(&song).await;
+            if !in_database {
+                // the file may have been moved or renamed outside of muzik
+                match db.find_moved_song(&song).await {
+                    Ok(Some(moved)) if !id_present.contains(&moved.id) => {
+                        info!(
+                            "relinking song {} from {} to {}",
+                            moved.id,
+                            moved.path.unwrap_or_default(),
+                            song.get_database_path()
+                        );
+                        song.set_id(moved.id);
+                        in_database = true;
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("unable to look up moved songs: {}", e),
+                }
+            }
             debug!(
                 "{} database check: {}",
                 song.title.as_ref().unwrap_or(&String::new()),
@@ -265,7 +773,16 @@ pub async fn load_songs(music_dir: PathBuf, db: Arc<DbConnection>) -> Vec<Song>
             );
             if in_database {
                 // TODO: verify correctness against all values in Song model
-                id_present.push(song.id.expect("has id if true in database"));
+                let id = song.id.expect("has id if true in database");
+                if let Err(e) = db.update_file_info(&song).await {
+                    warn!("unable to store file info of song {}: {}", id, e);
+                }
+                if let Some(lyrics) = song.lyrics.as_ref() {
+                    if let Err(e) = db.set_lyrics_if_missing(id, lyrics).await {
+                        warn!("unable to store lyrics of song {}: {}", id, e);
+                    }
+                }
+                id_present.push(id);
             }
             song.in_database = in_database;
             song.get_database_path();
@@ -1,6 +1,16 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
-use sea_orm_migration::SchemaManager;
+use sea_orm_migration::{IntoSchemaManagerConnection, SchemaManager, SchemaManagerConnection};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tracing::{debug, info, trace, warn};
 
 #[derive(Clone, Debug)]
@@ -13,12 +23,30 @@ pub struct AppSong {
     pub title: Option<String>,
     pub yt_id: Option<String>,
     pub tb_url: Option<String>,
+    pub source: Source,
+    pub source_url: Option<String>,
+    pub source_id: Option<String>,
 
     pub album: Option<Vec<album::Model>>,
     pub artist: Option<Vec<artist::Model>>,
     pub genre: Option<Vec<genre::Model>>,
+    /// User labels, only loaded from the database
+    pub label: Option<Vec<label::Model>>,
     pub yt_playlist: Option<Vec<youtube_playlist_id::Model>>,
     pub npath: Option<PathBuf>,
+
+    pub track_number: Option<i32>,
+    pub track_total: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub year: Option<i32>,
+    pub release_date: Option<String>,
+
+    pub audio_properties: Option<AudioProperties>,
+    pub play_stats: Option<PlayStats>,
+    /// How the file was downloaded, stored once the download finished
+    pub provenance: Option<Provenance>,
+    /// Lyrics from the database, written to the file with the other tags
+    pub lyrics: Option<SongLyrics>,
 }
 
 impl AppSong {
@@ -62,6 +90,7 @@ impl AppSong {
             .map(|s| album::Model {
                 id: 0,
                 name: s.trim().to_string(),
+                ..Default::default()
             })
             .collect::<Vec<_>>();
 
@@ -94,17 +123,88 @@ impl AppSong {
         self
     }
 
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn with_source_url(mut self, source_url: Option<String>) -> Self {
+        self.source_url = source_url;
+        self
+    }
+
+    pub fn with_source_id(mut self, source_id: Option<String>) -> Self {
+        self.source_id = source_id;
+        self
+    }
+
+    pub fn with_provenance(mut self, provenance: Option<Provenance>) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Sets the youtube playlists from a `;` separated list, `None` means no playlist
     pub fn with_yt_playlist_id(mut self, yt_playlist_id: Option<String>) -> Self {
-        let yt_playlist_id = yt_playlist_id.unwrap_or("Unknown".to_string());
         let yt_playlist_id_vec = yt_playlist_id
+            .unwrap_or_default()
             .split(';')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
             .map(|s| youtube_playlist_id::Model {
                 id: 0,
-                youtube_playlist_id: s.trim().to_string(),
+                youtube_playlist_id: s.to_string(),
             })
             .collect::<Vec<_>>();
 
-        self.yt_playlist = Some(yt_playlist_id_vec);
+        self.yt_playlist = if yt_playlist_id_vec.is_empty() {
+            None
+        } else {
+            Some(yt_playlist_id_vec)
+        };
+        self
+    }
+
+    pub fn with_track_number(mut self, track_number: Option<i32>) -> Self {
+        self.track_number = track_number;
+        self
+    }
+
+    pub fn with_track_total(mut self, track_total: Option<i32>) -> Self {
+        self.track_total = track_total;
+        self
+    }
+
+    pub fn with_disc_number(mut self, disc_number: Option<i32>) -> Self {
+        self.disc_number = disc_number;
+        self
+    }
+
+    pub fn with_year(mut self, year: Option<i32>) -> Self {
+        self.year = year;
+        self
+    }
+
+    pub fn with_release_date(mut self, release_date: Option<String>) -> Self {
+        self.release_date = release_date;
+        self
+    }
+
+    /// Copies the track information stored in the database
+    pub fn with_track_info(self, model: &SongModel) -> Self {
+        self.with_track_number(model.track_number)
+            .with_track_total(model.track_total)
+            .with_disc_number(model.disc_number)
+            .with_year(model.year)
+            .with_release_date(model.release_date.clone())
+    }
+
+    pub fn with_audio_properties(mut self, audio_properties: Option<AudioProperties>) -> Self {
+        self.audio_properties = audio_properties;
+        self
+    }
+
+    pub fn with_play_stats(mut self, play_stats: Option<PlayStats>) -> Self {
+        self.play_stats = play_stats;
         self
     }
 
@@ -168,6 +268,22 @@ impl AppSong {
         self.yt_id.as_ref().cloned()
     }
 
+    /// Id on the source site, youtube songs fall back to their youtube id
+    pub fn get_source_id(&self) -> Option<String> {
+        match self.source {
+            Source::Youtube => self.source_id.clone().or_else(|| self.get_yt_id()),
+            _ => self.source_id.clone(),
+        }
+    }
+
+    /// Page on the source site, built from the id where the source allows it
+    pub fn get_source_url(&self) -> Option<String> {
+        self.source_url.clone().or_else(|| {
+            self.get_source_id()
+                .and_then(|source_id| self.source.url_for(&source_id))
+        })
+    }
+
     pub fn get_title_string(&self) -> String {
         if let Some(title) = &self.title {
             title.clone()
@@ -218,6 +334,16 @@ impl AppSong {
         }
     }
 
+    /// Label names joined like `get_genre_string`, empty when the song has none
+    pub fn get_labels_string(&self) -> String {
+        self.label
+            .iter()
+            .flatten()
+            .map(|label| label.name.clone())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
     #[allow(dead_code)]
     pub fn get_youtube_playlist_id_string(&self) -> String {
         if let Some(youtube_playlist_id) = &self.yt_playlist {
@@ -309,10 +435,467 @@ impl Default for AppSong {
             album: None,
             artist: None,
             genre: None,
+            label: None,
             yt_id: None,
             tb_url: None,
+            source: Source::default(),
+            source_url: None,
+            source_id: None,
             npath: None,
             yt_playlist: None,
+            track_number: None,
+            track_total: None,
+            disc_number: None,
+            year: None,
+            release_date: None,
+            audio_properties: None,
+            play_stats: None,
+            provenance: None,
+            lyrics: None,
+        }
+    }
+}
+
+/// Only set when inserting, the `From` impls below leave the stored source untouched so a
+/// file scan can't reset it
+fn set_source(
+    model: &mut song::ActiveModel,
+    source: Source,
+    source_id: Option<String>,
+    source_url: Option<String>,
+) {
+    model.source = ActiveValue::Set(source);
+    model.source_id = ActiveValue::Set(source_id);
+    model.source_url = ActiveValue::Set(source_url);
+}
+
+/// Source columns of a song inserted with only a youtube id
+fn set_youtube_source(model: &mut song::ActiveModel, youtube_id: Option<&String>) {
+    if let Some(youtube_id) = youtube_id {
+        set_source(
+            model,
+            Source::Youtube,
+            Some(youtube_id.clone()),
+            Source::Youtube.url_for(youtube_id),
+        );
+    }
+}
+
+/// Songs without audio properties leave the stored columns untouched, see the `From` impls below
+fn set_audio_properties(model: &mut song::ActiveModel, properties: &AudioProperties) {
+    model.duration_ms = ActiveValue::Set(properties.duration_ms);
+    model.bitrate = ActiveValue::Set(properties.bitrate);
+    model.codec = ActiveValue::Set(properties.codec.clone());
+    model.sample_rate = ActiveValue::Set(properties.sample_rate);
+    model.file_size = ActiveValue::Set(properties.file_size);
+}
+
+/// Size and mtime of the file the content hash was computed from
+fn set_hashed_stamp(model: &mut song::ActiveModel, stamp: Option<FileStamp>) {
+    model.hashed_size = ActiveValue::Set(stamp.map(|stamp| stamp.size));
+    model.hashed_modified = ActiveValue::Set(stamp.map(|stamp| stamp.modified_ms));
+}
+
+/// Only used on insert, afterwards the play stats are changed through their own setters
+/// so an edit of stale song data can't reset them
+fn set_play_stats(model: &mut song::ActiveModel, play_stats: &PlayStats) {
+    model.rating = ActiveValue::Set(play_stats.rating);
+    model.favourite = ActiveValue::Set(play_stats.favourite);
+    model.play_count = ActiveValue::Set(play_stats.play_count);
+    model.last_played = ActiveValue::Set(play_stats.last_played);
+}
+
+/// The path is left untouched as the TUI does not track it yet
+impl From<&AppSong> for song::ActiveModel {
+    fn from(song: &AppSong) -> Self {
+        let mut model = Self {
+            id: song.id.map_or(ActiveValue::NotSet, ActiveValue::Set),
+            title: ActiveValue::Set(song.get_title_string()),
+            youtube_id: ActiveValue::Set(song.yt_id.clone()),
+            thumbnail_url: ActiveValue::Set(song.tb_url.clone()),
+            track_number: ActiveValue::Set(song.track_number),
+            track_total: ActiveValue::Set(song.track_total),
+            disc_number: ActiveValue::Set(song.disc_number),
+            year: ActiveValue::Set(song.year),
+            release_date: ActiveValue::Set(song.release_date.clone()),
+            ..Default::default()
+        };
+        if let Some(properties) = song.audio_properties.as_ref() {
+            set_audio_properties(&mut model, properties);
+        }
+        model
+    }
+}
+
+impl From<&GSong> for song::ActiveModel {
+    fn from(song: &GSong) -> Self {
+        let mut model = Self {
+            id: song.id.map_or(ActiveValue::NotSet, ActiveValue::Set),
+            title: ActiveValue::Set(song.get_title_string()),
+            youtube_id: ActiveValue::Set(song.youtube_id.clone()),
+            thumbnail_url: ActiveValue::Set(song.thumbnail_url.clone()),
+            // should only be the filename to ensure crossplatform
+            path: ActiveValue::Set(Some(song.get_database_path())),
+            track_number: ActiveValue::Set(song.track_number),
+            track_total: ActiveValue::Set(song.track_total),
+            disc_number: ActiveValue::Set(song.disc_number),
+            year: ActiveValue::Set(song.year),
+            release_date: ActiveValue::Set(song.release_date.clone()),
+            content_hash: song
+                .content_hash
+                .clone()
+                .map_or(ActiveValue::NotSet, |hash| ActiveValue::Set(Some(hash))),
+            ..Default::default()
+        };
+        if let Some(properties) = song.audio_properties.as_ref() {
+            set_audio_properties(&mut model, properties);
+        }
+        if song.content_hash.is_some() {
+            set_hashed_stamp(&mut model, song.content_hash_stamp);
+        }
+        model
+    }
+}
+
+/// Values of the tracked fields of a song, taken before an edit to find out what it changed
+#[derive(Clone, Debug)]
+pub struct SongSnapshot {
+    song_id: i32,
+    values: Vec<(SongField, Option<String>)>,
+}
+
+/// An album as the history records it, the album artist tells same-named albums apart
+#[derive(Serialize, Deserialize)]
+struct RecordedAlbum {
+    name: String,
+    album_artist: Option<String>,
+}
+
+/// Records the values of a multi-value field as a JSON array, `None` if there are none
+fn record_values<T: Serialize>(values: &[T]) -> Result<Option<String>, DatabaseError> {
+    if values.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(serde_json::to_string(values)?))
+    }
+}
+
+fn recorded_values<T: DeserializeOwned>(value: Option<String>) -> Result<Vec<T>, DatabaseError> {
+    match value {
+        Some(value) => Ok(serde_json::from_str(&value)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// turns user input into an FTS5 query where every word is a quoted prefix
+/// term, so characters like `-` or `"` in the input can't break the syntax
+fn fts_match_query(query: &str) -> Option<String> {
+    let terms = query
+        .split_whitespace()
+        .map(|term| term.replace('"', ""))
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{term}\"*"))
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Folds the spellings youtube channels use for one artist, like "Artist - Topic",
+/// "ArtistVEVO" and "artist", into the same key
+fn artist_key(name: &str) -> String {
+    let name = name.trim();
+    let name = name.strip_suffix("- Topic").unwrap_or(name);
+    let name = name.trim_end().strip_suffix("VEVO").unwrap_or(name);
+    let key = name
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect::<String>();
+    if key.is_empty() {
+        name.trim().to_lowercase()
+    } else {
+        key
+    }
+}
+
+pub(crate) fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Renames `from` to `to`, copying when they are on different file systems
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Copies every `(from, to)` pair, refusing to overwrite, and returns the copies. If one
+/// fails the copies made so far are removed again
+fn copy_files(files: &[(PathBuf, PathBuf)]) -> std::io::Result<Vec<PathBuf>> {
+    let mut copied = vec![];
+    for (from, to) in files {
+        let copy = || {
+            if to.exists() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", to.display()),
+                ));
+            }
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(from, to)
+        };
+        if let Err(e) = copy() {
+            remove_files(&copied);
+            return Err(e);
+        }
+        copied.push(to.clone());
+    }
+    Ok(copied)
+}
+
+/// Removes files made by a step that is rolled back, a failure is only logged
+fn remove_files(files: &[PathBuf]) {
+    for file in files {
+        if let Err(e) = std::fs::remove_file(file) {
+            warn!("unable to remove {}: {}", file.display(), e);
+        }
+    }
+}
+
+/// Files sqlite keeps next to the database at `path` while it is in use
+fn journal_files(path: &Path) -> [PathBuf; 3] {
+    ["-wal", "-shm", "-journal"].map(|suffix| {
+        let mut side_file = path.as_os_str().to_owned();
+        side_file.push(suffix);
+        PathBuf::from(side_file)
+    })
+}
+
+/// Backups of the database at `path` are kept in a `backups` dir next to it
+fn backup_dir(path: &Path) -> PathBuf {
+    path.parent()
+        .map(|parent| parent.join("backups"))
+        .unwrap_or_else(|| PathBuf::from("backups"))
+}
+
+/// File name of the database without extension, backups are named after it
+fn database_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "database".to_owned())
+}
+
+/// Backups kept from before migrations when none is configured
+pub const DEFAULT_KEEP_BACKUPS: usize = 5;
+
+/// Results the frontends ask [`DbConnection::search`] for
+pub const DEFAULT_SEARCH_LIMIT: u64 = 50;
+
+/// How long a write waits for another process writing to the same database, e.g. the GUI
+/// and TUI open on one library, before failing with "database is locked"
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// How long a statement waits for the connection while a transaction of this process
+/// holds it, imports of large libraries take a while
+const ACQUIRE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// Ids per `IN` lookup, well below the number of variables sqlite allows in a statement
+const SONG_BATCH_SIZE: usize = 500;
+
+/// Tells apart the pools of `DbConnection`s, clones and their transactions share an id
+static NEXT_CONNECTION_ID: AtomicUsize = AtomicUsize::new(1);
+
+fn next_connection_id() -> usize {
+    NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+tokio::task_local! {
+    /// Connections the current task runs a `DbConnection::transaction` closure of
+    static OPEN_TRANSACTIONS: Vec<usize>;
+}
+
+/// Artists, albums, genres, labels, youtube playlists, lyrics and download provenance of
+/// a batch of songs, by song id
+#[derive(Default)]
+struct SongRelations {
+    artists: HashMap<i32, Vec<ArtistModel>>,
+    albums: HashMap<i32, Vec<AlbumModel>>,
+    genres: HashMap<i32, Vec<GenreModel>>,
+    labels: HashMap<i32, Vec<LabelModel>>,
+    youtube_playlists: HashMap<i32, Vec<YoutubePlaylistIdModel>>,
+    lyrics: HashMap<i32, SongLyrics>,
+    provenance: HashMap<i32, Provenance>,
+}
+
+fn lyrics_from_model(lyrics: lyrics::Model) -> SongLyrics {
+    SongLyrics {
+        synced: lyrics
+            .synced
+            .and_then(|lrc| SongLyrics::from_lrc(&lrc).synced),
+        plain: lyrics.plain,
+    }
+}
+
+/// Fills a `Song` with the model and its relations, taken out of `relations`
+fn build_gui_song(s: SongModel, music_dir: &Path, relations: &mut SongRelations) -> GSong {
+    let song_id = s.id;
+    let mut new_song = GSong::new()
+        .set_track_info(&s)
+        .set_audio_properties(AudioProperties::from_model(&s))
+        .set_play_stats(Some(PlayStats::from_model(&s)))
+        .set_added_at(s.added_at)
+        .set_source(s.source)
+        .set_source_url(s.source_url)
+        .set_source_id(s.source_id)
+        .set_id(song_id)
+        .set_youtube_id(s.youtube_id.unwrap_or_default())
+        .set_thumbnail_url(s.thumbnail_url.unwrap_or_default())
+        .set_title(s.title);
+    new_song.music_dir = music_dir.to_path_buf();
+    // songs inserted from the TUI have no path yet
+    if let Some(path) = s.path.filter(|path| !path.is_empty()) {
+        new_song.set_path(music_dir.join(path));
+    }
+    new_song.set_artists(relations.artists.remove(&song_id).unwrap_or_default());
+    new_song.set_albums(relations.albums.remove(&song_id).unwrap_or_default());
+    new_song.set_genres(relations.genres.remove(&song_id).unwrap_or_default());
+    new_song.set_labels(relations.labels.remove(&song_id).unwrap_or_default());
+    new_song.set_youtube_playlists(
+        relations
+            .youtube_playlists
+            .remove(&song_id)
+            .unwrap_or_default(),
+    );
+    new_song.set_lyrics(relations.lyrics.remove(&song_id));
+    new_song.set_provenance(relations.provenance.remove(&song_id));
+    new_song
+}
+
+/// Fills an `AppSong` with the model and its relations, taken out of `relations`
+fn build_app_song(s: SongModel, music_dir: &Path, relations: &mut SongRelations) -> AppSong {
+    let mut new_song = AppSong::new()
+        .with_music_dir(Some(music_dir.to_path_buf()))
+        .with_id(Some(s.id))
+        .with_track_info(&s)
+        .with_audio_properties(AudioProperties::from_model(&s))
+        .with_play_stats(Some(PlayStats::from_model(&s)))
+        .with_yt_id(s.youtube_id)
+        .with_tb_url(s.thumbnail_url)
+        .with_source(s.source)
+        .with_source_url(s.source_url)
+        .with_source_id(s.source_id)
+        .with_title(Some(s.title));
+    new_song.artist = relations.artists.remove(&s.id);
+    new_song.album = relations.albums.remove(&s.id);
+    new_song.genre = relations.genres.remove(&s.id);
+    new_song.label = relations.labels.remove(&s.id);
+    new_song.yt_playlist = relations.youtube_playlists.remove(&s.id);
+    new_song.lyrics = relations.lyrics.remove(&s.id);
+    new_song.provenance = relations.provenance.remove(&s.id);
+    new_song.compute_filename();
+    new_song
+}
+
+/// An album and its songs, in track order
+#[derive(Debug, Clone)]
+pub struct AlbumWithTracks {
+    pub album: AlbumModel,
+    pub tracks: Vec<GSong>,
+}
+
+/// Where [`DbConnection::export_playlist_m3u8`] wrote a playlist
+#[derive(Debug, Clone)]
+pub struct PlaylistExport {
+    pub path: PathBuf,
+    /// Songs left out as they have no file
+    pub skipped: Vec<SongModel>,
+}
+
+/// Rows not referenced by any song, see [`DbConnection::clean_orphans`]
+#[derive(Debug, Clone, Default)]
+pub struct OrphanReport {
+    pub artists: Vec<ArtistModel>,
+    pub albums: Vec<AlbumModel>,
+    pub genres: Vec<GenreModel>,
+    pub youtube_playlist_ids: Vec<YoutubePlaylistIdModel>,
+}
+
+impl OrphanReport {
+    pub fn is_empty(&self) -> bool {
+        self.artists.is_empty()
+            && self.albums.is_empty()
+            && self.genres.is_empty()
+            && self.youtube_playlist_ids.is_empty()
+    }
+
+    /// One line per kind of row, e.g. `artists: Sui-chan, Comet-chan`
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return "no orphaned entries".to_string();
+        }
+        let mut lines = vec![];
+        for (kind, names) in [
+            (
+                "artists",
+                self.artists
+                    .iter()
+                    .map(|a| a.name.clone())
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "albums",
+                self.albums.iter().map(|a| a.name.clone()).collect(),
+            ),
+            (
+                "genres",
+                self.genres.iter().map(|g| g.genre.clone()).collect(),
+            ),
+            (
+                "youtube playlists",
+                self.youtube_playlist_ids
+                    .iter()
+                    .map(|y| y.youtube_playlist_id.clone())
+                    .collect(),
+            ),
+        ] {
+            if !names.is_empty() {
+                lines.push(format!("{kind}: {}", names.join(", ")));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// A song linked to another one, see [`DbConnection::get_song_relationships`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelatedSong {
+    pub song_id: i32,
+    pub title: String,
+    pub kind: RelationshipKind,
+    /// Whether this song is the one the other is a `kind` of, e.g. the original of a remix
+    pub original: bool,
+}
+
+impl RelatedSong {
+    /// e.g. `remix of Stellar Stellar` for the original, `remix: Stellar Stellar (Remix)`
+    /// for the remix
+    pub fn describe(&self) -> String {
+        if self.original {
+            format!("{} of {}", self.kind, self.title)
+        } else {
+            format!("{}: {}", self.kind, self.title)
         }
     }
 }
@@ -322,17 +905,116 @@ impl Default for AppSong {
 #[derive(Clone)]
 pub struct DbConnection {
     path: Option<PathBuf>,
-    db: Option<DatabaseConnection>,
+    db: Option<DbHandle>,
+    /// Held while a database file is open, see [`DatabaseLock`]
+    lock: Option<DatabaseLock>,
+    /// Removes orphaned rows after songs are deleted or relinked, see
+    /// [`DbConnection::with_auto_clean_orphans`]
+    auto_clean_orphans: bool,
+    /// Pool the connection uses, see [`DbConnection::ref_db`]
+    connection_id: usize,
+}
+
+/// What a `DbConnection` runs its statements on: the connection pool, or a transaction
+/// started by [`DbConnection::transaction`]
+#[derive(Clone)]
+pub enum DbHandle {
+    Connection(DatabaseConnection),
+    Transaction(Arc<DatabaseTransaction>),
+}
+
+#[async_trait::async_trait]
+impl ConnectionTrait for DbHandle {
+    fn get_database_backend(&self) -> DbBackend {
+        match self {
+            Self::Connection(db) => db.get_database_backend(),
+            Self::Transaction(txn) => txn.get_database_backend(),
+        }
+    }
+
+    async fn execute(&self, stmt: Statement) -> Result<ExecResult, DbErr> {
+        match self {
+            Self::Connection(db) => db.execute(stmt).await,
+            Self::Transaction(txn) => txn.execute(stmt).await,
+        }
+    }
+
+    async fn execute_unprepared(&self, sql: &str) -> Result<ExecResult, DbErr> {
+        match self {
+            Self::Connection(db) => db.execute_unprepared(sql).await,
+            Self::Transaction(txn) => txn.execute_unprepared(sql).await,
+        }
+    }
+
+    async fn query_one(&self, stmt: Statement) -> Result<Option<QueryResult>, DbErr> {
+        match self {
+            Self::Connection(db) => db.query_one(stmt).await,
+            Self::Transaction(txn) => txn.query_one(stmt).await,
+        }
+    }
+
+    async fn query_all(&self, stmt: Statement) -> Result<Vec<QueryResult>, DbErr> {
+        match self {
+            Self::Connection(db) => db.query_all(stmt).await,
+            Self::Transaction(txn) => txn.query_all(stmt).await,
+        }
+    }
+
+    fn support_returning(&self) -> bool {
+        match self {
+            Self::Connection(db) => db.support_returning(),
+            Self::Transaction(txn) => txn.support_returning(),
+        }
+    }
+}
+
+impl<'c> IntoSchemaManagerConnection<'c> for &'c DbHandle {
+    fn into_schema_manager_connection(self) -> SchemaManagerConnection<'c> {
+        match self {
+            DbHandle::Connection(db) => db.into_schema_manager_connection(),
+            DbHandle::Transaction(txn) => txn.as_ref().into_schema_manager_connection(),
+        }
+    }
 }
 
 use crate::{
-    data::{Song as GSong, Source},
+    data::{
+        AudioProperties, FileStamp, Lyrics as SongLyrics, PlayStats, Provenance, Song as GSong,
+        Source,
+    },
     entities::{
-        album::AlbumModel, artist::ArtistModel, genre::GenreModel, prelude::*, song::SongModel, *,
+        album::AlbumModel,
+        artist::ArtistModel,
+        change_set::{ChangeOrigin, ChangeSetModel},
+        field_change::{FieldChangeModel, SongField},
+        genre::GenreModel,
+        label::LabelModel,
+        playlist::PlaylistModel,
+        prelude::*,
+        song::SongModel,
+        song_relationship::{RelationshipKind, SongRelationshipModel},
+        song_youtube_playlist_id_junction::SongYoutubePlaylistIdJunctionModel,
+        youtube_playlist_id::YoutubePlaylistIdModel,
+        *,
     },
+    export::{
+        ExportedAlias, ExportedCover, ExportedPlaylist, ExportedRelationship, ExportedSong,
+        ImportReport, LibraryExport, EXPORT_VERSION,
+    },
+    lock::{DatabaseLock, MusicDirLock},
+    query::{SongQuery, SongSort},
+    tags,
+};
+use sea_orm::{
+    prelude::*, ActiveValue, ConnectOptions, DatabaseBackend, DatabaseTransaction, DbBackend,
+    ExecResult, QueryOrder, QueryResult, QuerySelect, RuntimeErr, SqlxSqliteConnector, Statement,
+    TransactionTrait,
 };
-use sea_orm::{prelude::*, ActiveValue, ConnectOptions, QuerySelect};
 use sea_orm_migration::prelude::*;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+    ConnectOptions as _,
+};
 
 use self::error::DatabaseError;
 
@@ -341,83 +1023,370 @@ impl DbConnection {
         Self {
             path: None,
             db: None,
+            lock: None,
+            auto_clean_orphans: false,
+            connection_id: next_connection_id(),
         }
     }
+    /// Removes artists, albums, genres and youtube playlist ids no song links to anymore
+    /// whenever a song is deleted or its links are replaced
+    pub fn with_auto_clean_orphans(mut self, auto_clean_orphans: bool) -> Self {
+        self.auto_clean_orphans = auto_clean_orphans;
+        self
+    }
+
     pub async fn new(path: PathBuf) -> Result<Self, DatabaseError> {
-        let db_path = format!("sqlite:{}?mode=rwc", path.display());
-        let mut opt = ConnectOptions::new(db_path);
-        opt.sqlx_logging(true)
-            .sqlx_logging_level(tracing::log::LevelFilter::Trace);
-        let db = sea_orm::Database::connect(opt).await?;
+        Self::with_backups(path, DEFAULT_KEEP_BACKUPS).await
+    }
 
-        let _schema_manager = SchemaManager::new(&db);
+    pub async fn m_new(path: PathBuf) -> Result<Self, DatabaseError> {
+        Self::with_backups(path, DEFAULT_KEEP_BACKUPS).await
+    }
+
+    /// Opens the database at `path` and applies pending migrations. A database that was
+    /// migrated before is backed up first, keeping the newest `keep_backups` backups, 0
+    /// turns the backups off.
+    pub async fn with_backups(path: PathBuf, keep_backups: usize) -> Result<Self, DatabaseError> {
+        let lock = DatabaseLock::try_shared(&path)?;
+        let db = Self::open(&path).await?;
+        let connection = Self {
+            path: Some(path),
+            db: Some(DbHandle::Connection(db)),
+            lock: Some(lock),
+            auto_clean_orphans: false,
+            connection_id: next_connection_id(),
+        };
 
         // ensure up to date
-        crate::migrator::Migrator::up(&db, None).await?;
+        let pending =
+            crate::migrator::Migrator::get_pending_migrations(connection.ref_db()?).await?;
+        if !pending.is_empty()
+            && keep_backups > 0
+            && !crate::migrator::Migrator::get_applied_migrations(connection.ref_db()?)
+                .await?
+                .is_empty()
+        {
+            let backup = connection.backup(keep_backups).await?;
+            info!(
+                "backed up database to {} before applying {} migrations",
+                backup.display(),
+                pending.len()
+            );
+        }
+        crate::migrator::Migrator::up(connection.ref_db()?, None).await?;
 
-        Ok(Self {
-            path: Some(path),
-            db: Some(db),
-        })
+        Ok(connection)
     }
 
-    pub async fn m_new(path: PathBuf) -> Result<Self, DatabaseError> {
-        let db_path = format!("sqlite:{}?mode=rwc", path.display());
-        let mut opt = ConnectOptions::new(db_path);
-        opt.sqlx_logging(true)
-            .sqlx_logging_level(tracing::log::LevelFilter::Trace);
-        let db = sea_orm::Database::connect(opt).await?;
+    /// Connects to the database at `path` without migrating it.
+    ///
+    /// The GUI, TUI and CLI may have the same database open. In WAL mode readers don't
+    /// block the writer and the other way around, and a write waits up to `BUSY_TIMEOUT`
+    /// for the write of another process instead of failing with "database is locked".
+    /// sea_orm can't set these per connection, so the sqlx pool is built here.
+    async fn open(path: &Path) -> Result<DatabaseConnection, DatabaseError> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            // WAL is safe from corruption with NORMAL, only the last commits before a
+            // power loss may be lost
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT)
+            .foreign_keys(true)
+            .log_statements(tracing::log::LevelFilter::Trace);
+        // sqlite has a single writer anyway, with one connection a transaction makes
+        // everything else in this process wait for it instead of hitting the lock. It is
+        // kept open so the pragmas and page cache aren't set up again.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .acquire_timeout(ACQUIRE_TIMEOUT)
+            .connect_with(options)
+            .await
+            .map_err(|e| DbErr::Conn(RuntimeErr::SqlxError(e)))?;
+        let db = SqlxSqliteConnector::from_sqlx_sqlite_pool(pool);
 
         let _schema_manager = SchemaManager::new(&db);
 
-        // ensure up to date
-        crate::migrator::Migrator::up(&db, None).await?;
+        Ok(db)
+    }
 
-        Ok(Self {
-            path: Some(path),
-            db: Some(db),
-        })
+    /// Connects with foreign keys enforced, so deleting a song cascades to its links.
+    /// sqlx already enables them for its sqlite connections, this makes sure a change of
+    /// driver default can't silently turn them off. The pool only holds one connection.
+    async fn connect(opt: ConnectOptions) -> Result<DatabaseConnection, DbErr> {
+        let db = sea_orm::Database::connect(opt).await?;
+        db.execute_unprepared("PRAGMA foreign_keys = ON;").await?;
+        Ok(db)
     }
 
-    pub fn ref_db(&self) -> &DatabaseConnection {
-        self.db.as_ref().unwrap()
+    /// Snapshots the database into the `backups` dir next to it and removes all but the
+    /// newest `keep_backups` snapshots. `VACUUM INTO` gives a consistent copy while the
+    /// database is in use, so this can't run inside a transaction.
+    ///
+    /// This stands in for sqlite's online backup API, which sqlx doesn't expose. Calling
+    /// it through `libsqlite3-sys` would take unsafe code on the raw connection and pin
+    /// the crate to the sqlite version sqlx links. The snapshot is taken in a single read
+    /// transaction like the backup API's, and comes out compacted.
+    pub async fn backup(&self, keep_backups: usize) -> Result<PathBuf, DatabaseError> {
+        let path = self.path.as_ref().ok_or(DatabaseError::InMemoryDatabase)?;
+        let dir = backup_dir(path);
+        std::fs::create_dir_all(&dir)?;
+        let mut millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+        // a backup taken in the same millisecond as the last one gets the next free
+        // timestamp, so the names stay unique and in the order they were taken
+        let mut backup = dir.join(format!("{}-{millis}.sqlite", database_stem(path)));
+        while backup.exists() {
+            millis += 1;
+            backup = dir.join(format!("{}-{millis}.sqlite", database_stem(path)));
+        }
+        self.ref_db()?
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                "VACUUM INTO $1",
+                [backup.to_string_lossy().into_owned().into()],
+            ))
+            .await?;
+
+        for old in Self::list_backups(path)?.into_iter().skip(keep_backups) {
+            debug!("removing old backup {}", old.display());
+            std::fs::remove_file(old)?;
+        }
+        Ok(backup)
     }
-    pub async fn open_in_memory() -> Self {
-        let mut opt = ConnectOptions::new("sqlite::memory:".to_owned());
-        opt.sqlx_logging(true)
-            .sqlx_logging_level(tracing::log::LevelFilter::Debug);
-        let db = sea_orm::Database::connect(opt).await.expect("success");
-        info!("spawned new in memory sqlite database");
 
-        Self {
-            path: None,
-            db: Some(db),
+    /// Backups of the database at `path`, newest first
+    pub fn list_backups(path: &Path) -> Result<Vec<PathBuf>, DatabaseError> {
+        let dir = backup_dir(path);
+        if !dir.is_dir() {
+            return Ok(vec![]);
         }
+        let stem = database_stem(path);
+        let mut backups = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let backup = entry?.path();
+            let taken_at = backup
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(stem.as_str()))
+                .and_then(|name| name.strip_prefix('-'))
+                .and_then(|name| name.strip_suffix(".sqlite"))
+                .and_then(|millis| millis.parse::<u128>().ok());
+            if let Some(taken_at) = taken_at {
+                backups.push((taken_at, backup));
+            }
+        }
+        backups.sort_by_key(|(taken_at, _)| std::cmp::Reverse(*taken_at));
+        Ok(backups.into_iter().map(|(_, backup)| backup).collect())
     }
 
-    /// insert an entry into the `artist` table
-    #[tracing::instrument(skip(self))]
-    pub async fn insert_artist(&self, artist: String) -> Result<i32, DatabaseError> {
-        let artist_model = Artist::find()
-            .filter(artist::Column::Name.eq(artist.clone()))
-            .one(self.ref_db())
-            .await?;
-        if let Some(artist) = artist_model {
-            warn!("artist {} already exists in database", artist.name);
-            Ok(artist.id)
-        } else {
-            let model = artist::ActiveModel {
-                name: ActiveValue::Set(artist.to_owned()),
-                ..Default::default()
+    /// Replaces the database at `path` with `backup`, refusing while anything has the
+    /// database open as its journal files are removed. Unless `keep_backups` is 0 the
+    /// replaced database is backed up first, that backup is returned.
+    pub async fn restore(
+        path: &Path,
+        backup: &Path,
+        keep_backups: usize,
+    ) -> Result<Option<PathBuf>, DatabaseError> {
+        if !backup.is_file() {
+            return Err(DatabaseError::NoBackupFound(backup.to_path_buf()));
+        }
+        let lock = DatabaseLock::try_exclusive(path, "restoring it")?;
+        // copied first, rotating the backups below may remove `backup`
+        let staged = path.with_extension("restore");
+        std::fs::copy(backup, &staged)?;
+
+        let previous = if keep_backups > 0 && path.exists() {
+            let current = Self {
+                path: Some(path.to_path_buf()),
+                db: Some(DbHandle::Connection(Self::open(path).await?)),
+                lock: None,
+                auto_clean_orphans: false,
+                connection_id: next_connection_id(),
             };
-            Ok(Artist::insert(model)
-                .exec(self.ref_db())
-                .await?
-                .last_insert_id)
+            let previous = current.backup(keep_backups).await?;
+            if let Some(DbHandle::Connection(db)) = current.db {
+                db.close().await?;
+            }
+            Some(previous)
+        } else {
+            None
+        };
+
+        for side_file in journal_files(path) {
+            if side_file.exists() {
+                std::fs::remove_file(side_file)?;
+            }
         }
+        std::fs::rename(&staged, path)?;
+        drop(lock);
+        info!("restored {} from {}", path.display(), backup.display());
+        Ok(previous)
     }
 
-    #[tracing::instrument(skip(self))]
+    /// Moves the database at `from`, with its journal files, to `to` when only `from`
+    /// exists, returning whether it was moved. Errors instead of picking one when both
+    /// paths hold a database, and refuses while another connection has `from` open.
+    pub fn relocate(from: &Path, to: &Path) -> Result<bool, DatabaseError> {
+        if from == to || !from.exists() {
+            return Ok(false);
+        }
+        if to.exists() {
+            return Err(DatabaseError::DatabaseInBothLocations(
+                from.to_path_buf(),
+                to.to_path_buf(),
+            ));
+        }
+        let lock = DatabaseLock::try_exclusive(from, "moving it")?;
+        move_file(from, to)?;
+        for (side_file, new_side_file) in journal_files(from).into_iter().zip(journal_files(to)) {
+            if side_file.exists() {
+                move_file(&side_file, &new_side_file)?;
+            }
+        }
+        // the lock file would be left behind in the old location, the music dir
+        std::fs::remove_file(DatabaseLock::path(from))?;
+        drop(lock);
+        info!("moved database from {} to {}", from.display(), to.display());
+        Ok(true)
+    }
+
+    /// What to run statements on.
+    ///
+    /// Fails with `UsedInsideTransaction` when called on the pool inside a transaction closure
+    /// of the same connection, the transaction holds the only connection of the pool so the
+    /// statement would wait for it until `ACQUIRE_TIMEOUT`.
+    pub fn ref_db(&self) -> Result<&DbHandle, DatabaseError> {
+        let db = self.db.as_ref().ok_or(DatabaseError::NotConnected)?;
+        if matches!(db, DbHandle::Connection(_)) {
+            let in_transaction = OPEN_TRANSACTIONS
+                .try_with(|open| open.contains(&self.connection_id))
+                .unwrap_or(false);
+            if in_transaction {
+                return Err(DatabaseError::UsedInsideTransaction);
+            }
+        }
+        Ok(db)
+    }
+
+    /// Runs `f` in a transaction, committing if it returns `Ok` and rolling back otherwise.
+    ///
+    /// `f` gets a `DbConnection` bound to the transaction, everything done through it is
+    /// part of the transaction. Nested calls use savepoints. Only use the given connection
+    /// inside `f`, the pool has a single connection which the transaction holds until it ends.
+    /// Statements run on `self` in `f` fail, see [`DbConnection::ref_db`].
+    ///
+    /// The write lock is taken when the transaction starts with `BEGIN IMMEDIATE`. A plain
+    /// `BEGIN` only takes it on the first write, and in WAL mode that write fails with
+    /// `SQLITE_BUSY_SNAPSHOT` if another process committed after the transaction read.
+    pub async fn transaction<F, Fut, T>(&self, f: F) -> Result<T, DatabaseError>
+    where
+        F: FnOnce(DbConnection) -> Fut,
+        Fut: Future<Output = Result<T, DatabaseError>>,
+    {
+        let txn = Arc::new(match self.ref_db()? {
+            DbHandle::Connection(db) => {
+                let txn = db.begin().await?;
+                // sqlx 0.7 only sends a deferred `BEGIN`, nothing ran in it yet so it is
+                // swapped for an immediate one on the same connection. `BEGIN IMMEDIATE`
+                // waits up to `BUSY_TIMEOUT` for other writers.
+                txn.execute_unprepared("COMMIT").await?;
+                if let Err(e) = txn.execute_unprepared("BEGIN IMMEDIATE").await {
+                    // sqlx still counts the transaction as open and rolls it back on drop
+                    txn.execute_unprepared("BEGIN").await?;
+                    return Err(e.into());
+                }
+                txn
+            }
+            DbHandle::Transaction(txn) => txn.begin().await?,
+        });
+        let mut open = OPEN_TRANSACTIONS
+            .try_with(|open| open.clone())
+            .unwrap_or_default();
+        open.push(self.connection_id);
+        let result = OPEN_TRANSACTIONS
+            .scope(
+                open,
+                f(Self {
+                    path: self.path.clone(),
+                    db: Some(DbHandle::Transaction(txn.clone())),
+                    lock: self.lock.clone(),
+                    auto_clean_orphans: self.auto_clean_orphans,
+                    connection_id: self.connection_id,
+                }),
+            )
+            .await;
+
+        let txn = Arc::try_unwrap(txn).map_err(|_| DatabaseError::TransactionInUse)?;
+        match result {
+            Ok(value) => {
+                txn.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                txn.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+    pub async fn open_in_memory() -> Self {
+        let mut opt = ConnectOptions::new("sqlite::memory:".to_owned());
+        opt.sqlx_logging(true)
+            .sqlx_logging_level(tracing::log::LevelFilter::Debug);
+        let db = Self::connect(opt).await.expect("success");
+        info!("spawned new in memory sqlite database");
+
+        Self {
+            path: None,
+            db: Some(DbHandle::Connection(db)),
+            lock: None,
+            auto_clean_orphans: false,
+            connection_id: next_connection_id(),
+        }
+    }
+
+    /// insert an entry into the `artist` table, aliases resolve to their artist
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_artist(&self, artist: String) -> Result<i32, DatabaseError> {
+        let artist_model = Artist::find()
+            .filter(artist::Column::Name.eq(artist.clone()))
+            .one(self.ref_db()?)
+            .await?;
+        if let Some(artist) = artist_model {
+            warn!("artist {} already exists in database", artist.name);
+            Ok(artist.id)
+        } else if let Some(alias) = ArtistAlias::find()
+            .filter(artist_alias::Column::Alias.eq(artist.clone()))
+            .one(self.ref_db()?)
+            .await?
+        {
+            info!(
+                "artist {} is an alias of artist {}",
+                artist, alias.artist_id
+            );
+            Ok(alias.artist_id)
+        } else if let Some(artist_id) = self.find_merged_artist(&artist).await? {
+            info!("artist {} is a spelling of artist {}", artist, artist_id);
+            Ok(artist_id)
+        } else {
+            let model = artist::ActiveModel {
+                name: ActiveValue::Set(artist.to_owned()),
+                ..Default::default()
+            };
+            Ok(Artist::insert(model)
+                .exec(self.ref_db()?)
+                .await?
+                .last_insert_id)
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
     pub async fn insert_song_artist(
         &self,
         artist_id: i32,
@@ -429,7 +1398,7 @@ impl DbConnection {
             ..Default::default()
         };
         Ok(SongArtistJunction::insert(model)
-            .exec(self.ref_db())
+            .exec(self.ref_db()?)
             .await?
             .last_insert_id)
     }
@@ -437,25 +1406,76 @@ impl DbConnection {
     /// insert an entry into the `album` table
     #[tracing::instrument(skip(self))]
     pub async fn insert_album(&self, album: String) -> Result<i32, DatabaseError> {
-        let album_model = Album::find()
-            .filter(album::Column::Name.eq(album.clone()))
-            .one(self.ref_db())
-            .await?;
+        self.insert_album_with_artist(album, None).await
+    }
+
+    /// insert an entry into the `album` table, albums are unique by name and album artist
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_album_with_artist(
+        &self,
+        album: String,
+        album_artist: Option<String>,
+    ) -> Result<i32, DatabaseError> {
+        let album_model = self.find_album(&album, album_artist.as_deref()).await?;
         if let Some(album) = album_model {
             warn!("album {} exists in database", album.name);
             Ok(album.id)
         } else {
             let model = album::ActiveModel {
                 name: ActiveValue::Set(album.to_owned()),
+                album_artist: ActiveValue::Set(album_artist),
                 ..Default::default()
             };
             Ok(Album::insert(model)
-                .exec(self.ref_db())
+                .exec(self.ref_db()?)
                 .await?
                 .last_insert_id)
         }
     }
 
+    /// Artist that other artists were merged into whose name or aliases are spelled like
+    /// `name`, ignoring case, punctuation and the "- Topic" and "VEVO" channel suffixes
+    async fn find_merged_artist(&self, name: &str) -> Result<Option<i32>, DatabaseError> {
+        let key = artist_key(name);
+        Ok(ArtistAlias::find()
+            .find_also_related(Artist)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .find(|(alias, artist)| {
+                artist_key(&alias.alias) == key
+                    || artist
+                        .as_ref()
+                        .is_some_and(|artist| artist_key(&artist.name) == key)
+            })
+            .map(|(alias, _)| alias.artist_id))
+    }
+
+    /// The artist with exactly this name
+    pub async fn find_artist(&self, name: &str) -> Result<Option<ArtistModel>, DatabaseError> {
+        Ok(Artist::find()
+            .filter(artist::Column::Name.eq(name))
+            .one(self.ref_db()?)
+            .await?)
+    }
+
+    /// The album with this name and album artist
+    pub async fn find_album(
+        &self,
+        name: &str,
+        album_artist: Option<&str>,
+    ) -> Result<Option<AlbumModel>, DatabaseError> {
+        let album_artist_condition = match album_artist {
+            Some(album_artist) => album::Column::AlbumArtist.eq(album_artist),
+            None => album::Column::AlbumArtist.is_null(),
+        };
+        Ok(Album::find()
+            .filter(album::Column::Name.eq(name))
+            .filter(album_artist_condition)
+            .one(self.ref_db()?)
+            .await?)
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn insert_song_album(
         &self,
@@ -468,7 +1488,7 @@ impl DbConnection {
             ..Default::default()
         };
         Ok(SongAlbumJunction::insert(model)
-            .exec(self.ref_db())
+            .exec(self.ref_db()?)
             .await?
             .last_insert_id)
     }
@@ -477,7 +1497,7 @@ impl DbConnection {
     pub async fn insert_genre(&self, genre: String) -> Result<i32, DatabaseError> {
         let genre_model = Genre::find()
             .filter(genre::Column::Genre.eq(genre.clone()))
-            .one(self.ref_db())
+            .one(self.ref_db()?)
             .await?;
         if let Some(genre) = genre_model {
             warn!("genre {} exists in database", genre.genre);
@@ -488,7 +1508,7 @@ impl DbConnection {
                 ..Default::default()
             };
             Ok(Genre::insert(model)
-                .exec(self.ref_db())
+                .exec(self.ref_db()?)
                 .await?
                 .last_insert_id)
         }
@@ -507,11 +1527,59 @@ impl DbConnection {
         };
 
         Ok(SongGenreJunction::insert(model)
-            .exec(self.ref_db())
+            .exec(self.ref_db()?)
+            .await?
+            .last_insert_id)
+    }
+
+    /// insert an entry into the `label` table, returning the id of the label if it exists
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_label(&self, name: String) -> Result<i32, DatabaseError> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(DatabaseError::EmptyLabel);
+        }
+        if name.contains(';') {
+            return Err(DatabaseError::LabelSeparator(name));
+        }
+        if let Some(label) = Label::find()
+            .filter(label::Column::Name.eq(name.clone()))
+            .one(self.ref_db()?)
+            .await?
+        {
+            return Ok(label.id);
+        }
+        let model = label::ActiveModel {
+            name: ActiveValue::Set(name),
+            ..Default::default()
+        };
+        Ok(Label::insert(model)
+            .exec(self.ref_db()?)
             .await?
             .last_insert_id)
     }
 
+    /// Links the label to the song unless it already has it
+    async fn insert_song_label(&self, song_id: i32, name: String) -> Result<(), DatabaseError> {
+        let label_id = self.insert_label(name).await?;
+        let linked = SongLabelJunction::find()
+            .filter(song_label_junction::Column::SongId.eq(song_id))
+            .filter(song_label_junction::Column::LabelId.eq(label_id))
+            .one(self.ref_db()?)
+            .await?;
+        if linked.is_none() {
+            let model = song_label_junction::ActiveModel {
+                song_id: ActiveValue::Set(song_id),
+                label_id: ActiveValue::Set(label_id),
+                ..Default::default()
+            };
+            SongLabelJunction::insert(model)
+                .exec(self.ref_db()?)
+                .await?;
+        }
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn insert_youtube_playlist_id(
         &self,
@@ -519,7 +1587,7 @@ impl DbConnection {
     ) -> Result<i32, DatabaseError> {
         let youtube_playlist_id_model = YoutubePlaylistId::find()
             .filter(youtube_playlist_id::Column::YoutubePlaylistId.eq(youtube_playlist_id.clone()))
-            .one(self.ref_db())
+            .one(self.ref_db()?)
             .await?;
         if let Some(youtube_playlist_id) = youtube_playlist_id_model {
             warn!(
@@ -533,27 +1601,152 @@ impl DbConnection {
                 ..Default::default()
             };
             Ok(YoutubePlaylistId::insert(model)
-                .exec(self.ref_db())
+                .exec(self.ref_db()?)
                 .await?
                 .last_insert_id)
         }
     }
 
+    /// Appends the song to the end of the youtube playlist, returning the junction key.
+    /// Songs already in the playlist keep their position.
     pub async fn insert_song_youtube_playlist_id(
         &self,
         youtube_playlist_id_id: i32,
         song_id: i32,
     ) -> Result<i32, DatabaseError> {
-        let model = song_youtube_playlist_id_junction::ActiveModel {
-            song_id: ActiveValue::Set(song_id),
-            youtube_playlist_id_id: ActiveValue::Set(youtube_playlist_id_id),
-            ..Default::default()
-        };
+        self.transaction(|db| async move {
+            let entries = SongYoutubePlaylistIdJunction::find()
+                .filter(
+                    song_youtube_playlist_id_junction::Column::YoutubePlaylistIdId
+                        .eq(youtube_playlist_id_id),
+                )
+                .order_by_desc(song_youtube_playlist_id_junction::Column::Position)
+                .all(db.ref_db()?)
+                .await?;
+            if let Some(entry) = entries.iter().find(|entry| entry.song_id == song_id) {
+                return Ok(entry.key);
+            }
+
+            let model = song_youtube_playlist_id_junction::ActiveModel {
+                song_id: ActiveValue::Set(song_id),
+                youtube_playlist_id_id: ActiveValue::Set(youtube_playlist_id_id),
+                position: ActiveValue::Set(entries.first().map_or(0, |last| last.position + 1)),
+                added_at: ActiveValue::Set(unix_now()),
+                ..Default::default()
+            };
+            Ok(SongYoutubePlaylistIdJunction::insert(model)
+                .exec(db.ref_db()?)
+                .await?
+                .last_insert_id)
+        })
+        .await
+    }
+
+    /// Adds the song to the youtube playlist, creating the playlist id if needed
+    #[tracing::instrument(skip(self))]
+    pub async fn add_song_to_youtube_playlist(
+        &self,
+        song_id: i32,
+        youtube_playlist_id: String,
+    ) -> Result<i32, DatabaseError> {
+        self.transaction(|db| async move {
+            let youtube_playlist_id_id = db.insert_youtube_playlist_id(youtube_playlist_id).await?;
+            db.insert_song_youtube_playlist_id(youtube_playlist_id_id, song_id)
+                .await
+        })
+        .await
+    }
+
+    /// Removes the song from the youtube playlist, the following songs move up by one
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_song_from_youtube_playlist(
+        &self,
+        song_id: i32,
+        youtube_playlist_id_id: i32,
+    ) -> Result<(), DatabaseError> {
+        self.transaction(|db| async move {
+            let entry = SongYoutubePlaylistIdJunction::find()
+                .filter(song_youtube_playlist_id_junction::Column::SongId.eq(song_id))
+                .filter(
+                    song_youtube_playlist_id_junction::Column::YoutubePlaylistIdId
+                        .eq(youtube_playlist_id_id),
+                )
+                .one(db.ref_db()?)
+                .await?
+                .ok_or(DatabaseError::NoSongFound)?;
+            SongYoutubePlaylistIdJunction::delete_by_id(entry.key)
+                .exec(db.ref_db()?)
+                .await?;
+            SongYoutubePlaylistIdJunction::update_many()
+                .col_expr(
+                    song_youtube_playlist_id_junction::Column::Position,
+                    Expr::col(song_youtube_playlist_id_junction::Column::Position).sub(1),
+                )
+                .filter(
+                    song_youtube_playlist_id_junction::Column::YoutubePlaylistIdId
+                        .eq(youtube_playlist_id_id),
+                )
+                .filter(song_youtube_playlist_id_junction::Column::Position.gt(entry.position))
+                .exec(db.ref_db()?)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_youtube_playlist_id(
+        &self,
+        youtube_playlist_id: &str,
+    ) -> Result<Option<YoutubePlaylistIdModel>, DatabaseError> {
+        Ok(YoutubePlaylistId::find()
+            .filter(youtube_playlist_id::Column::YoutubePlaylistId.eq(youtube_playlist_id))
+            .one(self.ref_db()?)
+            .await?)
+    }
+
+    pub async fn get_all_youtube_playlist_ids(
+        &self,
+    ) -> Result<Vec<YoutubePlaylistIdModel>, DatabaseError> {
+        Ok(YoutubePlaylistId::find()
+            .order_by_asc(youtube_playlist_id::Column::YoutubePlaylistId)
+            .all(self.ref_db()?)
+            .await?)
+    }
 
-        Ok(SongYoutubePlaylistIdJunction::insert(model)
-            .exec(self.ref_db())
+    /// Songs of the youtube playlist in playlist order, with their position and date added
+    pub async fn get_youtube_playlist_songs(
+        &self,
+        youtube_playlist_id_id: i32,
+    ) -> Result<Vec<(SongYoutubePlaylistIdJunctionModel, SongModel)>, DatabaseError> {
+        Ok(SongYoutubePlaylistIdJunction::find()
+            .filter(
+                song_youtube_playlist_id_junction::Column::YoutubePlaylistIdId
+                    .eq(youtube_playlist_id_id),
+            )
+            .order_by_asc(song_youtube_playlist_id_junction::Column::Position)
+            .find_also_related(SongEntity)
+            .all(self.ref_db()?)
             .await?
-            .last_insert_id)
+            .into_iter()
+            .filter_map(|(entry, song)| song.map(|song| (entry, song)))
+            .collect())
+    }
+
+    /// Youtube playlists the song is part of, with its position and date added in each
+    pub async fn get_song_youtube_playlists(
+        &self,
+        song_id: i32,
+    ) -> Result<Vec<(SongYoutubePlaylistIdJunctionModel, YoutubePlaylistIdModel)>, DatabaseError>
+    {
+        Ok(SongYoutubePlaylistIdJunction::find()
+            .filter(song_youtube_playlist_id_junction::Column::SongId.eq(song_id))
+            .order_by_asc(song_youtube_playlist_id_junction::Column::AddedAt)
+            .find_also_related(YoutubePlaylistId)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .filter_map(|(entry, playlist)| playlist.map(|playlist| (entry, playlist)))
+            .collect())
     }
 
     #[tracing::instrument(skip(self))]
@@ -563,15 +1756,17 @@ impl DbConnection {
         youtube_id: Option<String>,
         thumbnail_url: Option<String>,
     ) -> Result<i32, DatabaseError> {
-        let model = song::ActiveModel {
+        let mut model = song::ActiveModel {
             title: ActiveValue::Set(title),
-            youtube_id: ActiveValue::Set(youtube_id),
             thumbnail_url: ActiveValue::Set(thumbnail_url),
+            added_at: ActiveValue::Set(Some(unix_now())),
             ..Default::default()
         };
+        set_youtube_source(&mut model, youtube_id.as_ref());
+        model.youtube_id = ActiveValue::Set(youtube_id);
 
         Ok(SongEntity::insert(model)
-            .exec(self.ref_db())
+            .exec(self.ref_db()?)
             .await?
             .last_insert_id)
     }
@@ -583,237 +1778,277 @@ impl DbConnection {
         thumbnail_url: Option<String>,
         path: Option<String>,
     ) -> Result<i32, DatabaseError> {
-        let model = song::ActiveModel {
+        let mut model = song::ActiveModel {
             title: ActiveValue::Set(title),
-            youtube_id: ActiveValue::Set(youtube_id),
             thumbnail_url: ActiveValue::Set(thumbnail_url),
             // should only be the filename to ensure crossplatform
             path: ActiveValue::Set(path),
+            added_at: ActiveValue::Set(Some(unix_now())),
             ..Default::default()
         };
+        set_youtube_source(&mut model, youtube_id.as_ref());
+        model.youtube_id = ActiveValue::Set(youtube_id);
 
         Ok(SongEntity::insert(model)
-            .exec(self.ref_db())
+            .exec(self.ref_db()?)
             .await?
             .last_insert_id)
     }
 
     pub async fn get_all_songs_gui(&self, music_dir: PathBuf) -> Vec<GSong> {
-        let songs = song::Entity::find()
-            .all(self.ref_db())
+        let Ok(db) = self.ref_db() else {
+            return vec![];
+        };
+        let songs = song::Entity::find().all(db).await.unwrap_or(vec![]);
+        self.load_gui_songs(songs, &music_dir)
             .await
-            .unwrap_or(vec![]);
-        let mut vvec = vec![];
-        for s in songs {
-            let mut new_song = GSong::new()
-                .set_path(PathBuf::from(
-                    music_dir.join(s.path.expect("must have partial path")),
-                ))
-                .set_id(s.id)
-                .set_youtube_id(s.youtube_id.unwrap_or_default())
-                .set_thumbnail_url(s.thumbnail_url.unwrap_or_default())
-                .set_title(s.title);
+            .unwrap_or(vec![])
+    }
 
-            let artists = {
-                let mut a_vec = vec![];
-                for (_sa, mut artists) in song::Entity::find()
-                    .find_with_related(Artist)
-                    .filter(song::Column::Id.eq(s.id))
-                    .all(self.ref_db())
-                    .await
-                    .unwrap_or(vec![])
-                {
-                    artists.sort_by_key(|s1| s1.id);
-                    for artist in artists {
-                        a_vec.push(artist);
-                    }
-                }
-                a_vec
-            };
-            new_song.set_artists(artists);
-
-            let albums = {
-                let mut albums_v = vec![];
-                for (_, albums) in song::Entity::find()
-                    .find_with_related(Album)
-                    .filter(song::Column::Id.eq(s.id))
-                    .all(self.ref_db())
-                    .await
-                    .unwrap_or(vec![])
-                {
-                    for album in albums {
-                        albums_v.push(album);
-                    }
-                }
-                albums_v
-            };
-            new_song.set_albums(albums);
-
-            let genres = {
-                let mut genres_v = vec![];
-                for (_, genres) in song::Entity::find()
-                    .find_with_related(Genre)
-                    .filter(song::Column::Id.eq(s.id))
-                    .all(self.ref_db())
-                    .await
-                    .unwrap_or(vec![])
-                {
-                    for genre in genres {
-                        genres_v.push(genre);
-                    }
-                }
-                genres_v
-            };
-            new_song.set_genres(genres);
-
-            let youtube_playlist_ids = {
-                let mut yt_p_id = vec![];
-                for (_, youtube_playlist_id_model) in song::Entity::find()
-                    .find_with_related(YoutubePlaylistId)
-                    .filter(song::Column::Id.eq(s.id))
-                    .all(self.ref_db())
-                    .await
-                    .unwrap_or(vec![])
-                {
-                    for youtube_playlist_id in youtube_playlist_id_model {
-                        yt_p_id.push(youtube_playlist_id);
-                    }
-                }
-                yt_p_id
-            };
-            new_song.set_youtube_playlists(youtube_playlist_ids);
-            vvec.push(new_song);
-        }
-        vvec
+    pub async fn get_song_gui(
+        &self,
+        song_id: i32,
+        music_dir: PathBuf,
+    ) -> Result<GSong, DatabaseError> {
+        let s = SongEntity::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoSongFound)?;
+        self.load_gui_song(s, &music_dir).await
     }
 
-    pub async fn get_all_songs_empty(&self, music_dir: PathBuf) -> Vec<AppSong> {
-        let songs = song::Entity::find()
-            .all(self.ref_db())
-            .await
-            .unwrap_or(vec![]);
-        let mut vvec = vec![];
-        for s in songs {
-            let mut new_song = AppSong::new()
-                .with_music_dir(Some(music_dir.clone()))
-                .with_id(Some(s.id))
-                .with_yt_id(s.youtube_id)
-                .with_tb_url(s.thumbnail_url)
-                .with_title(Some(s.title));
-            for (_sa, mut artists) in song::Entity::find()
-                .find_with_related(Artist)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
-                .await
-                .unwrap_or(vec![])
-            {
-                artists.sort_by_key(|s1| s1.id);
-                for artist in artists {
-                    new_song.add_artist(artist);
-                }
-            }
-            for (_, albums) in song::Entity::find()
-                .find_with_related(Album)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
-                .await
-                .unwrap_or(vec![])
-            {
-                for album in albums {
-                    new_song.add_album(album);
-                }
-            }
-            for (_, genres) in song::Entity::find()
-                .find_with_related(Genre)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
-                .await
-                .unwrap_or(vec![])
-            {
-                for genre in genres {
-                    new_song.add_genre(genre);
-                }
-            }
+    /// Fills a `Song` with the model and its relations
+    async fn load_gui_song(&self, s: SongModel, music_dir: &Path) -> Result<GSong, DatabaseError> {
+        let mut relations = self.load_relations(&[s.id], true).await?;
+        Ok(build_gui_song(s, music_dir, &mut relations))
+    }
 
-            for (_, youtube_playlist_id_model) in song::Entity::find()
-                .find_with_related(YoutubePlaylistId)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
-                .await
-                .unwrap_or(vec![])
+    /// Fills `Song`s with their models and relations, with a fixed number of queries
+    /// per `SONG_BATCH_SIZE` songs
+    async fn load_gui_songs(
+        &self,
+        songs: Vec<SongModel>,
+        music_dir: &Path,
+    ) -> Result<Vec<GSong>, DatabaseError> {
+        let song_ids = songs.iter().map(|s| s.id).collect::<Vec<_>>();
+        let mut relations = self.load_relations(&song_ids, true).await?;
+        Ok(songs
+            .into_iter()
+            .map(|s| build_gui_song(s, music_dir, &mut relations))
+            .collect())
+    }
+
+    /// Like `load_gui_songs`, for `AppSong`s
+    async fn load_app_songs(
+        &self,
+        songs: Vec<SongModel>,
+        music_dir: &Path,
+    ) -> Result<Vec<AppSong>, DatabaseError> {
+        let song_ids = songs.iter().map(|s| s.id).collect::<Vec<_>>();
+        let mut relations = self.load_relations(&song_ids, true).await?;
+        Ok(songs
+            .into_iter()
+            .map(|s| build_app_song(s, music_dir, &mut relations))
+            .collect())
+    }
+
+    /// Loads the relations of the songs with one query per relation for every
+    /// `SONG_BATCH_SIZE` songs, instead of one per song. Lyrics and download provenance
+    /// are only loaded `with_details`.
+    async fn load_relations(
+        &self,
+        song_ids: &[i32],
+        with_details: bool,
+    ) -> Result<SongRelations, DatabaseError> {
+        let mut relations = SongRelations::default();
+        for batch in song_ids.chunks(SONG_BATCH_SIZE) {
+            for (link, artist) in SongArtistJunction::find()
+                .filter(song_artist_junction::Column::SongId.is_in(batch.iter().copied()))
+                .find_also_related(Artist)
+                .all(self.ref_db()?)
+                .await?
             {
-                for youtube_playlist_id in youtube_playlist_id_model {
-                    new_song.add_yt_playlist_id(youtube_playlist_id);
+                if let Some(artist) = artist {
+                    relations
+                        .artists
+                        .entry(link.song_id)
+                        .or_default()
+                        .push(artist);
                 }
             }
-            new_song.compute_filename();
-            vvec.push(new_song);
-        }
-        vvec
-    }
-    pub async fn get_all_songs(&self, music_dir: PathBuf) -> Result<Vec<AppSong>, DatabaseError> {
-        let songs = song::Entity::find().all(self.ref_db()).await?;
-        let mut vvec = vec![];
-        for s in songs {
-            let mut new_song = AppSong::new()
-                .with_music_dir(Some(music_dir.clone()))
-                .with_id(Some(s.id))
-                .with_yt_id(s.youtube_id)
-                .with_tb_url(s.thumbnail_url)
-                .with_title(Some(s.title));
-            for (_sa, mut artists) in song::Entity::find()
-                .find_with_related(Artist)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
+            for (link, album) in SongAlbumJunction::find()
+                .filter(song_album_junction::Column::SongId.is_in(batch.iter().copied()))
+                .order_by_asc(song_album_junction::Column::Key)
+                .find_also_related(Album)
+                .all(self.ref_db()?)
                 .await?
             {
-                artists.sort_by_key(|s1| s1.id);
-                for artist in artists {
-                    new_song.add_artist(artist);
+                if let Some(album) = album {
+                    relations
+                        .albums
+                        .entry(link.song_id)
+                        .or_default()
+                        .push(album);
                 }
             }
-            for (_, albums) in song::Entity::find()
-                .find_with_related(Album)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
+            for (link, genre) in SongGenreJunction::find()
+                .filter(song_genre_junction::Column::SongId.is_in(batch.iter().copied()))
+                .order_by_asc(song_genre_junction::Column::Key)
+                .find_also_related(Genre)
+                .all(self.ref_db()?)
                 .await?
             {
-                for album in albums {
-                    new_song.add_album(album);
+                if let Some(genre) = genre {
+                    relations
+                        .genres
+                        .entry(link.song_id)
+                        .or_default()
+                        .push(genre);
                 }
             }
-            for (_, genres) in song::Entity::find()
-                .find_with_related(Genre)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
+            for (link, label) in SongLabelJunction::find()
+                .filter(song_label_junction::Column::SongId.is_in(batch.iter().copied()))
+                .find_also_related(Label)
+                .all(self.ref_db()?)
                 .await?
             {
-                for genre in genres {
-                    new_song.add_genre(genre);
+                if let Some(label) = label {
+                    relations
+                        .labels
+                        .entry(link.song_id)
+                        .or_default()
+                        .push(label);
                 }
             }
-
-            for (_, youtube_playlist_id_model) in song::Entity::find()
-                .find_with_related(YoutubePlaylistId)
-                .filter(song::Column::Id.eq(s.id))
-                .all(self.ref_db())
+            for (link, youtube_playlist_id) in SongYoutubePlaylistIdJunction::find()
+                .filter(
+                    song_youtube_playlist_id_junction::Column::SongId.is_in(batch.iter().copied()),
+                )
+                .order_by_asc(song_youtube_playlist_id_junction::Column::Key)
+                .find_also_related(YoutubePlaylistId)
+                .all(self.ref_db()?)
                 .await?
             {
-                for youtube_playlist_id in youtube_playlist_id_model {
-                    new_song.add_yt_playlist_id(youtube_playlist_id);
+                if let Some(youtube_playlist_id) = youtube_playlist_id {
+                    relations
+                        .youtube_playlists
+                        .entry(link.song_id)
+                        .or_default()
+                        .push(youtube_playlist_id);
+                }
+            }
+            if with_details {
+                for lyrics in Lyrics::find()
+                    .filter(lyrics::Column::SongId.is_in(batch.iter().copied()))
+                    .all(self.ref_db()?)
+                    .await?
+                {
+                    relations
+                        .lyrics
+                        .insert(lyrics.song_id, lyrics_from_model(lyrics));
+                }
+                for download in SongDownload::find()
+                    .filter(song_download::Column::SongId.is_in(batch.iter().copied()))
+                    .all(self.ref_db()?)
+                    .await?
+                {
+                    relations
+                        .provenance
+                        .insert(download.song_id, Provenance::from_model(download));
                 }
             }
-            new_song.compute_filename();
-            vvec.push(new_song);
         }
-        Ok(vvec)
+        for artists in relations.artists.values_mut() {
+            artists.sort_by_key(|artist| artist.id);
+        }
+        for labels in relations.labels.values_mut() {
+            labels.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(relations)
+    }
+
+    pub async fn get_all_songs_empty(&self, music_dir: PathBuf) -> Vec<AppSong> {
+        let Ok(db) = self.ref_db() else {
+            return vec![];
+        };
+        let songs = song::Entity::find().all(db).await.unwrap_or(vec![]);
+        self.load_app_songs(songs, &music_dir)
+            .await
+            .unwrap_or(vec![])
+    }
+    pub async fn get_all_songs(&self, music_dir: PathBuf) -> Result<Vec<AppSong>, DatabaseError> {
+        let songs = song::Entity::find().all(self.ref_db()?).await?;
+        self.load_app_songs(songs, &music_dir).await
+    }
+
+    /// Songs matching `query`, filtered, sorted and paged by the database
+    pub async fn query_songs(
+        &self,
+        query: &SongQuery,
+        music_dir: PathBuf,
+    ) -> Result<Vec<AppSong>, DatabaseError> {
+        let songs = query.select().all(self.ref_db()?).await?;
+        self.load_app_songs(songs, &music_dir).await
+    }
+
+    /// Like `query_songs`, loaded for the GUI
+    pub async fn query_songs_gui(
+        &self,
+        query: &SongQuery,
+        music_dir: PathBuf,
+    ) -> Result<Vec<GSong>, DatabaseError> {
+        let songs = query.select().all(self.ref_db()?).await?;
+        self.load_gui_songs(songs, &music_dir).await
+    }
+
+    /// Number of songs matching `query`, ignoring its limit and offset
+    pub async fn count_songs(&self, query: &SongQuery) -> Result<u64, DatabaseError> {
+        let query = SongQuery {
+            limit: None,
+            offset: None,
+            ..query.clone()
+        };
+        Ok(query.select().count(self.ref_db()?).await?)
+    }
+
+    /// Full-text search over song titles, artists, albums and genres.
+    ///
+    /// Every word of `query` is matched as a prefix and diacritics are ignored,
+    /// so `beyon` finds "Beyoncé". Results are ordered best match first, with
+    /// title matches weighted above artists, albums and genres, and cut off after
+    /// `limit` songs if given.
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: Option<u64>,
+        music_dir: PathBuf,
+    ) -> Result<Vec<AppSong>, DatabaseError> {
+        let Some(match_query) = fts_match_query(query) else {
+            return Ok(vec![]);
+        };
+        let songs = SongEntity::find()
+            .from_raw_sql(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "SELECT song.* FROM song_search JOIN song ON song.id = song_search.rowid \
+                WHERE song_search MATCH $1 \
+                ORDER BY bm25(song_search, 10.0, 5.0, 2.0, 1.0) \
+                LIMIT $2",
+                // a negative limit is no limit to sqlite
+                [
+                    match_query.into(),
+                    limit.map_or(-1, |limit| limit as i64).into(),
+                ],
+            ))
+            .all(self.ref_db()?)
+            .await?;
+        self.load_app_songs(songs, &music_dir).await
     }
 
     pub async fn get_all_artists(&self) -> Result<Vec<String>, DatabaseError> {
         let artists_vec = Artist::find()
             .select_only()
             .column(artist::Column::Name)
-            .all(self.ref_db())
+            .all(self.ref_db()?)
             .await?;
 
         Ok(artists_vec
@@ -826,97 +2061,47 @@ impl DbConnection {
         &self,
         present: Vec<i32>,
     ) -> Result<Vec<GSong>, DatabaseError> {
-        let songs = SongEntity::find().all(self.ref_db()).await?;
-
-        let diff: Vec<_> = songs
+        let present = present.into_iter().collect::<HashSet<_>>();
+        let diff: Vec<_> = SongEntity::find()
+            .all(self.ref_db()?)
+            .await?
             .into_iter()
             .filter(|item| !present.contains(&item.id))
             .collect();
+        let song_ids = diff.iter().map(|s| s.id).collect::<Vec<_>>();
+        let mut relations = self.load_relations(&song_ids, false).await?;
 
         let mut vvec = vec![];
         for s in diff {
             // TODO: wrap this in a function
             let mut new_song = GSong::new()
+                .set_track_info(&s)
+                .set_audio_properties(AudioProperties::from_model(&s))
+                .set_play_stats(Some(PlayStats::from_model(&s)))
                 .set_path(PathBuf::from(s.path.unwrap_or_default()))
                 .set_id(s.id)
                 // .set_youtube_id(s.youtube_id.unwrap_or_default())
                 .set_thumbnail_url(s.thumbnail_url.unwrap_or_default())
+                .set_source(s.source)
+                .set_source_url(s.source_url)
+                .set_source_id(s.source_id)
                 .set_title(s.title);
 
             if let Some(youtube_id) = s.youtube_id {
                 new_song.set_youtube_id(youtube_id);
-                new_song.set_source(Source::Youtube);
 
                 // youtube playlist should only exist if source is youtube
-                let youtube_playlist_ids = {
-                    let mut yt_p_id = vec![];
-                    for (_, youtube_playlist_id_model) in song::Entity::find()
-                        .find_with_related(YoutubePlaylistId)
-                        .filter(song::Column::Id.eq(s.id))
-                        .all(self.ref_db())
-                        .await
-                        .unwrap_or(vec![])
-                    {
-                        for youtube_playlist_id in youtube_playlist_id_model {
-                            yt_p_id.push(youtube_playlist_id);
-                        }
-                    }
-                    yt_p_id
-                };
-                new_song.set_youtube_playlists(youtube_playlist_ids);
+                new_song.set_youtube_playlists(
+                    relations
+                        .youtube_playlists
+                        .remove(&s.id)
+                        .unwrap_or_default(),
+                );
             }
 
-            let artists = {
-                let mut a_vec = vec![];
-                for (_sa, mut artists) in song::Entity::find()
-                    .find_with_related(Artist)
-                    .filter(song::Column::Id.eq(s.id))
-                    .all(self.ref_db())
-                    .await
-                    .unwrap_or(vec![])
-                {
-                    artists.sort_by_key(|s1| s1.id);
-                    for artist in artists {
-                        a_vec.push(artist);
-                    }
-                }
-                a_vec
-            };
-            new_song.set_artists(artists);
-
-            let albums = {
-                let mut albums_v = vec![];
-                for (_, albums) in song::Entity::find()
-                    .find_with_related(Album)
-                    .filter(song::Column::Id.eq(s.id))
-                    .all(self.ref_db())
-                    .await
-                    .unwrap_or(vec![])
-                {
-                    for album in albums {
-                        albums_v.push(album);
-                    }
-                }
-                albums_v
-            };
-            new_song.set_albums(albums);
-
-            let genres = {
-                let mut genres_v = vec![];
-                for (_, genres) in song::Entity::find()
-                    .find_with_related(Genre)
-                    .filter(song::Column::Id.eq(s.id))
-                    .all(self.ref_db())
-                    .await
-                    .unwrap_or(vec![])
-                {
-                    for genre in genres {
-                        genres_v.push(genre);
-                    }
-                }
-                genres_v
-            };
-            new_song.set_genres(genres);
+            new_song.set_artists(relations.artists.remove(&s.id).unwrap_or_default());
+            new_song.set_albums(relations.albums.remove(&s.id).unwrap_or_default());
+            new_song.set_genres(relations.genres.remove(&s.id).unwrap_or_default());
 
             new_song.in_database = true;
             vvec.push(new_song);
@@ -926,9 +2111,12 @@ impl DbConnection {
 
     pub async fn check_song_in_database(&self, song: &GSong) -> bool {
         // check if id exists
+        let Ok(db) = self.ref_db() else {
+            return false;
+        };
         if let Some(db_id) = song.id {
             let possible_song: Option<song::Model> = song::Entity::find_by_id(db_id)
-                .one(self.ref_db())
+                .one(db)
                 .await
                 .expect("operation success");
             match possible_song {
@@ -955,60 +2143,135 @@ impl DbConnection {
         }
     }
 
+    /// Finds the entry of a file that was moved or renamed outside of muzik by its content hash.
+    /// Only entries whose recorded file no longer exists are considered
+    pub async fn find_moved_song(&self, song: &GSong) -> Result<Option<SongModel>, DatabaseError> {
+        if let Some(content_hash) = song.content_hash.as_ref() {
+            let candidates = SongEntity::find()
+                .filter(song::Column::ContentHash.eq(content_hash.clone()))
+                .all(self.ref_db()?)
+                .await?;
+            Ok(candidates
+                .into_iter()
+                .find(|candidate| match candidate.path.as_ref() {
+                    Some(path) => !song.music_dir.join(path).exists(),
+                    None => true,
+                }))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub async fn insert_from_gui_song(&self, mut song: GSong) -> Result<GSong, DatabaseError> {
-        let title = song.get_title_string();
-        let youtube_id = song.youtube_id.clone();
-        let thumbnail_url = song.thumbnail_url.clone();
-        let path = song.get_database_path();
-        let song_id = self
-            .insert_song_with_path(title, youtube_id, thumbnail_url, Some(path))
-            .await?;
+        self.transaction(|db| async move {
+            let mut model = song::ActiveModel {
+                id: ActiveValue::NotSet,
+                added_at: ActiveValue::Set(Some(unix_now())),
+                ..song::ActiveModel::from(&song)
+            };
+            set_source(
+                &mut model,
+                song.source,
+                song.get_source_id(),
+                song.get_source_url(),
+            );
+            // ratings read from the tags survive a database rebuild
+            if let Some(play_stats) = song.play_stats.as_ref() {
+                set_play_stats(&mut model, play_stats);
+            }
+            let song_id = SongEntity::insert(model)
+                .exec(db.ref_db()?)
+                .await?
+                .last_insert_id;
 
-        let artists_vec = song.artists.clone().unwrap_or(vec![]);
-        for artist in artists_vec {
-            let artist_id = self.insert_artist(artist.name).await?;
-            self.insert_song_artist(artist_id, song_id).await?;
-        }
+            let artists_vec = song.artists.clone().unwrap_or(vec![]);
+            for artist in artists_vec {
+                let artist_id = db.insert_artist(artist.name).await?;
+                db.insert_song_artist(artist_id, song_id).await?;
+            }
 
-        let albums_vec = song.albums.clone().unwrap_or(vec![]);
-        for album in albums_vec {
-            let album_id = self.insert_album(album.name).await?;
-            self.insert_song_album(album_id, song_id).await?;
-        }
+            let albums_vec = song.albums.clone().unwrap_or(vec![]);
+            for album in albums_vec {
+                let album_id = db
+                    .insert_album_with_artist(album.name, album.album_artist)
+                    .await?;
+                db.insert_song_album(album_id, song_id).await?;
+            }
 
-        let genres_vec = song.genres.clone().unwrap_or(vec![]);
-        for genre in genres_vec {
-            let genre_id = self.insert_genre(genre.genre).await?;
-            self.insert_song_genre(genre_id, song_id).await?;
-        }
+            let genres_vec = song.genres.clone().unwrap_or(vec![]);
+            for genre in genres_vec {
+                let genre_id = db.insert_genre(genre.genre).await?;
+                db.insert_song_genre(genre_id, song_id).await?;
+            }
+
+            // labels written to the tags survive a database rebuild
+            for label in song.get_labels_vec() {
+                db.insert_song_label(song_id, label).await?;
+            }
+
+            for youtube_playlist in song.get_youtube_playlists() {
+                db.add_song_to_youtube_playlist(song_id, youtube_playlist.youtube_playlist_id)
+                    .await?;
+            }
+
+            if let Some(lyrics) = song.lyrics.as_ref() {
+                db.set_lyrics(song_id, lyrics).await?;
+            }
 
-        Ok(song.set_id(song_id))
+            Ok(song.set_id(song_id))
+        })
+        .await
     }
-    pub async fn insert_from_app_song(&self, song: AppSong) -> Result<(), DatabaseError> {
-        let title = song.get_title_string();
-        let youtube_id = song.yt_id;
-        let thumbnail_url = song.tb_url;
-        let song_id = self.insert_song(title, youtube_id, thumbnail_url).await?;
+    pub async fn insert_from_app_song(&self, song: AppSong) -> Result<AppSong, DatabaseError> {
+        self.transaction(|db| async move {
+            let mut model = song::ActiveModel {
+                id: ActiveValue::NotSet,
+                added_at: ActiveValue::Set(Some(unix_now())),
+                ..song::ActiveModel::from(&song)
+            };
+            set_source(
+                &mut model,
+                song.source,
+                song.get_source_id(),
+                song.get_source_url(),
+            );
+            if let Some(play_stats) = song.play_stats.as_ref() {
+                set_play_stats(&mut model, play_stats);
+            }
+            let song_id = SongEntity::insert(model)
+                .exec(db.ref_db()?)
+                .await?
+                .last_insert_id;
 
-        let artists_vec = song.artist.clone().unwrap_or(vec![]);
-        for artist in artists_vec {
-            let artist_id = self.insert_artist(artist.name).await?;
-            self.insert_song_artist(artist_id, song_id).await?;
-        }
+            let artists_vec = song.artist.clone().unwrap_or(vec![]);
+            for artist in artists_vec {
+                let artist_id = db.insert_artist(artist.name).await?;
+                db.insert_song_artist(artist_id, song_id).await?;
+            }
 
-        let albums_vec = song.album.clone().unwrap_or(vec![]);
-        for album in albums_vec {
-            let album_id = self.insert_album(album.name).await?;
-            self.insert_song_album(album_id, song_id).await?;
-        }
+            let albums_vec = song.album.clone().unwrap_or(vec![]);
+            for album in albums_vec {
+                let album_id = db
+                    .insert_album_with_artist(album.name, album.album_artist)
+                    .await?;
+                db.insert_song_album(album_id, song_id).await?;
+            }
 
-        let genres_vec = song.genre.clone().unwrap_or(vec![]);
-        for genre in genres_vec {
-            let genre_id = self.insert_genre(genre.genre).await?;
-            self.insert_song_genre(genre_id, song_id).await?;
-        }
+            let genres_vec = song.genre.clone().unwrap_or(vec![]);
+            for genre in genres_vec {
+                let genre_id = db.insert_genre(genre.genre).await?;
+                db.insert_song_genre(genre_id, song_id).await?;
+            }
 
-        Ok(())
+            let youtube_playlists = song.yt_playlist.clone().unwrap_or(vec![]);
+            for youtube_playlist in youtube_playlists {
+                db.add_song_to_youtube_playlist(song_id, youtube_playlist.youtube_playlist_id)
+                    .await?;
+            }
+
+            Ok(song.with_id(Some(song_id)))
+        })
+        .await
     }
 
     #[tracing::instrument(skip(self))]
@@ -1026,71 +2289,220 @@ impl DbConnection {
             youtube_id: ActiveValue::Set(youtube_id),
             thumbnail_url: ActiveValue::Set(thumbnail_url),
             path: ActiveValue::Set(path),
+            ..Default::default()
         };
 
-        Ok(SongEntity::update(model).exec(self.ref_db()).await?.id)
+        Ok(SongEntity::update(model).exec(self.ref_db()?).await?.id)
+    }
+
+    /// Records where a song was downloaded from, for songs that came from elsewhere
+    /// than their youtube id says
+    pub async fn set_song_source(
+        &self,
+        song_id: i32,
+        source: Source,
+        source_id: Option<String>,
+        source_url: Option<String>,
+    ) -> Result<(), DatabaseError> {
+        let mut model = song::ActiveModel {
+            id: ActiveValue::Set(song_id),
+            ..Default::default()
+        };
+        set_source(&mut model, source, source_id, source_url);
+        SongEntity::update(model).exec(self.ref_db()?).await?;
+        Ok(())
     }
 
     pub async fn update_song_from_gui_song(&self, song: GSong) -> Result<i32, DatabaseError> {
         let model = song::ActiveModel {
             id: ActiveValue::Set(song.id.expect("exists")),
-            title: ActiveValue::Set(song.get_title_string()),
-            youtube_id: ActiveValue::Set(song.youtube_id.clone()),
-            thumbnail_url: ActiveValue::Set(song.thumbnail_url.clone()),
+            ..song::ActiveModel::from(&song)
+        };
+
+        Ok(SongEntity::update(model).exec(self.ref_db()?).await?.id)
+    }
+
+    /// Stores what was read from the song's file: its location, content hash and audio properties
+    pub async fn update_file_info(&self, song: &GSong) -> Result<(), DatabaseError> {
+        let mut model = song::ActiveModel {
+            id: ActiveValue::Set(song.id.ok_or(DatabaseError::NoSongId)?),
             path: ActiveValue::Set(Some(song.get_database_path())),
+            content_hash: ActiveValue::Set(song.content_hash.clone()),
+            ..Default::default()
         };
+        if let Some(properties) = song.audio_properties.as_ref() {
+            set_audio_properties(&mut model, properties);
+        }
+        set_hashed_stamp(&mut model, song.content_hash_stamp);
+        SongEntity::update(model).exec(self.ref_db()?).await?;
+        Ok(())
+    }
 
-        Ok(SongEntity::update(model).exec(self.ref_db()).await?.id)
+    /// The stored content hash of the song at `path`, if the file still has the size and
+    /// modification time it had when it was hashed
+    pub async fn get_unchanged_content_hash(
+        &self,
+        path: &str,
+        stamp: FileStamp,
+    ) -> Result<Option<String>, DatabaseError> {
+        Ok(SongEntity::find()
+            .filter(song::Column::Path.eq(path))
+            .filter(song::Column::HashedSize.eq(stamp.size))
+            .filter(song::Column::HashedModified.eq(stamp.modified_ms))
+            .one(self.ref_db()?)
+            .await?
+            .and_then(|song| song.content_hash))
     }
 
-    pub async fn update_all_from_app_song(&self, song: AppSong) -> Result<(), DatabaseError> {
-        self.update_song(
-            song.id.unwrap(),
-            song.get_title_string(),
-            song.yt_id.clone(),
-            song.tb_url.clone(),
-            // TODO: update path from TUI
-            None,
-        )
-        .await?;
-        // TODO: update artists, albums, etc
+    /// Stores the audio properties of a song after its file was tagged
+    pub async fn update_audio_properties(
+        &self,
+        song_id: i32,
+        properties: &AudioProperties,
+    ) -> Result<(), DatabaseError> {
+        let mut model = song::ActiveModel {
+            id: ActiveValue::Set(song_id),
+            ..Default::default()
+        };
+        set_audio_properties(&mut model, properties);
+        SongEntity::update(model).exec(self.ref_db()?).await?;
         Ok(())
     }
 
-    pub async fn update_all_from_gui_song(&self, song: GSong) -> Result<(), DatabaseError> {
-        if let Some(previous_model) =
-            SongEntity::find_by_id(song.id.ok_or(DatabaseError::NoSongId)?)
-                .one(self.ref_db())
-                .await?
-        {
-            self.update_song(
-                song.id.ok_or(DatabaseError::NoSongId)?,
-                song.get_title_string(),
-                song.youtube_id.clone(),
-                song.thumbnail_url.clone(),
-                Some(song.get_database_path()),
-            )
-            .await?;
+    /// Sets the 0 to 5 star rating of a song, `None` clears it. The rating tags are
+    /// rewritten too.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_rating(
+        &self,
+        song_id: i32,
+        rating: Option<i32>,
+        music_dir: PathBuf,
+    ) -> Result<(), DatabaseError> {
+        if let Some(rating) = rating.filter(|rating| !(0..=5).contains(rating)) {
+            return Err(DatabaseError::InvalidRating(rating));
+        }
+        let model = song::ActiveModel {
+            id: ActiveValue::Set(song_id),
+            rating: ActiveValue::Set(rating),
+            ..Default::default()
+        };
+        SongEntity::update(model).exec(self.ref_db()?).await?;
+        self.write_song_play_stats(song_id, music_dir).await
+    }
 
-            // call functions to update
-            let new_artists = song.artists.unwrap_or_default();
-            self.update_song_artists_links(previous_model.clone(), new_artists)
-                .await?;
-            let new_albums = song.albums.unwrap_or_default();
-            self.update_song_albums_links(previous_model.clone(), new_albums)
-                .await?;
-            let new_genres = song.genres.unwrap_or_default();
-            self.update_song_genres_links(previous_model.clone(), new_genres)
-                .await?;
+    /// Marks or unmarks a song as favourite. The favourite tag is rewritten too.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_favourite(
+        &self,
+        song_id: i32,
+        favourite: bool,
+        music_dir: PathBuf,
+    ) -> Result<(), DatabaseError> {
+        let model = song::ActiveModel {
+            id: ActiveValue::Set(song_id),
+            favourite: ActiveValue::Set(favourite),
+            ..Default::default()
+        };
+        SongEntity::update(model).exec(self.ref_db()?).await?;
+        self.write_song_play_stats(song_id, music_dir).await
+    }
 
-            // TODO: update youtube_playlists
-        } else {
+    /// Counts one play of the song and sets its last played time to now.
+    /// The play count tag is rewritten too.
+    #[tracing::instrument(skip(self))]
+    pub async fn record_play(&self, song_id: i32, music_dir: PathBuf) -> Result<(), DatabaseError> {
+        let rows = SongEntity::update_many()
+            .col_expr(
+                song::Column::PlayCount,
+                Expr::col(song::Column::PlayCount).add(1),
+            )
+            .col_expr(song::Column::LastPlayed, Expr::value(unix_now()))
+            .filter(song::Column::Id.eq(song_id))
+            .exec(self.ref_db()?)
+            .await?
+            .rows_affected;
+        if rows == 0 {
             return Err(DatabaseError::NoSongFound);
         }
+        self.write_song_play_stats(song_id, music_dir).await
+    }
 
-        // update song
+    /// Favourite songs, highest rated first
+    pub async fn get_favourite_songs(&self) -> Result<Vec<SongModel>, DatabaseError> {
+        Ok(SongEntity::find()
+            .filter(song::Column::Favourite.eq(true))
+            .order_by_desc(song::Column::Rating)
+            .order_by_asc(song::Column::Title)
+            .all(self.ref_db()?)
+            .await?)
+    }
 
-        Ok(())
+    pub async fn update_all_from_app_song(
+        &self,
+        song: AppSong,
+        origin: ChangeOrigin,
+    ) -> Result<(), DatabaseError> {
+        let id = song.id.ok_or(DatabaseError::NoSongId)?;
+        self.transaction(|db| async move {
+            let before = db.snapshot_song(id).await?;
+            let model = song::ActiveModel {
+                id: ActiveValue::Set(id),
+                // TODO: update path from TUI
+                ..song::ActiveModel::from(&song)
+            };
+            let updated = SongEntity::update(model).exec(db.ref_db()?).await?;
+            // TODO: update artists, albums, etc
+            db.update_song_youtube_playlists_links(
+                updated,
+                song.yt_playlist.clone().unwrap_or_default(),
+            )
+            .await?;
+            db.record_song_changes(before, origin).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn update_all_from_gui_song(
+        &self,
+        song: GSong,
+        origin: ChangeOrigin,
+    ) -> Result<(), DatabaseError> {
+        self.transaction(|db| async move {
+            if let Some(previous_model) =
+                SongEntity::find_by_id(song.id.ok_or(DatabaseError::NoSongId)?)
+                    .one(db.ref_db()?)
+                    .await?
+            {
+                let before = db.snapshot_song(previous_model.id).await?;
+                SongEntity::update(song::ActiveModel::from(&song))
+                    .exec(db.ref_db()?)
+                    .await?;
+
+                // call functions to update
+                let new_youtube_playlists = song.get_youtube_playlists();
+                let new_artists = song.artists.unwrap_or_default();
+                db.update_song_artists_links(previous_model.clone(), new_artists)
+                    .await?;
+                let new_albums = song.albums.unwrap_or_default();
+                db.update_song_albums_links(previous_model.clone(), new_albums)
+                    .await?;
+                let new_genres = song.genres.unwrap_or_default();
+                db.update_song_genres_links(previous_model.clone(), new_genres)
+                    .await?;
+                db.update_song_youtube_playlists_links(previous_model, new_youtube_playlists)
+                    .await?;
+
+                db.record_song_changes(before, origin).await?;
+            } else {
+                return Err(DatabaseError::NoSongFound);
+            }
+
+            // update song
+
+            Ok(())
+        })
+        .await
     }
 
     /// update relation of a song's artists by deleting all relations and adding new ones
@@ -1100,26 +2512,31 @@ impl DbConnection {
         song: SongModel,
         new_artists: Vec<ArtistModel>,
     ) -> Result<(), DatabaseError> {
-        let rows = SongArtistJunction::delete_many()
-            .filter(
-                Condition::all().add(Expr::col(song_artist_junction::Column::SongId).eq(song.id)),
-            )
-            .exec(self.ref_db())
-            .await?
-            .rows_affected;
-        trace!("rows affected: {rows}");
-
-        for (i, new_artist) in new_artists.iter().enumerate() {
-            // create new from name or use existing
-            let artist = self.insert_artist(new_artist.name.clone()).await?;
-            let insert_id = self.insert_song_artist(artist, song.id).await?;
-
-            info!(
-                "Inserted artist {} [{}], number {}",
-                new_artist.name, insert_id, i
-            );
-        }
-        Ok(())
+        self.transaction(|db| async move {
+            let rows = SongArtistJunction::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(Expr::col(song_artist_junction::Column::SongId).eq(song.id)),
+                )
+                .exec(db.ref_db()?)
+                .await?
+                .rows_affected;
+            trace!("rows affected: {rows}");
+
+            for (i, new_artist) in new_artists.iter().enumerate() {
+                // create new from name or use existing
+                let artist = db.insert_artist(new_artist.name.clone()).await?;
+                let insert_id = db.insert_song_artist(artist, song.id).await?;
+
+                info!(
+                    "Inserted artist {} [{}], number {}",
+                    new_artist.name, insert_id, i
+                );
+            }
+            db.maybe_clean_orphans().await?;
+            Ok(())
+        })
+        .await
     }
 
     /// update relation of a song's albums by deleting all relations and adding new ones
@@ -1129,33 +2546,43 @@ impl DbConnection {
         song: SongModel,
         new_albums: Vec<AlbumModel>,
     ) -> Result<(), DatabaseError> {
-        let rows = SongAlbumJunction::delete_many()
-            .filter(
-                Condition::all().add(Expr::col(song_album_junction::Column::SongId).eq(song.id)),
-            )
-            .exec(self.ref_db())
-            .await?
-            .rows_affected;
-        trace!("rows affected: {rows}");
-
-        for (i, new_album) in new_albums.iter().enumerate() {
-            // create new from name or use existing
-            let album = self.insert_album(new_album.name.clone()).await?;
-            let model = song_album_junction::ActiveModel {
-                song_id: ActiveValue::Set(song.id),
-                album_id: ActiveValue::Set(album),
-                ..Default::default()
-            };
-            let insert_id = SongAlbumJunction::insert(model)
-                .exec(self.ref_db())
+        self.transaction(|db| async move {
+            let rows = SongAlbumJunction::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(Expr::col(song_album_junction::Column::SongId).eq(song.id)),
+                )
+                .exec(db.ref_db()?)
                 .await?
-                .last_insert_id;
-            info!(
-                "Inserted album {} [{}], number {}",
-                new_album.name, insert_id, i
-            );
-        }
-        Ok(())
+                .rows_affected;
+            trace!("rows affected: {rows}");
+
+            for (i, new_album) in new_albums.iter().enumerate() {
+                // create new from name or use existing
+                let album = db
+                    .insert_album_with_artist(
+                        new_album.name.clone(),
+                        new_album.album_artist.clone(),
+                    )
+                    .await?;
+                let model = song_album_junction::ActiveModel {
+                    song_id: ActiveValue::Set(song.id),
+                    album_id: ActiveValue::Set(album),
+                    ..Default::default()
+                };
+                let insert_id = SongAlbumJunction::insert(model)
+                    .exec(db.ref_db()?)
+                    .await?
+                    .last_insert_id;
+                info!(
+                    "Inserted album {} [{}], number {}",
+                    new_album.name, insert_id, i
+                );
+            }
+            db.maybe_clean_orphans().await?;
+            Ok(())
+        })
+        .await
     }
 
     /// update relation of a song's genres by deleting all relations and adding new ones
@@ -1165,217 +2592,3027 @@ impl DbConnection {
         song: SongModel,
         new_genres: Vec<GenreModel>,
     ) -> Result<(), DatabaseError> {
-        let rows = SongGenreJunction::delete_many()
-            .filter(
-                Condition::all().add(Expr::col(song_genre_junction::Column::SongId).eq(song.id)),
-            )
-            .exec(self.ref_db())
-            .await?
-            .rows_affected;
-        trace!("rows affected: {rows}");
-
-        for (i, new_genres) in new_genres.iter().enumerate() {
-            // create new from name or use existing
-            let genre = self.insert_genre(new_genres.genre.clone()).await?;
-            let model = song_genre_junction::ActiveModel {
-                song_id: ActiveValue::Set(song.id),
-                genre_id: ActiveValue::Set(genre),
-                ..Default::default()
-            };
-            let insert_id = SongGenreJunction::insert(model)
-                .exec(self.ref_db())
+        self.transaction(|db| async move {
+            let rows = SongGenreJunction::delete_many()
+                .filter(
+                    Condition::all()
+                        .add(Expr::col(song_genre_junction::Column::SongId).eq(song.id)),
+                )
+                .exec(db.ref_db()?)
                 .await?
-                .last_insert_id;
-            info!(
-                "Inserted genre {} [{}], number {}",
-                new_genres.genre, insert_id, i
-            );
-        }
-        Ok(())
+                .rows_affected;
+            trace!("rows affected: {rows}");
+
+            for (i, new_genres) in new_genres.iter().enumerate() {
+                // create new from name or use existing
+                let genre = db.insert_genre(new_genres.genre.clone()).await?;
+                let model = song_genre_junction::ActiveModel {
+                    song_id: ActiveValue::Set(song.id),
+                    genre_id: ActiveValue::Set(genre),
+                    ..Default::default()
+                };
+                let insert_id = SongGenreJunction::insert(model)
+                    .exec(db.ref_db()?)
+                    .await?
+                    .last_insert_id;
+                info!(
+                    "Inserted genre {} [{}], number {}",
+                    new_genres.genre, insert_id, i
+                );
+            }
+            db.maybe_clean_orphans().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// update relation of a song's youtube playlists, playlists the song stays in keep
+    /// its position and date added
+    #[tracing::instrument(skip_all, fields(id = song.id))]
+    async fn update_song_youtube_playlists_links(
+        &self,
+        song: SongModel,
+        new_youtube_playlists: Vec<YoutubePlaylistIdModel>,
+    ) -> Result<(), DatabaseError> {
+        self.transaction(|db| async move {
+            let current = db.get_song_youtube_playlists(song.id).await?;
+            for (entry, youtube_playlist) in &current {
+                if !new_youtube_playlists
+                    .iter()
+                    .any(|new| new.youtube_playlist_id == youtube_playlist.youtube_playlist_id)
+                {
+                    db.remove_song_from_youtube_playlist(song.id, entry.youtube_playlist_id_id)
+                        .await?;
+                    info!(
+                        "Removed from youtube playlist {}",
+                        youtube_playlist.youtube_playlist_id
+                    );
+                }
+            }
+
+            for new_youtube_playlist in new_youtube_playlists {
+                let insert_id = db
+                    .add_song_to_youtube_playlist(
+                        song.id,
+                        new_youtube_playlist.youtube_playlist_id.clone(),
+                    )
+                    .await?;
+                trace!(
+                    "In youtube playlist {} [{}]",
+                    new_youtube_playlist.youtube_playlist_id,
+                    insert_id
+                );
+            }
+            db.maybe_clean_orphans().await?;
+            Ok(())
+        })
+        .await
     }
 
     pub async fn delete_song_from_app_song(&self, song: AppSong) -> Result<u64, DatabaseError> {
-        if let Some(song_id) = song.id {
-            // delete all foreign keys
-            SongArtistJunction::delete_many()
-                .filter(song_artist_junction::Column::SongId.eq(song_id))
-                .exec(self.ref_db())
+        self.transaction(|db| async move {
+            if let Some(song_id) = song.id {
+                // every row of the song goes with it, youtube playlists are left one by
+                // one first so the positions after the song close up
+                for (entry, _) in db.get_song_youtube_playlists(song_id).await? {
+                    db.remove_song_from_youtube_playlist(song_id, entry.youtube_playlist_id_id)
+                        .await?;
+                }
+                let rows = SongEntity::delete_by_id(song_id)
+                    .exec(db.ref_db()?)
+                    .await?
+                    .rows_affected;
+                db.maybe_clean_orphans().await?;
+                Ok(rows)
+            } else {
+                Err(DatabaseError::NoSongId)
+            }
+        })
+        .await
+    }
+
+    /// Removes artists, albums, genres and youtube playlist ids that no song links to
+    /// anymore, returning what was removed. With `dry_run` nothing is deleted and the
+    /// report holds what would be removed.
+    #[tracing::instrument(skip(self))]
+    pub async fn clean_orphans(&self, dry_run: bool) -> Result<OrphanReport, DatabaseError> {
+        self.transaction(|db| async move {
+            let report = OrphanReport {
+                artists: Artist::find()
+                    .filter(
+                        artist::Column::Id.not_in_subquery(
+                            Query::select()
+                                .column(song_artist_junction::Column::ArtistId)
+                                .from(SongArtistJunction)
+                                .to_owned(),
+                        ),
+                    )
+                    .all(db.ref_db()?)
+                    .await?,
+                albums: Album::find()
+                    .filter(
+                        album::Column::Id.not_in_subquery(
+                            Query::select()
+                                .column(song_album_junction::Column::AlbumId)
+                                .from(SongAlbumJunction)
+                                .to_owned(),
+                        ),
+                    )
+                    .all(db.ref_db()?)
+                    .await?,
+                genres: Genre::find()
+                    .filter(
+                        genre::Column::Id.not_in_subquery(
+                            Query::select()
+                                .column(song_genre_junction::Column::GenreId)
+                                .from(SongGenreJunction)
+                                .to_owned(),
+                        ),
+                    )
+                    .all(db.ref_db()?)
+                    .await?,
+                youtube_playlist_ids: YoutubePlaylistId::find()
+                    .filter(
+                        youtube_playlist_id::Column::Id.not_in_subquery(
+                            Query::select()
+                                .column(
+                                    song_youtube_playlist_id_junction::Column::YoutubePlaylistIdId,
+                                )
+                                .from(SongYoutubePlaylistIdJunction)
+                                .to_owned(),
+                        ),
+                    )
+                    .all(db.ref_db()?)
+                    .await?,
+            };
+            if dry_run || report.is_empty() {
+                return Ok(report);
+            }
+
+            // aliases and covers cascade
+            Artist::delete_many()
+                .filter(artist::Column::Id.is_in(report.artists.iter().map(|a| a.id)))
+                .exec(db.ref_db()?)
                 .await?;
-            SongAlbumJunction::delete_many()
-                .filter(song_album_junction::Column::SongId.eq(song_id))
-                .exec(self.ref_db())
+            Album::delete_many()
+                .filter(album::Column::Id.is_in(report.albums.iter().map(|a| a.id)))
+                .exec(db.ref_db()?)
                 .await?;
-            SongGenreJunction::delete_many()
-                .filter(song_genre_junction::Column::SongId.eq(song_id))
-                .exec(self.ref_db())
+
+            Genre::delete_many()
+                .filter(genre::Column::Id.is_in(report.genres.iter().map(|g| g.id)))
+                .exec(db.ref_db()?)
                 .await?;
-            Ok(SongEntity::delete_by_id(song_id)
-                .exec(self.ref_db())
-                .await?
-                .rows_affected)
-        } else {
-            Err(DatabaseError::NoSongId)
+            YoutubePlaylistId::delete_many()
+                .filter(
+                    youtube_playlist_id::Column::Id
+                        .is_in(report.youtube_playlist_ids.iter().map(|y| y.id)),
+                )
+                .exec(db.ref_db()?)
+                .await?;
+
+            info!("removed orphaned entries:\n{}", report.summary());
+            Ok(report)
+        })
+        .await
+    }
+
+    /// Runs `clean_orphans` if `auto_clean_orphans` is set
+    async fn maybe_clean_orphans(&self) -> Result<(), DatabaseError> {
+        if self.auto_clean_orphans {
+            self.clean_orphans(false).await?;
         }
+        Ok(())
     }
 
-    pub async fn delete_song_artist(&self, artist_id: i32) -> Result<(), DatabaseError> {
-        let model = SongArtistJunction::find()
+    /// Unlinks the artist from the song, the artist itself is kept
+    pub async fn remove_song_artist(
+        &self,
+        song_id: i32,
+        artist_id: i32,
+    ) -> Result<(), DatabaseError> {
+        let result = SongArtistJunction::delete_many()
+            .filter(song_artist_junction::Column::SongId.eq(song_id))
             .filter(song_artist_junction::Column::ArtistId.eq(artist_id))
-            .one(self.ref_db())
-            .await?
-            .expect("One SongArtistJunction model is given");
-        model.delete(self.ref_db()).await?;
+            .exec(self.ref_db()?)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DatabaseError::SongArtistNotFound(song_id, artist_id));
+        }
         Ok(())
     }
 
-    pub async fn delete_song_album(&self, album_id: i32) -> Result<(), DatabaseError> {
-        let model = SongAlbumJunction::find()
+    /// Unlinks the album from the song, the album itself is kept
+    pub async fn remove_song_album(
+        &self,
+        song_id: i32,
+        album_id: i32,
+    ) -> Result<(), DatabaseError> {
+        let result = SongAlbumJunction::delete_many()
+            .filter(song_album_junction::Column::SongId.eq(song_id))
             .filter(song_album_junction::Column::AlbumId.eq(album_id))
-            .one(self.ref_db())
-            .await?
-            .expect("One SongAlbumJunction model is given");
-        model.delete(self.ref_db()).await?;
+            .exec(self.ref_db()?)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DatabaseError::SongAlbumNotFound(song_id, album_id));
+        }
         Ok(())
     }
 
-    pub async fn in_memory_test() -> Result<(), DatabaseError> {
-        let mut opt = ConnectOptions::new("sqlite::memory:".to_owned());
-        opt.sqlx_logging(true)
-            .sqlx_logging_level(tracing::log::LevelFilter::Debug);
-        let db = sea_orm::Database::connect(opt).await?;
-        info!("spawned new in memory sqlite database");
-
-        let strct = Self {
-            path: None,
-            db: Some(db),
-        };
-        strct.test_db().await?;
-        Ok(())
+    /// Albums of the song, in the order of its tags
+    pub async fn get_song_albums(&self, song_id: i32) -> Result<Vec<AlbumModel>, DatabaseError> {
+        Ok(Album::find()
+            .inner_join(SongAlbumJunction)
+            .filter(song_album_junction::Column::SongId.eq(song_id))
+            .order_by_asc(song_album_junction::Column::Key)
+            .all(self.ref_db()?)
+            .await?)
     }
 
-    pub async fn test_db(&self) -> Result<(), DatabaseError> {
-        // ensure database is up to date
-        crate::migrator::Migrator::refresh(self.ref_db()).await?;
-
-        for artist in [
-            "Hoshimashi Suisei",
-            "Hoshimashi Suisei",
-            "Comet-chan",
-            "Sui-chan",
-            "Minato Aqua",
-        ] {
-            self.insert_artist(artist.to_string()).await?;
+    /// Labels the song, creating the label if it is new. With a music dir the labels are
+    /// also written to the `MUZIK_LABELS` tag of the file.
+    #[tracing::instrument(skip(self))]
+    pub async fn add_song_label(
+        &self,
+        song_id: i32,
+        label: String,
+        music_dir: Option<PathBuf>,
+    ) -> Result<(), DatabaseError> {
+        // a new label is only kept once it is linked
+        self.transaction(|db| async move {
+            SongEntity::find_by_id(song_id)
+                .one(db.ref_db()?)
+                .await?
+                .ok_or(DatabaseError::NoSongFound)?;
+            db.insert_song_label(song_id, label).await
+        })
+        .await?;
+        match music_dir {
+            Some(music_dir) => self.write_song_labels(song_id, music_dir).await,
+            None => Ok(()),
         }
+    }
 
-        for album in ["Still Still Stellar", "AYAYA", "Minato Aqua Originals"] {
-            self.insert_album(album.to_owned()).await?;
+    /// Removes the label from the song, the label itself is kept. With a music dir the
+    /// `MUZIK_LABELS` tag of the file is rewritten too.
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_song_label(
+        &self,
+        song_id: i32,
+        label: &str,
+        music_dir: Option<PathBuf>,
+    ) -> Result<(), DatabaseError> {
+        let result = SongLabelJunction::delete_many()
+            .filter(song_label_junction::Column::SongId.eq(song_id))
+            .filter(
+                song_label_junction::Column::LabelId.in_subquery(
+                    Query::select()
+                        .column(label::Column::Id)
+                        .from(Label)
+                        .and_where(label::Column::Name.eq(label.trim()))
+                        .to_owned(),
+                ),
+            )
+            .exec(self.ref_db()?)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DatabaseError::SongLabelNotFound(song_id, label.to_string()));
         }
-
-        for genre in ["Vtuber", "Jpop"] {
-            self.insert_genre(genre.to_owned()).await?;
+        match music_dir {
+            Some(music_dir) => self.write_song_labels(song_id, music_dir).await,
+            None => Ok(()),
         }
+    }
 
-        let youtube_playlist_id_res = self
+    /// Every label, by name
+    pub async fn get_labels(&self) -> Result<Vec<LabelModel>, DatabaseError> {
+        Ok(Label::find()
+            .order_by_asc(label::Column::Name)
+            .all(self.ref_db()?)
+            .await?)
+    }
+
+    /// Labels of the song, by name
+    pub async fn get_song_labels(&self, song_id: i32) -> Result<Vec<LabelModel>, DatabaseError> {
+        Ok(Label::find()
+            .inner_join(SongLabelJunction)
+            .filter(song_label_junction::Column::SongId.eq(song_id))
+            .order_by_asc(label::Column::Name)
+            .all(self.ref_db()?)
+            .await?)
+    }
+
+    /// Songs with the label, by title
+    pub async fn get_label_songs(
+        &self,
+        label: &str,
+        music_dir: PathBuf,
+    ) -> Result<Vec<GSong>, DatabaseError> {
+        let query = SongQuery::new()
+            .with_label(label)
+            .with_sort(SongSort::Title, false);
+        self.query_songs_gui(&query, music_dir).await
+    }
+
+    /// Marks `song_id` as a `kind` of `related_song_id`, e.g. a remix of it. An earlier
+    /// link between the two songs, in either direction, is replaced.
+    #[tracing::instrument(skip(self))]
+    pub async fn link_songs(
+        &self,
+        song_id: i32,
+        related_song_id: i32,
+        kind: RelationshipKind,
+    ) -> Result<(), DatabaseError> {
+        if song_id == related_song_id {
+            return Err(DatabaseError::SelfRelationship(song_id));
+        }
+        self.transaction(|db| async move {
+            for id in [song_id, related_song_id] {
+                SongEntity::find_by_id(id)
+                    .one(db.ref_db()?)
+                    .await?
+                    .ok_or(DatabaseError::NoSongFound)?;
+            }
+            db.delete_song_relationship(song_id, related_song_id)
+                .await?;
+            SongRelationship::insert(song_relationship::ActiveModel {
+                song_id: ActiveValue::Set(song_id),
+                related_song_id: ActiveValue::Set(related_song_id),
+                kind: ActiveValue::Set(kind),
+                ..Default::default()
+            })
+            .exec(db.ref_db()?)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes the link between the two songs, in whichever direction it was made
+    #[tracing::instrument(skip(self))]
+    pub async fn unlink_songs(
+        &self,
+        song_id: i32,
+        other_song_id: i32,
+    ) -> Result<(), DatabaseError> {
+        if self
+            .delete_song_relationship(song_id, other_song_id)
+            .await?
+            == 0
+        {
+            return Err(DatabaseError::SongRelationshipNotFound(
+                song_id,
+                other_song_id,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Deletes the links between the two songs, returning how many there were
+    async fn delete_song_relationship(
+        &self,
+        song_id: i32,
+        other_song_id: i32,
+    ) -> Result<u64, DatabaseError> {
+        let pair = |from: i32, to: i32| {
+            Condition::all()
+                .add(song_relationship::Column::SongId.eq(from))
+                .add(song_relationship::Column::RelatedSongId.eq(to))
+        };
+        Ok(SongRelationship::delete_many()
+            .filter(
+                Condition::any()
+                    .add(pair(song_id, other_song_id))
+                    .add(pair(other_song_id, song_id)),
+            )
+            .exec(self.ref_db()?)
+            .await?
+            .rows_affected)
+    }
+
+    /// Songs linked directly to the song, by title
+    pub async fn get_song_relationships(
+        &self,
+        song_id: i32,
+    ) -> Result<Vec<RelatedSong>, DatabaseError> {
+        let links = SongRelationship::find()
+            .filter(
+                Condition::any()
+                    .add(song_relationship::Column::SongId.eq(song_id))
+                    .add(song_relationship::Column::RelatedSongId.eq(song_id)),
+            )
+            .all(self.ref_db()?)
+            .await?;
+        let other_id = |link: &SongRelationshipModel| {
+            if link.song_id == song_id {
+                link.related_song_id
+            } else {
+                link.song_id
+            }
+        };
+        let titles = SongEntity::find()
+            .filter(song::Column::Id.is_in(links.iter().map(other_id)))
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .map(|s| (s.id, s.title))
+            .collect::<HashMap<_, _>>();
+
+        let mut related = links
+            .iter()
+            .map(|link| RelatedSong {
+                song_id: other_id(link),
+                title: titles.get(&other_id(link)).cloned().unwrap_or_default(),
+                kind: link.kind,
+                original: link.song_id == song_id,
+            })
+            .collect::<Vec<_>>();
+        related.sort_by(|a, b| a.title.cmp(&b.title));
+        Ok(related)
+    }
+
+    /// Every version of the song: the songs linked to it, the songs linked to those and so
+    /// on, including the song itself. By title.
+    pub async fn get_song_versions(
+        &self,
+        song_id: i32,
+        music_dir: PathBuf,
+    ) -> Result<Vec<GSong>, DatabaseError> {
+        let songs = self.find_song_versions(song_id).await?;
+        self.load_gui_songs(songs, &music_dir).await
+    }
+
+    /// The other versions of the song for the song detail, each described by its link to
+    /// the song, or as `version: <title>` if it is only linked through another version
+    pub async fn describe_song_versions(
+        &self,
+        song_id: i32,
+    ) -> Result<Vec<(i32, String)>, DatabaseError> {
+        let related = self.get_song_relationships(song_id).await?;
+        let mut versions = related
+            .iter()
+            .map(|related| (related.song_id, related.describe()))
+            .collect::<Vec<_>>();
+        for version in self.find_song_versions(song_id).await? {
+            if version.id != song_id && !related.iter().any(|r| r.song_id == version.id) {
+                versions.push((version.id, format!("version: {}", version.title)));
+            }
+        }
+        Ok(versions)
+    }
+
+    async fn find_song_versions(&self, song_id: i32) -> Result<Vec<SongModel>, DatabaseError> {
+        // UNION drops the songs that were already visited, so cycles end
+        Ok(SongEntity::find()
+            .from_raw_sql(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "WITH RECURSIVE versions(id) AS (SELECT $1 \
+                UNION SELECT CASE WHEN link.song_id = versions.id \
+                THEN link.related_song_id ELSE link.song_id END \
+                FROM song_relationship link \
+                JOIN versions ON versions.id IN (link.song_id, link.related_song_id)) \
+                SELECT song.* FROM song JOIN versions ON song.id = versions.id \
+                ORDER BY song.title",
+                [song_id.into()],
+            ))
+            .all(self.ref_db()?)
+            .await?)
+    }
+
+    /// Merges the `duplicates` into the `canonical` artist.
+    ///
+    /// Songs of the duplicates are linked to the canonical artist, the duplicate names are
+    /// kept as aliases so `insert_artist` resolves them in the future, and the tags of the
+    /// affected files are rewritten. The merge is recorded as one change set, undoing it
+    /// brings the duplicates back. Returns the ids of the affected songs.
+    #[tracing::instrument(skip(self))]
+    pub async fn merge_artists(
+        &self,
+        canonical: i32,
+        duplicates: Vec<i32>,
+        origin: ChangeOrigin,
+        music_dir: PathBuf,
+    ) -> Result<Vec<i32>, DatabaseError> {
+        let song_ids = self
+            .transaction(|db| async move {
+                Artist::find_by_id(canonical)
+                    .one(db.ref_db()?)
+                    .await?
+                    .ok_or(DatabaseError::NoArtistFound)?;
+                let duplicates = duplicates
+                    .into_iter()
+                    .filter(|id| *id != canonical)
+                    .collect::<Vec<_>>();
+
+                let mut song_ids = vec![];
+                for link in SongArtistJunction::find()
+                    .filter(song_artist_junction::Column::ArtistId.is_in(duplicates.clone()))
+                    .order_by_asc(song_artist_junction::Column::SongId)
+                    .all(db.ref_db()?)
+                    .await?
+                {
+                    if !song_ids.contains(&link.song_id) {
+                        song_ids.push(link.song_id);
+                    }
+                }
+                let mut befores = vec![];
+                for song_id in &song_ids {
+                    befores.push(db.snapshot_song(*song_id).await?);
+                }
+
+                for duplicate_id in duplicates {
+                    let duplicate = Artist::find_by_id(duplicate_id)
+                        .one(db.ref_db()?)
+                        .await?
+                        .ok_or(DatabaseError::NoArtistFound)?;
+
+                    for link in SongArtistJunction::find()
+                        .filter(song_artist_junction::Column::ArtistId.eq(duplicate_id))
+                        .all(db.ref_db()?)
+                        .await?
+                    {
+                        let already_linked = SongArtistJunction::find()
+                            .filter(song_artist_junction::Column::SongId.eq(link.song_id))
+                            .filter(song_artist_junction::Column::ArtistId.eq(canonical))
+                            .one(db.ref_db()?)
+                            .await?
+                            .is_some();
+                        if already_linked {
+                            SongArtistJunction::delete_by_id(link.key)
+                                .exec(db.ref_db()?)
+                                .await?;
+                        } else {
+                            let mut link: song_artist_junction::ActiveModel = link.into();
+                            link.artist_id = ActiveValue::Set(canonical);
+                            link.update(db.ref_db()?).await?;
+                        }
+                    }
+
+                    // aliases of the duplicate now belong to the canonical artist
+                    ArtistAlias::update_many()
+                        .col_expr(artist_alias::Column::ArtistId, Expr::value(canonical))
+                        .filter(artist_alias::Column::ArtistId.eq(duplicate_id))
+                        .exec(db.ref_db()?)
+                        .await?;
+                    Artist::delete_by_id(duplicate_id)
+                        .exec(db.ref_db()?)
+                        .await?;
+                    db.add_artist_alias(canonical, duplicate.name).await?;
+                }
+                db.record_changes(befores, origin).await?;
+                Ok(song_ids)
+            })
+            .await?;
+
+        // the files are only retagged once the merge is committed
+        for song_id in &song_ids {
+            self.write_song_tags(*song_id, music_dir.clone()).await?;
+        }
+        Ok(song_ids)
+    }
+
+    /// Makes `alias` resolve to the artist, moving it if it belonged to another artist
+    #[tracing::instrument(skip(self))]
+    pub async fn add_artist_alias(
+        &self,
+        artist_id: i32,
+        alias: String,
+    ) -> Result<(), DatabaseError> {
+        match ArtistAlias::find()
+            .filter(artist_alias::Column::Alias.eq(alias.clone()))
+            .one(self.ref_db()?)
+            .await?
+        {
+            Some(existing) => {
+                let mut model: artist_alias::ActiveModel = existing.into();
+                model.artist_id = ActiveValue::Set(artist_id);
+                model.update(self.ref_db()?).await?;
+            }
+            None => {
+                let model = artist_alias::ActiveModel {
+                    artist_id: ActiveValue::Set(artist_id),
+                    alias: ActiveValue::Set(alias),
+                    ..Default::default()
+                };
+                ArtistAlias::insert(model).exec(self.ref_db()?).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get_artist_aliases(&self, artist_id: i32) -> Result<Vec<String>, DatabaseError> {
+        Ok(ArtistAlias::find()
+            .filter(artist_alias::Column::ArtistId.eq(artist_id))
+            .order_by_asc(artist_alias::Column::Alias)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .map(|alias| alias.alias)
+            .collect())
+    }
+
+    pub async fn get_all_albums(&self) -> Result<Vec<AlbumModel>, DatabaseError> {
+        Ok(Album::find()
+            .order_by_asc(album::Column::Name)
+            .all(self.ref_db()?)
+            .await?)
+    }
+
+    /// The album with its songs ordered by disc and track number
+    pub async fn get_album_with_tracks(
+        &self,
+        album_id: i32,
+        music_dir: PathBuf,
+    ) -> Result<AlbumWithTracks, DatabaseError> {
+        let album = Album::find_by_id(album_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoAlbumFound)?;
+        let songs = album
+            .find_related(SongEntity)
+            .order_by_asc(song::Column::DiscNumber)
+            .order_by_asc(song::Column::TrackNumber)
+            .order_by_asc(song::Column::Title)
+            .all(self.ref_db()?)
+            .await?;
+
+        let tracks = self.load_gui_songs(songs, &music_dir).await?;
+        Ok(AlbumWithTracks { album, tracks })
+    }
+
+    /// update the name, album artist and year of an album
+    #[tracing::instrument(skip(self))]
+    pub async fn update_album(&self, album: AlbumModel) -> Result<(), DatabaseError> {
+        let model = album::ActiveModel {
+            id: ActiveValue::Unchanged(album.id),
+            name: ActiveValue::Set(album.name),
+            album_artist: ActiveValue::Set(album.album_artist),
+            year: ActiveValue::Set(album.year),
+        };
+        Album::update(model).exec(self.ref_db()?).await?;
+        Ok(())
+    }
+
+    /// set the cover of an album from any image format, it is stored as PNG
+    #[tracing::instrument(skip(self, image))]
+    pub async fn set_album_cover(&self, album_id: i32, image: &[u8]) -> Result<(), DatabaseError> {
+        self.transaction(|db| async move {
+            let mut data: Vec<u8> = vec![];
+            image::load_from_memory(image)?
+                .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)?;
+
+            AlbumCover::delete_by_id(album_id)
+                .exec(db.ref_db()?)
+                .await?;
+            let model = album_cover::ActiveModel {
+                album_id: ActiveValue::Set(album_id),
+                data: ActiveValue::Set(data),
+            };
+            AlbumCover::insert(model).exec(db.ref_db()?).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// PNG encoded cover of the album, if it has one
+    pub async fn get_album_cover(&self, album_id: i32) -> Result<Option<Vec<u8>>, DatabaseError> {
+        Ok(AlbumCover::find_by_id(album_id)
+            .one(self.ref_db()?)
+            .await?
+            .map(|cover| cover.data))
+    }
+
+    /// PNG encoded cover of the first album of the song that has one
+    pub async fn get_song_album_cover(
+        &self,
+        song_id: i32,
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let album_ids = SongAlbumJunction::find()
+            .filter(song_album_junction::Column::SongId.eq(song_id))
+            .order_by_asc(song_album_junction::Column::Key)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .map(|link| link.album_id)
+            .collect::<Vec<_>>();
+        let mut covers = AlbumCover::find()
+            .filter(album_cover::Column::AlbumId.is_in(album_ids.iter().copied()))
+            .all(self.ref_db()?)
+            .await?;
+        Ok(album_ids.into_iter().find_map(|album_id| {
+            let index = covers.iter().position(|cover| cover.album_id == album_id)?;
+            Some(covers.swap_remove(index).data)
+        }))
+    }
+
+    pub async fn get_lyrics(&self, song_id: i32) -> Result<Option<SongLyrics>, DatabaseError> {
+        Ok(Lyrics::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .map(lyrics_from_model))
+    }
+
+    /// Stores the lyrics of a song, replacing the ones it had
+    #[tracing::instrument(skip(self, lyrics))]
+    pub async fn set_lyrics(&self, song_id: i32, lyrics: &SongLyrics) -> Result<(), DatabaseError> {
+        let model = lyrics::ActiveModel {
+            song_id: ActiveValue::Set(song_id),
+            plain: ActiveValue::Set(lyrics.plain.clone()),
+            synced: ActiveValue::Set(lyrics.to_lrc()),
+        };
+        Lyrics::insert(model)
+            .on_conflict(
+                OnConflict::column(lyrics::Column::SongId)
+                    .update_columns([lyrics::Column::Plain, lyrics::Column::Synced])
+                    .to_owned(),
+            )
+            .exec_without_returning(self.ref_db()?)
+            .await?;
+        Ok(())
+    }
+
+    /// Stores the lyrics only if the song has none yet, returns whether they were stored
+    pub async fn set_lyrics_if_missing(
+        &self,
+        song_id: i32,
+        lyrics: &SongLyrics,
+    ) -> Result<bool, DatabaseError> {
+        let model = lyrics::ActiveModel {
+            song_id: ActiveValue::Set(song_id),
+            plain: ActiveValue::Set(lyrics.plain.clone()),
+            synced: ActiveValue::Set(lyrics.to_lrc()),
+        };
+        let rows = Lyrics::insert(model)
+            .on_conflict(
+                OnConflict::column(lyrics::Column::SongId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(self.ref_db()?)
+            .await?;
+        Ok(rows > 0)
+    }
+
+    /// Records how a song was downloaded, replacing what an earlier download recorded
+    pub async fn set_download_provenance(
+        &self,
+        song_id: i32,
+        provenance: &Provenance,
+    ) -> Result<(), DatabaseError> {
+        let model = song_download::ActiveModel {
+            song_id: ActiveValue::Set(song_id),
+            url: ActiveValue::Set(provenance.url.clone()),
+            uploader_id: ActiveValue::Set(provenance.uploader_id.clone()),
+            upload_date: ActiveValue::Set(provenance.upload_date.clone()),
+            format_id: ActiveValue::Set(provenance.format_id.clone()),
+            extractor: ActiveValue::Set(provenance.extractor.clone()),
+            ytdlp_version: ActiveValue::Set(provenance.ytdlp_version.clone()),
+            downloaded_at: ActiveValue::Set(provenance.downloaded_at),
+        };
+        SongDownload::insert(model)
+            .on_conflict(
+                OnConflict::column(song_download::Column::SongId)
+                    .update_columns([
+                        song_download::Column::Url,
+                        song_download::Column::UploaderId,
+                        song_download::Column::UploadDate,
+                        song_download::Column::FormatId,
+                        song_download::Column::Extractor,
+                        song_download::Column::YtdlpVersion,
+                        song_download::Column::DownloadedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(self.ref_db()?)
+            .await?;
+        Ok(())
+    }
+
+    /// How a song was downloaded, `None` for local files and songs downloaded before
+    /// it was recorded
+    pub async fn get_download_provenance(
+        &self,
+        song_id: i32,
+    ) -> Result<Option<Provenance>, DatabaseError> {
+        Ok(SongDownload::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .map(Provenance::from_model))
+    }
+
+    pub async fn delete_lyrics(&self, song_id: i32) -> Result<(), DatabaseError> {
+        Lyrics::delete_by_id(song_id).exec(self.ref_db()?).await?;
+        Ok(())
+    }
+
+    /// Reads a `.lrc` file and stores it as the lyrics of the song
+    #[tracing::instrument(skip(self))]
+    pub async fn import_lrc(
+        &self,
+        song_id: i32,
+        lrc_path: PathBuf,
+    ) -> Result<SongLyrics, DatabaseError> {
+        let lyrics = SongLyrics::from_lrc(&std::fs::read_to_string(lrc_path)?);
+        self.set_lyrics(song_id, &lyrics).await?;
+        Ok(lyrics)
+    }
+
+    /// Every song with its relations, every local playlist, album cover and artist alias,
+    /// see `LibraryExport`
+    #[tracing::instrument(skip(self))]
+    pub async fn export_library(&self) -> Result<LibraryExport, DatabaseError> {
+        let models = SongEntity::find()
+            .order_by_asc(song::Column::Id)
+            .all(self.ref_db()?)
+            .await?;
+        // an empty music dir keeps the paths relative
+        let songs = self
+            .load_gui_songs(models, Path::new(""))
+            .await?
+            .iter()
+            .map(ExportedSong::from_song)
+            .collect();
+
+        let mut playlists = vec![];
+        for playlist in self.get_all_playlists().await? {
+            playlists.push(ExportedPlaylist {
+                song_ids: self.get_playlist_song_ids(playlist.id).await?,
+                name: playlist.name,
+                description: playlist.description,
+            });
+        }
+
+        let relationships = SongRelationship::find()
+            .order_by_asc(song_relationship::Column::Id)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .map(|link| ExportedRelationship {
+                song_id: link.song_id,
+                related_song_id: link.related_song_id,
+                kind: link.kind,
+            })
+            .collect();
+
+        let album_covers = AlbumCover::find()
+            .find_also_related(Album)
+            .order_by_asc(album_cover::Column::AlbumId)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .filter_map(|(cover, album)| Some(ExportedCover::new(&album?, &cover.data)))
+            .collect();
+        let artist_aliases = ArtistAlias::find()
+            .find_also_related(Artist)
+            .order_by_asc(artist_alias::Column::Alias)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .filter_map(|(alias, artist)| {
+                Some(ExportedAlias {
+                    artist: artist?.name,
+                    alias: alias.alias,
+                })
+            })
+            .collect();
+
+        Ok(LibraryExport {
+            version: EXPORT_VERSION,
+            exported_at: unix_now(),
+            songs,
+            playlists,
+            relationships,
+            album_covers,
+            artist_aliases,
+        })
+    }
+
+    /// Inserts the songs and playlists of an export. Songs already in the database, see
+    /// `find_imported_song`, are kept as they are, so importing into an existing library
+    /// merges both. Playlists are matched by name and get the songs they are missing
+    /// appended. Covers and aliases are only added to albums and artists without them.
+    #[tracing::instrument(skip_all)]
+    pub async fn import_library(
+        &self,
+        export: &LibraryExport,
+        music_dir: PathBuf,
+    ) -> Result<ImportReport, DatabaseError> {
+        self.transaction(|db| async move {
+            let mut report = ImportReport::default();
+            // ids in the export to ids in this database
+            let mut song_ids = HashMap::new();
+
+            for exported in &export.songs {
+                if let Some(existing) = db.find_imported_song(exported).await? {
+                    song_ids.insert(exported.id, existing.id);
+                    report.songs_skipped += 1;
+                    continue;
+                }
+
+                let song_id = db.import_song(exported, music_dir.clone()).await?;
+                song_ids.insert(exported.id, song_id);
+                report.songs_inserted += 1;
+            }
+
+            for exported in &export.playlists {
+                let existing = Playlist::find()
+                    .filter(playlist::Column::Name.eq(exported.name.clone()))
+                    .one(db.ref_db()?)
+                    .await?;
+                let playlist_id = match existing {
+                    Some(playlist) => playlist.id,
+                    None => {
+                        report.playlists_created += 1;
+                        db.create_playlist(exported.name.clone(), exported.description.clone())
+                            .await?
+                    }
+                };
+
+                let mut playlist_song_ids = db.get_playlist_song_ids(playlist_id).await?;
+                for song_id in exported.song_ids.iter().filter_map(|id| song_ids.get(id)) {
+                    if !playlist_song_ids.contains(song_id) {
+                        playlist_song_ids.push(*song_id);
+                    }
+                }
+                db.reorder_playlist(playlist_id, playlist_song_ids).await?;
+            }
+
+            for exported in &export.relationships {
+                if let (Some(song_id), Some(related_song_id)) = (
+                    song_ids.get(&exported.song_id),
+                    song_ids.get(&exported.related_song_id),
+                ) {
+                    db.link_songs(*song_id, *related_song_id, exported.kind)
+                        .await?;
+                }
+            }
+
+            for exported in &export.album_covers {
+                let Some(album) = db
+                    .find_album(&exported.album, exported.album_artist.as_deref())
+                    .await?
+                else {
+                    continue;
+                };
+                if db.get_album_cover(album.id).await?.is_none() {
+                    db.set_album_cover(album.id, &exported.decode_png()?)
+                        .await?;
+                }
+            }
+
+            for exported in &export.artist_aliases {
+                let artist = Artist::find()
+                    .filter(artist::Column::Name.eq(exported.artist.clone()))
+                    .one(db.ref_db()?)
+                    .await?;
+                let known = ArtistAlias::find()
+                    .filter(artist_alias::Column::Alias.eq(exported.alias.clone()))
+                    .one(db.ref_db()?)
+                    .await?
+                    .is_some();
+                if let (Some(artist), false) = (artist, known) {
+                    db.add_artist_alias(artist.id, exported.alias.clone())
+                        .await?;
+                }
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+
+    /// Inserts one exported song with its lyrics, returning its new id
+    async fn import_song(
+        &self,
+        exported: &ExportedSong,
+        music_dir: PathBuf,
+    ) -> Result<i32, DatabaseError> {
+        let song = self
+            .insert_from_app_song(exported.to_app_song(music_dir))
+            .await?;
+        let song_id = song.id.ok_or(DatabaseError::NoSongId)?;
+        // the TUI leaves the path to the file scan, an import already knows it
+        let model = song::ActiveModel {
+            id: ActiveValue::Set(song_id),
+            path: ActiveValue::Set(exported.path.clone()),
+            content_hash: ActiveValue::Set(exported.content_hash.clone()),
+            added_at: exported.added_at.map_or(ActiveValue::NotSet, |added_at| {
+                ActiveValue::Set(Some(added_at))
+            }),
+            ..Default::default()
+        };
+        SongEntity::update(model).exec(self.ref_db()?).await?;
+        if let Some(lyrics) = exported.get_lyrics() {
+            self.set_lyrics(song_id, &lyrics).await?;
+        }
+        if let Some(provenance) = exported.provenance.as_ref() {
+            self.set_download_provenance(song_id, provenance).await?;
+        }
+        for label in &exported.labels {
+            self.insert_song_label(song_id, label.clone()).await?;
+        }
+        Ok(song_id)
+    }
+
+    /// The song an exported song already is in the database: the one with the same audio,
+    /// path or youtube id, or else one with the same title and artists that isn't known
+    /// to be a different file or video
+    async fn find_imported_song(
+        &self,
+        exported: &ExportedSong,
+    ) -> Result<Option<SongModel>, DatabaseError> {
+        if let Some(content_hash) = exported.content_hash.as_ref() {
+            let same_audio = SongEntity::find()
+                .filter(song::Column::ContentHash.eq(content_hash.clone()))
+                .one(self.ref_db()?)
+                .await?;
+            if same_audio.is_some() {
+                return Ok(same_audio);
+            }
+        }
+
+        let mut condition = Condition::any();
+        if let Some(path) = exported.path.as_ref() {
+            condition = condition.add(song::Column::Path.eq(path.clone()));
+        }
+        if let Some(youtube_id) = exported.youtube_id.as_ref() {
+            condition = condition.add(song::Column::YoutubeId.eq(youtube_id.clone()));
+        }
+        if !condition.is_empty() {
+            let found = SongEntity::find()
+                .filter(condition)
+                .one(self.ref_db()?)
+                .await?;
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+
+        let differs = |ours: &Option<String>, theirs: &Option<String>| {
+            ours.is_some() && theirs.is_some() && ours != theirs
+        };
+        let mut artists = exported.artists.clone();
+        artists.sort();
+        let same_title = SongEntity::find()
+            .filter(song::Column::Title.eq(exported.title.clone()))
+            .order_by_asc(song::Column::Id)
+            .all(self.ref_db()?)
+            .await?;
+        for song in same_title {
+            if differs(&song.content_hash, &exported.content_hash)
+                || differs(&song.path, &exported.path)
+                || differs(&song.youtube_id, &exported.youtube_id)
+            {
+                continue;
+            }
+            let mut names = song
+                .find_related(Artist)
+                .all(self.ref_db()?)
+                .await?
+                .into_iter()
+                .map(|artist| artist.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            if names == artists {
+                return Ok(Some(song));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Moves a song into another library, returning its id in `target`. The song is
+    /// inserted with its metadata, stats, lyrics and labels, added to the local playlists
+    /// of the same name and linked to its versions, and its file and `.lrc` sidecar are
+    /// moved to the same relative path under `target_music_dir`.
+    ///
+    /// The files are copied first and the song is only deleted from this library once
+    /// `target` committed it, the originals are removed last. If any step before that
+    /// fails both libraries and the files are left as they were. A song linked to songs
+    /// that aren't in `target` is refused, the links would be lost.
+    #[tracing::instrument(skip(self, target))]
+    pub async fn move_song_to_library(
+        &self,
+        song_id: i32,
+        music_dir: PathBuf,
+        target: &DbConnection,
+        target_music_dir: PathBuf,
+    ) -> Result<i32, DatabaseError> {
+        let model = SongEntity::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoSongFound)?;
+        // an empty music dir keeps the path relative
+        let exported = ExportedSong::from_song(&self.load_gui_song(model, Path::new("")).await?);
+        if target.find_imported_song(&exported).await?.is_some() {
+            return Err(DatabaseError::SongInTargetLibrary);
+        }
+        let playlists = PlaylistSong::find()
+            .filter(playlist_song::Column::SongId.eq(song_id))
+            .find_also_related(Playlist)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .filter_map(|(_, playlist)| playlist)
+            .collect::<Vec<_>>();
+        // the versions of the song, by their id in the target library
+        let mut relationships = vec![];
+        for related in self.get_song_relationships(song_id).await? {
+            let model = SongEntity::find_by_id(related.song_id)
+                .one(self.ref_db()?)
+                .await?
+                .ok_or(DatabaseError::NoSongFound)?;
+            let exported =
+                ExportedSong::from_song(&self.load_gui_song(model, Path::new("")).await?);
+            match target.find_imported_song(&exported).await? {
+                Some(target_song) => relationships.push((target_song.id, related)),
+                None => return Err(DatabaseError::RelatedSongNotInTarget(related.song_id)),
+            }
+        }
+
+        let _locks = match exported.path.is_some() {
+            true => Some([
+                MusicDirLock::try_acquire(&music_dir, "moving a song")?,
+                MusicDirLock::try_acquire(&target_music_dir, "moving a song")?,
+            ]),
+            false => None,
+        };
+        let mut files = vec![];
+        if let Some(path) = exported.path.as_ref() {
+            let from = music_dir.join(path);
+            files.push((from.clone(), target_music_dir.join(path)));
+            let sidecar = from.with_extension("lrc");
+            if sidecar.exists() {
+                files.push((sidecar, target_music_dir.join(path).with_extension("lrc")));
+            }
+        }
+        let copied = copy_files(&files)?;
+
+        let inserted = target
+            .transaction(|db| async move {
+                let new_id = db.import_song(&exported, target_music_dir.clone()).await?;
+                for playlist in playlists {
+                    let existing = Playlist::find()
+                        .filter(playlist::Column::Name.eq(playlist.name.clone()))
+                        .one(db.ref_db()?)
+                        .await?;
+                    let playlist_id = match existing {
+                        Some(existing) => existing.id,
+                        None => {
+                            db.create_playlist(playlist.name, playlist.description)
+                                .await?
+                        }
+                    };
+                    db.add_song_to_playlist(playlist_id, new_id).await?;
+                }
+                for (related_id, related) in relationships {
+                    match related.original {
+                        true => db.link_songs(new_id, related_id, related.kind).await?,
+                        false => db.link_songs(related_id, new_id, related.kind).await?,
+                    }
+                }
+                Ok(new_id)
+            })
+            .await;
+        let new_id = match inserted {
+            Ok(new_id) => new_id,
+            Err(e) => {
+                remove_files(&copied);
+                return Err(e);
+            }
+        };
+
+        let deleted = self
+            .delete_song_from_app_song(AppSong::new().with_id(Some(song_id)))
+            .await;
+        if let Err(e) = deleted {
+            // the song stays here, take it out of the target library again
+            remove_files(&copied);
+            target
+                .delete_song_from_app_song(AppSong::new().with_id(Some(new_id)))
+                .await?;
+            return Err(e);
+        }
+
+        for (from, _) in &files {
+            if let Err(e) = std::fs::remove_file(from) {
+                warn!(
+                    "song was moved but {} could not be removed: {}",
+                    from.display(),
+                    e
+                );
+            }
+        }
+        Ok(new_id)
+    }
+
+    /// create a new local playlist, returning its id
+    #[tracing::instrument(skip(self))]
+    pub async fn create_playlist(
+        &self,
+        name: String,
+        description: Option<String>,
+    ) -> Result<i32, DatabaseError> {
+        let model = playlist::ActiveModel {
+            name: ActiveValue::Set(name),
+            description: ActiveValue::Set(description),
+            ..Default::default()
+        };
+        Ok(Playlist::insert(model)
+            .exec(self.ref_db()?)
+            .await?
+            .last_insert_id)
+    }
+
+    pub async fn get_playlist(&self, playlist_id: i32) -> Result<PlaylistModel, DatabaseError> {
+        Playlist::find_by_id(playlist_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoPlaylistFound)
+    }
+
+    pub async fn get_all_playlists(&self) -> Result<Vec<PlaylistModel>, DatabaseError> {
+        Ok(Playlist::find()
+            .order_by_asc(playlist::Column::Name)
+            .all(self.ref_db()?)
+            .await?)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn rename_playlist(
+        &self,
+        playlist_id: i32,
+        name: String,
+    ) -> Result<(), DatabaseError> {
+        let mut model: playlist::ActiveModel = self.get_playlist(playlist_id).await?.into();
+        model.name = ActiveValue::Set(name);
+        model.update(self.ref_db()?).await?;
+        Ok(())
+    }
+
+    /// Songs of the playlist in playlist order
+    pub async fn get_playlist_songs(
+        &self,
+        playlist_id: i32,
+    ) -> Result<Vec<SongModel>, DatabaseError> {
+        Ok(PlaylistSong::find()
+            .filter(playlist_song::Column::PlaylistId.eq(playlist_id))
+            .order_by_asc(playlist_song::Column::Position)
+            .find_also_related(SongEntity)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .filter_map(|(_, song)| song)
+            .collect())
+    }
+
+    /// Appends a song to the end of the playlist
+    #[tracing::instrument(skip(self))]
+    pub async fn add_song_to_playlist(
+        &self,
+        playlist_id: i32,
+        song_id: i32,
+    ) -> Result<i32, DatabaseError> {
+        self.get_playlist(playlist_id).await?;
+        SongEntity::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoSongFound)?;
+        let position = PlaylistSong::find()
+            .filter(playlist_song::Column::PlaylistId.eq(playlist_id))
+            .order_by_desc(playlist_song::Column::Position)
+            .one(self.ref_db()?)
+            .await?
+            .map_or(0, |last| last.position + 1);
+        let model = playlist_song::ActiveModel {
+            playlist_id: ActiveValue::Set(playlist_id),
+            song_id: ActiveValue::Set(song_id),
+            position: ActiveValue::Set(position),
+            ..Default::default()
+        };
+        Ok(PlaylistSong::insert(model)
+            .exec(self.ref_db()?)
+            .await?
+            .last_insert_id)
+    }
+
+    /// Removes the song at `position`, the following songs move up by one
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_song_from_playlist(
+        &self,
+        playlist_id: i32,
+        position: usize,
+    ) -> Result<(), DatabaseError> {
+        let mut song_ids = self.get_playlist_song_ids(playlist_id).await?;
+        if position >= song_ids.len() {
+            return Err(DatabaseError::NoSongFound);
+        }
+        song_ids.remove(position);
+        self.reorder_playlist(playlist_id, song_ids).await
+    }
+
+    /// Moves the song at position `from` to position `to`
+    #[tracing::instrument(skip(self))]
+    pub async fn move_playlist_song(
+        &self,
+        playlist_id: i32,
+        from: usize,
+        to: usize,
+    ) -> Result<(), DatabaseError> {
+        let mut song_ids = self.get_playlist_song_ids(playlist_id).await?;
+        if from >= song_ids.len() || to >= song_ids.len() {
+            return Err(DatabaseError::NoSongFound);
+        }
+        let song_id = song_ids.remove(from);
+        song_ids.insert(to, song_id);
+        self.reorder_playlist(playlist_id, song_ids).await
+    }
+
+    /// Replaces the content of the playlist by `song_ids`, in that order
+    #[tracing::instrument(skip(self))]
+    pub async fn reorder_playlist(
+        &self,
+        playlist_id: i32,
+        song_ids: Vec<i32>,
+    ) -> Result<(), DatabaseError> {
+        self.transaction(|db| async move {
+            PlaylistSong::delete_many()
+                .filter(playlist_song::Column::PlaylistId.eq(playlist_id))
+                .exec(db.ref_db()?)
+                .await?;
+            if song_ids.is_empty() {
+                return Ok(());
+            }
+
+            let models = song_ids.into_iter().enumerate().map(|(position, song_id)| {
+                playlist_song::ActiveModel {
+                    playlist_id: ActiveValue::Set(playlist_id),
+                    song_id: ActiveValue::Set(song_id),
+                    position: ActiveValue::Set(position as i32),
+                    ..Default::default()
+                }
+            });
+            PlaylistSong::insert_many(models).exec(db.ref_db()?).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_playlist_song_ids(&self, playlist_id: i32) -> Result<Vec<i32>, DatabaseError> {
+        Ok(PlaylistSong::find()
+            .filter(playlist_song::Column::PlaylistId.eq(playlist_id))
+            .order_by_asc(playlist_song::Column::Position)
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .map(|entry| entry.song_id)
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn delete_playlist(&self, playlist_id: i32) -> Result<(), DatabaseError> {
+        self.transaction(|db| async move {
+            PlaylistSong::delete_many()
+                .filter(playlist_song::Column::PlaylistId.eq(playlist_id))
+                .exec(db.ref_db()?)
+                .await?;
+            let rows = Playlist::delete_by_id(playlist_id)
+                .exec(db.ref_db()?)
+                .await?
+                .rows_affected;
+            if rows == 0 {
+                return Err(DatabaseError::NoPlaylistFound);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Renders the playlist as M3U8, with the songs left out as they have no file. Song
+    /// paths are stored relative to the music dir, so the playlist is meant to live in the
+    /// music dir as well
+    pub async fn playlist_to_m3u8(
+        &self,
+        playlist_id: i32,
+    ) -> Result<(String, Vec<SongModel>), DatabaseError> {
+        let playlist = self.get_playlist(playlist_id).await?;
+        let mut m3u8 = format!("#EXTM3U\n#PLAYLIST:{}\n", playlist.name);
+        let mut skipped = vec![];
+        for song in self.get_playlist_songs(playlist_id).await? {
+            match song.path.as_ref().filter(|path| !path.is_empty()) {
+                Some(path) => {
+                    // -1 is used by players for an unknown duration
+                    let duration = song.duration_ms.map_or(-1, |ms| ms / 1000);
+                    m3u8.push_str(&format!("#EXTINF:{},{}\n{}\n", duration, song.title, path));
+                }
+                None => {
+                    warn!(
+                        "song {} has no file, leaving it out of the playlist",
+                        song.id
+                    );
+                    skipped.push(song);
+                }
+            }
+        }
+        Ok((m3u8, skipped))
+    }
+
+    /// Writes the playlist to `<music_dir>/<playlist name>.m3u8`
+    pub async fn export_playlist_m3u8(
+        &self,
+        playlist_id: i32,
+        music_dir: PathBuf,
+    ) -> Result<PlaylistExport, DatabaseError> {
+        let playlist = self.get_playlist(playlist_id).await?;
+        let fname = format!("{}.m3u8", playlist.name.replace(['/', '\\'], "_"));
+        let path = music_dir.join(fname);
+        let (m3u8, skipped) = self.playlist_to_m3u8(playlist_id).await?;
+        std::fs::write(&path, m3u8)?;
+        info!("exported playlist {} to {}", playlist.name, path.display());
+        Ok(PlaylistExport { path, skipped })
+    }
+
+    /// Takes the current values of the tracked fields of a song, see `record_song_changes`
+    pub async fn snapshot_song(&self, song_id: i32) -> Result<SongSnapshot, DatabaseError> {
+        let model = SongEntity::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoSongFound)?;
+        let mut artists = model.find_related(Artist).all(self.ref_db()?).await?;
+        artists.sort_by_key(|artist| artist.id);
+        let albums = self
+            .get_song_albums(song_id)
+            .await?
+            .into_iter()
+            .map(|album| RecordedAlbum {
+                name: album.name,
+                album_artist: album.album_artist,
+            })
+            .collect::<Vec<_>>();
+        let genres = model.find_related(Genre).all(self.ref_db()?).await?;
+
+        let values = vec![
+            (SongField::Title, Some(model.title)),
+            (
+                SongField::Artists,
+                record_values(&artists.into_iter().map(|a| a.name).collect::<Vec<_>>())?,
+            ),
+            (SongField::Albums, record_values(&albums)?),
+            (
+                SongField::Genres,
+                record_values(&genres.into_iter().map(|g| g.genre).collect::<Vec<_>>())?,
+            ),
+            (SongField::YoutubeId, model.youtube_id),
+            (SongField::ThumbnailUrl, model.thumbnail_url),
+            (
+                SongField::TrackNumber,
+                model.track_number.map(|n| n.to_string()),
+            ),
+            (
+                SongField::TrackTotal,
+                model.track_total.map(|n| n.to_string()),
+            ),
+            (
+                SongField::DiscNumber,
+                model.disc_number.map(|n| n.to_string()),
+            ),
+            (SongField::Year, model.year.map(|n| n.to_string())),
+            (SongField::ReleaseDate, model.release_date),
+        ];
+        Ok(SongSnapshot { song_id, values })
+    }
+
+    /// Compares the song against a snapshot taken before an edit and stores every changed field
+    /// as one change set. Returns the id of the change set, `None` if nothing changed
+    #[tracing::instrument(skip(self))]
+    pub async fn record_song_changes(
+        &self,
+        before: SongSnapshot,
+        origin: ChangeOrigin,
+    ) -> Result<Option<i32>, DatabaseError> {
+        self.record_changes(vec![before], origin).await
+    }
+
+    /// Like `record_song_changes`, for an edit that touched several songs
+    async fn record_changes(
+        &self,
+        befores: Vec<SongSnapshot>,
+        origin: ChangeOrigin,
+    ) -> Result<Option<i32>, DatabaseError> {
+        let mut changes = vec![];
+        for before in befores {
+            let after = self.snapshot_song(before.song_id).await?;
+            changes.extend(
+                before
+                    .values
+                    .into_iter()
+                    .zip(after.values)
+                    .filter(|((_, old), (_, new))| old != new)
+                    .map(|((field, old), (_, new))| (before.song_id, field, old, new)),
+            );
+        }
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let change_set = change_set::ActiveModel {
+            origin: ActiveValue::Set(origin),
+            created_at: ActiveValue::Set(unix_now()),
+            undone: ActiveValue::Set(false),
+            ..Default::default()
+        };
+        let change_set_id = ChangeSet::insert(change_set)
+            .exec(self.ref_db()?)
+            .await?
+            .last_insert_id;
+
+        let count = changes.len();
+        let models =
+            changes
+                .into_iter()
+                .map(|(song_id, field, old, new)| field_change::ActiveModel {
+                    change_set_id: ActiveValue::Set(change_set_id),
+                    song_id: ActiveValue::Set(song_id),
+                    field: ActiveValue::Set(field),
+                    old_value: ActiveValue::Set(old),
+                    new_value: ActiveValue::Set(new),
+                    ..Default::default()
+                });
+        FieldChange::insert_many(models)
+            .exec(self.ref_db()?)
+            .await?;
+        info!("recorded change set {change_set_id} with {count} changes");
+        Ok(Some(change_set_id))
+    }
+
+    /// Latest change sets first, with the changes they contain
+    pub async fn get_change_sets(
+        &self,
+        limit: u64,
+    ) -> Result<Vec<(ChangeSetModel, Vec<FieldChangeModel>)>, DatabaseError> {
+        let change_sets = ChangeSet::find()
+            .order_by_desc(change_set::Column::Id)
+            .limit(limit)
+            .all(self.ref_db()?)
+            .await?;
+        let mut vvec = vec![];
+        for change_set in change_sets {
+            let changes = change_set
+                .find_related(FieldChange)
+                .order_by_asc(field_change::Column::Key)
+                .all(self.ref_db()?)
+                .await?;
+            vvec.push((change_set, changes));
+        }
+        Ok(vvec)
+    }
+
+    /// Reverts every field of a change set to its old value, in the database and in the file tags.
+    /// Later edits of the same fields are overwritten. The tags are written once the database
+    /// committed, a failed undo leaves the files alone
+    #[tracing::instrument(skip(self))]
+    pub async fn undo_change_set(
+        &self,
+        change_set_id: i32,
+        music_dir: PathBuf,
+    ) -> Result<(), DatabaseError> {
+        let song_ids = self
+            .transaction(|db| async move {
+                let change_set = ChangeSet::find_by_id(change_set_id)
+                    .one(db.ref_db()?)
+                    .await?
+                    .ok_or(DatabaseError::NoChangeSetFound)?;
+                if change_set.undone {
+                    return Err(DatabaseError::ChangeSetAlreadyUndone);
+                }
+
+                let changes = change_set
+                    .find_related(FieldChange)
+                    .order_by_desc(field_change::Column::Key)
+                    .all(db.ref_db()?)
+                    .await?;
+                let mut song_ids = vec![];
+                for change in changes {
+                    if change.field == SongField::Artists {
+                        db.unmerge_artists(&change).await?;
+                    }
+                    db.set_song_field(change.song_id, change.field, change.old_value)
+                        .await?;
+                    if !song_ids.contains(&change.song_id) {
+                        song_ids.push(change.song_id);
+                    }
+                }
+
+                let mut change_set: change_set::ActiveModel = change_set.into();
+                change_set.undone = ActiveValue::Set(true);
+                change_set.update(db.ref_db()?).await?;
+                Ok(song_ids)
+            })
+            .await?;
+
+        // keep the file tags in line with the database
+        for song_id in song_ids {
+            self.write_song_tags(song_id, music_dir.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Old artists of the change that were merged into one of its new artists become artists
+    /// of their own again, so undoing a merge brings the duplicates back
+    async fn unmerge_artists(&self, change: &FieldChangeModel) -> Result<(), DatabaseError> {
+        let new_ids = Artist::find()
+            .filter(
+                artist::Column::Name.is_in(recorded_values::<String>(change.new_value.clone())?),
+            )
+            .all(self.ref_db()?)
+            .await?
+            .into_iter()
+            .map(|artist| artist.id)
+            .collect::<Vec<_>>();
+        for name in recorded_values::<String>(change.old_value.clone())? {
+            let removed = ArtistAlias::delete_many()
+                .filter(artist_alias::Column::Alias.eq(name.clone()))
+                .filter(artist_alias::Column::ArtistId.is_in(new_ids.clone()))
+                .exec(self.ref_db()?)
+                .await?
+                .rows_affected;
+            if removed > 0 && self.find_artist(&name).await?.is_none() {
+                let model = artist::ActiveModel {
+                    name: ActiveValue::Set(name),
+                    ..Default::default()
+                };
+                Artist::insert(model).exec(self.ref_db()?).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the database state of a song to its file, if it has one
+    async fn write_song_tags(&self, song_id: i32, music_dir: PathBuf) -> Result<(), DatabaseError> {
+        let mut song = self.get_song_gui(song_id, music_dir.clone()).await?;
+        match song.path.clone().filter(|path| path.exists()) {
+            Some(path) => {
+                let _lock = MusicDirLock::try_acquire(&music_dir, "writing tags")?;
+                if let Some(cover) = self.get_song_album_cover(song_id).await? {
+                    song.set_thumbnail(cover);
+                }
+                tags::write_tags_song(path, &song).await?;
+            }
+            None => warn!("song {song_id} has no file, only the database was updated"),
+        }
+        Ok(())
+    }
+
+    /// Writes the play stats of the song to its file, if it has one
+    async fn write_song_play_stats(
+        &self,
+        song_id: i32,
+        music_dir: PathBuf,
+    ) -> Result<(), DatabaseError> {
+        let song = SongEntity::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoSongFound)?;
+        let path = song
+            .path
+            .as_ref()
+            .filter(|path| !path.is_empty())
+            .map(|path| music_dir.join(path))
+            .filter(|path| path.exists());
+        match path {
+            Some(path) => {
+                let _lock = MusicDirLock::try_acquire(&music_dir, "writing play stats")?;
+                tags::write_play_stats_to_file(path, &PlayStats::from_model(&song)).await?
+            }
+            None => warn!("song {song_id} has no file, only the database was updated"),
+        }
+        Ok(())
+    }
+
+    /// Writes the labels of the song to its file, if it has one
+    async fn write_song_labels(
+        &self,
+        song_id: i32,
+        music_dir: PathBuf,
+    ) -> Result<(), DatabaseError> {
+        let song = self.get_song_gui(song_id, music_dir.clone()).await?;
+        match song.path.as_ref().filter(|path| path.exists()) {
+            Some(path) => {
+                let _lock = MusicDirLock::try_acquire(&music_dir, "writing labels")?;
+                tags::write_labels(path.clone(), &song.get_labels_vec()).await?
+            }
+            None => warn!("song {song_id} has no file, only the database was updated"),
+        }
+        Ok(())
+    }
+
+    /// Sets one tracked field of a song from its history representation
+    async fn set_song_field(
+        &self,
+        song_id: i32,
+        field: SongField,
+        value: Option<String>,
+    ) -> Result<(), DatabaseError> {
+        let song = SongEntity::find_by_id(song_id)
+            .one(self.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoSongFound)?;
+        let number = value.as_ref().and_then(|value| value.parse::<i32>().ok());
+        let mut model = song::ActiveModel {
+            id: ActiveValue::Set(song_id),
+            ..Default::default()
+        };
+        match field {
+            SongField::Title => {
+                model.title = ActiveValue::Set(value.unwrap_or("Unknown".to_string()))
+            }
+            SongField::Artists => {
+                let artists = recorded_values::<String>(value)?
+                    .into_iter()
+                    .map(|name| ArtistModel {
+                        name,
+                        ..Default::default()
+                    })
+                    .collect();
+                return self.update_song_artists_links(song, artists).await;
+            }
+            SongField::Albums => {
+                let albums = recorded_values::<RecordedAlbum>(value)?
+                    .into_iter()
+                    .map(|album| AlbumModel {
+                        name: album.name,
+                        album_artist: album.album_artist,
+                        ..Default::default()
+                    })
+                    .collect();
+                return self.update_song_albums_links(song, albums).await;
+            }
+            SongField::Genres => {
+                let genres = recorded_values::<String>(value)?
+                    .into_iter()
+                    .map(|genre| GenreModel {
+                        genre,
+                        ..Default::default()
+                    })
+                    .collect();
+                return self.update_song_genres_links(song, genres).await;
+            }
+            SongField::YoutubeId => model.youtube_id = ActiveValue::Set(value),
+            SongField::ThumbnailUrl => model.thumbnail_url = ActiveValue::Set(value),
+            SongField::TrackNumber => model.track_number = ActiveValue::Set(number),
+            SongField::TrackTotal => model.track_total = ActiveValue::Set(number),
+            SongField::DiscNumber => model.disc_number = ActiveValue::Set(number),
+            SongField::Year => model.year = ActiveValue::Set(number),
+            SongField::ReleaseDate => model.release_date = ActiveValue::Set(value),
+        }
+        SongEntity::update(model).exec(self.ref_db()?).await?;
+        Ok(())
+    }
+
+    pub async fn in_memory_test() -> Result<(), DatabaseError> {
+        let mut opt = ConnectOptions::new("sqlite::memory:".to_owned());
+        opt.sqlx_logging(true)
+            .sqlx_logging_level(tracing::log::LevelFilter::Debug);
+        let db = Self::connect(opt).await?;
+        info!("spawned new in memory sqlite database");
+
+        let strct = Self {
+            path: None,
+            db: Some(DbHandle::Connection(db)),
+            lock: None,
+            auto_clean_orphans: false,
+            connection_id: next_connection_id(),
+        };
+        strct.test_db().await?;
+        Ok(())
+    }
+
+    /// Fills an in memory database with test data, refusing a database file as the
+    /// migrations are refreshed, dropping every table first
+    pub async fn test_db(&self) -> Result<(), DatabaseError> {
+        if let Some(path) = &self.path {
+            return Err(DatabaseError::NotInMemory(path.clone()));
+        }
+        // ensure database is up to date
+        crate::migrator::Migrator::refresh(self.ref_db()?).await?;
+
+        for artist in [
+            "Hoshimashi Suisei",
+            "Hoshimashi Suisei",
+            "Comet-chan",
+            "Sui-chan",
+            "Minato Aqua",
+        ] {
+            self.insert_artist(artist.to_string()).await?;
+        }
+
+        for album in ["Still Still Stellar", "AYAYA", "Minato Aqua Originals"] {
+            self.insert_album(album.to_owned()).await?;
+        }
+
+        for genre in ["Vtuber", "Jpop"] {
+            self.insert_genre(genre.to_owned()).await?;
+        }
+
+        let youtube_playlist_id_res = self
             .insert_youtube_playlist_id("PLFnrkmfz7sBLzcERljKHFXfVaplrh53AY".to_string())
             .await?;
 
-        for (title, yt_id) in [
-            ("Stellar Stellar", "a51VH9BYzZA"),
-            ("Aquairo Palette", "xGihoycGivE"),
+        for (title, yt_id) in [
+            ("Stellar Stellar", "a51VH9BYzZA"),
+            ("Aquairo Palette", "xGihoycGivE"),
+        ] {
+            let tb = format!("https://i.ytimg.com/vi_webp/{yt_id}/maxresdefault.webp");
+            self.insert_song(
+                title.to_owned(),
+                Some(yt_id.to_owned()),
+                Some(tb.to_owned()),
+            )
+            .await?;
+        }
+
+        for artist in ["Hoshimashi Suisei", "Comet-chan"] {
+            if let Some(artist_id) = Artist::find()
+                .filter(artist::Column::Name.eq(artist))
+                .one(self.ref_db()?)
+                .await?
+            {
+                self.insert_song_artist(artist_id.id, 1).await?;
+            }
+        }
+
+        for artist in ["Minato Aqua"] {
+            if let Some(artist_id) = Artist::find()
+                .filter(artist::Column::Name.eq(artist))
+                .one(self.ref_db()?)
+                .await?
+            {
+                self.insert_song_artist(artist_id.id, 2).await?;
+            }
+        }
+
+        for album in ["Still Still Stellar"] {
+            if let Some(album_model) = Album::find()
+                .filter(album::Column::Name.eq(album))
+                .one(self.ref_db()?)
+                .await?
+            {
+                self.insert_song_album(album_model.id, 1).await?;
+            }
+        }
+
+        for album in ["Minato Aqua Originals"] {
+            if let Some(album_model) = Album::find()
+                .filter(album::Column::Name.eq(album))
+                .one(self.ref_db()?)
+                .await?
+            {
+                self.insert_song_album(album_model.id, 2).await?;
+            }
+        }
+
+        for genre in ["Vtuber", "Jpop"] {
+            if let Some(genre_model) = Genre::find()
+                .filter(genre::Column::Genre.eq(genre))
+                .one(self.ref_db()?)
+                .await?
+            {
+                self.insert_song_genre(genre_model.id, 1).await?;
+                self.insert_song_genre(genre_model.id, 2).await?;
+            }
+        }
+
+        self.insert_song_youtube_playlist_id(youtube_playlist_id_res, 1)
+            .await?;
+
+        let vvec = self
+            .get_all_songs(PathBuf::from("/home/luqman/Music"))
+            .await?;
+        dbg!(vvec);
+
+        Ok(())
+    }
+}
+
+pub mod error {
+    use std::path::PathBuf;
+
+    use miette::Diagnostic;
+    use thiserror::Error;
+
+    #[derive(Error, Diagnostic, Debug)]
+    pub enum DatabaseError {
+        #[error(transparent)]
+        SeaOrm(#[from] sea_orm::DbErr),
+        #[error("The database isn't open")]
+        NotConnected,
+        #[error(
+            "The connection was used inside its own transaction, use the one passed to the closure"
+        )]
+        UsedInsideTransaction,
+        #[error("No song id was given")]
+        NoSongId,
+        #[error("No song was found with the associated ID")]
+        NoSongFound,
+        #[error("No playlist was found with the associated ID")]
+        NoPlaylistFound,
+        #[error("No album was found with the associated ID")]
+        NoAlbumFound,
+        #[error("No artist was found with the associated ID")]
+        NoArtistFound,
+        #[error(transparent)]
+        IoError(#[from] std::io::Error),
+        #[error("No change set was found with the associated ID")]
+        NoChangeSetFound,
+        #[error("This change set was already undone")]
+        ChangeSetAlreadyUndone,
+        #[error(transparent)]
+        TagError(#[from] crate::tags::error::TagError),
+        #[error(transparent)]
+        ImageError(#[from] image::ImageError),
+        #[error("The connection of a transaction was still in use after it ended")]
+        TransactionInUse,
+        #[error("A rating has to be between 0 and 5, got {0}")]
+        InvalidRating(i32),
+        #[error("The song is already in the target library")]
+        SongInTargetLibrary,
+        #[error(
+            "Found a database at both {} and {}, remove the one not in use",
+            .0.display(),
+            .1.display()
+        )]
+        DatabaseInBothLocations(PathBuf, PathBuf),
+        #[error("The database is in memory and can't be backed up")]
+        InMemoryDatabase,
+        #[error("The database at {} isn't in memory, test data only goes in memory", .0.display())]
+        NotInMemory(PathBuf),
+        #[error("No backup was found at {}", .0.display())]
+        NoBackupFound(PathBuf),
+        #[error("Song {0} isn't linked to artist {1}")]
+        SongArtistNotFound(i32, i32),
+        #[error("Song {0} isn't linked to album {1}")]
+        SongAlbumNotFound(i32, i32),
+        #[error("Song {0} has no label {1}")]
+        SongLabelNotFound(i32, String),
+        #[error("A label can't be empty")]
+        EmptyLabel,
+        #[error("Label {0} can't contain ';', it separates the labels in file tags")]
+        LabelSeparator(String),
+        #[error("Song {0} can't be a version of itself")]
+        SelfRelationship(i32),
+        #[error("Songs {0} and {1} aren't linked")]
+        SongRelationshipNotFound(i32, i32),
+        #[error("Song {0} is a version of the song but isn't in the target library, move or unlink it first")]
+        RelatedSongNotInTarget(i32),
+        #[error(transparent)]
+        Lock(#[from] crate::lock::error::LockError),
+        #[error(transparent)]
+        JsonError(#[from] serde_json::Error),
+        #[error("An exported album cover isn't valid base64: {0}")]
+        InvalidCover(#[from] base64::DecodeError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The library `test_db` fills: "Stellar Stellar" is song 1 and "Aquairo Palette" song 2,
+    /// neither has a file
+    async fn library() -> DbConnection {
+        let db = DbConnection::open_in_memory().await;
+        db.test_db().await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_db() {
+        DbConnection::in_memory_test().await.unwrap();
+    }
+
+    /// Number of statements `load` runs against `db`
+    async fn count_statements<F, Fut>(db: &mut DbConnection, load: F) -> usize
+    where
+        F: FnOnce(&DbConnection) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = count.clone();
+        match db.db.as_mut() {
+            Some(DbHandle::Connection(conn)) => conn.set_metric_callback(move |_| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }),
+            _ => unreachable!("tests use a connection"),
+        }
+        load(db).await;
+        count.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn loading_songs_takes_the_same_statements_for_any_library_size() {
+        let mut db = library().await;
+        let load = |db: &DbConnection| {
+            let db = db.clone();
+            async move {
+                assert!(!db.get_all_songs_gui(PathBuf::new()).await.is_empty());
+                db.get_all_songs(PathBuf::new()).await.unwrap();
+            }
+        };
+        let small = count_statements(&mut db, load).await;
+        for i in 0..100 {
+            db.insert_song(format!("song {i}"), Some(format!("id{i}")), None)
+                .await
+                .unwrap();
+        }
+        let large = count_statements(&mut db, load).await;
+        assert!(small > 0);
+        assert_eq!(small, large);
+    }
+
+    #[tokio::test]
+    async fn search_ranks_title_and_artist_matches() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let found = db.search("stellar suise", None, PathBuf::new()).await?;
+        assert_eq!(found.first().and_then(|song| song.id), Some(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn search_ignores_diacritics_and_limits() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let song = db
+            .insert_from_app_song(
+                AppSong::new()
+                    .with_title(Some("Halo".to_owned()))
+                    .with_artists_string("Beyoncé".to_owned())
+                    .with_yt_id(Some("bnVUHWCynig".to_owned())),
+            )
+            .await?;
+        for query in ["beyonce", "Beyoncé", "BEYON"] {
+            let found = db.search(query, None, PathBuf::new()).await?;
+            assert_eq!(
+                found.iter().map(|song| song.id).collect::<Vec<_>>(),
+                [song.id],
+                "{query}"
+            );
+        }
+
+        // both songs of the library are vtuber songs
+        assert_eq!(db.search("vtuber", None, PathBuf::new()).await?.len(), 2);
+        assert_eq!(db.search("vtuber", Some(1), PathBuf::new()).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_filters_sorts_and_pages() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let by_artist = SongQuery::new().with_artist("Minato Aqua");
+        let found = db.query_songs(&by_artist, PathBuf::new()).await?;
+        assert_eq!(
+            found.iter().map(|song| song.id).collect::<Vec<_>>(),
+            [Some(2)]
+        );
+
+        let in_playlist = SongQuery::new()
+            .with_genre("Jpop")
+            .with_youtube_playlist("PLFnrkmfz7sBLzcERljKHFXfVaplrh53AY");
+        assert_eq!(db.count_songs(&in_playlist).await?, 1);
+
+        let without_file = SongQuery::new()
+            .with_has_path(false)
+            .with_sort(SongSort::Title, true)
+            .with_limit(1)
+            .with_offset(1);
+        let found = db.query_songs(&without_file, PathBuf::new()).await?;
+        assert_eq!(
+            found.first().and_then(|song| song.title.clone()).as_deref(),
+            Some("Aquairo Palette")
+        );
+        assert_eq!(db.count_songs(&without_file).await?, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_by_youtube_id_uses_an_index() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let statement = sea_orm::QueryTrait::build(
+            &SongQuery::new().with_youtube_id("3TzMmsNx0JQ").select(),
+            DbBackend::Sqlite,
+        );
+        let plan = db
+            .ref_db()?
+            .query_all(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                format!("EXPLAIN QUERY PLAN {}", statement.sql),
+                statement.values.expect("the youtube id is bound"),
+            ))
+            .await?
+            .into_iter()
+            .filter_map(|row| row.try_get::<String>("", "detail").ok())
+            .collect::<Vec<_>>();
+        assert!(
+            plan.iter()
+                .any(|step| step.contains("USING INDEX") && step.contains("youtube_id")),
+            "{plan:?}"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_by_source() -> Result<(), DatabaseError> {
+        let db = library().await;
+        // the songs were inserted with a youtube id only
+        let from_youtube = SongQuery::new().with_source(Source::Youtube);
+        assert_eq!(db.count_songs(&from_youtube).await?, 2);
+
+        let url = "https://minatoaqua.bandcamp.com/track/aquairo-palette";
+        assert_eq!(Source::from_url(url), Source::Bandcamp);
+        db.set_song_source(2, Source::Bandcamp, None, Some(url.to_owned()))
+            .await?;
+        let from_bandcamp = SongQuery::new().with_source(Source::Bandcamp);
+        let found = db.query_songs_gui(&from_bandcamp, PathBuf::new()).await?;
+        assert_eq!(
+            found
+                .first()
+                .and_then(|song| song.get_source_url())
+                .as_deref(),
+            Some(url)
+        );
+        assert_eq!(db.count_songs(&from_youtube).await?, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn albums_are_told_apart_by_album_artist() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let queen = db
+            .insert_album_with_artist("Greatest Hits".to_string(), Some("Queen".to_string()))
+            .await?;
+        let abba = db
+            .insert_album_with_artist("Greatest Hits".to_string(), Some("ABBA".to_string()))
+            .await?;
+        assert_ne!(queen, abba);
+        let again = db
+            .insert_album_with_artist("Greatest Hits".to_string(), Some("Queen".to_string()))
+            .await?;
+        assert_eq!(again, queen);
+
+        db.insert_song_album(queen, 2).await?;
+        let album = db.get_album_with_tracks(queen, PathBuf::new()).await?;
+        assert_eq!(
+            album.tracks.iter().map(|song| song.id).collect::<Vec<_>>(),
+            [Some(2)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn songs_use_the_cover_of_their_album() -> Result<(), DatabaseError> {
+        let db = library().await;
+        assert_eq!(db.get_song_album_cover(2).await?, None);
+
+        let album = db
+            .insert_album_with_artist("Stellar Stellar".to_string(), Some("Suisei".to_string()))
+            .await?;
+        db.insert_song_album(album, 2).await?;
+        let mut jpeg = vec![];
+        image::RgbImage::new(2, 2)
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .expect("can encode jpeg");
+        db.set_album_cover(album, &jpeg).await?;
+
+        let cover = db
+            .get_song_album_cover(2)
+            .await?
+            .expect("album has a cover");
+        assert_eq!(Some(cover.clone()), db.get_album_cover(album).await?);
+        assert_eq!(
+            image::guess_format(&cover).expect("cover is an image"),
+            image::ImageFormat::Png
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merged_artists_become_aliases() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let suisei = db.insert_artist("Hoshimashi Suisei".to_string()).await?;
+        let mut duplicates = vec![];
+        for artist in ["Comet-chan", "Sui-chan"] {
+            duplicates.push(db.insert_artist(artist.to_string()).await?);
+        }
+        db.merge_artists(suisei, duplicates, ChangeOrigin::Cli, PathBuf::new())
+            .await?;
+        assert_eq!(db.insert_artist("Comet-chan".to_string()).await?, suisei);
+        let mut aliases = db.get_artist_aliases(suisei).await?;
+        aliases.sort();
+        assert_eq!(aliases, ["Comet-chan", "Sui-chan"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn channel_spellings_resolve_to_the_merged_artist() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let artist = db.insert_artist("Hoshimachi Suisei".to_string()).await?;
+        let topic = db
+            .insert_artist("Hoshimachi Suisei - Topic".to_string())
+            .await?;
+        assert_ne!(topic, artist);
+        db.merge_artists(artist, vec![topic], ChangeOrigin::Cli, PathBuf::new())
+            .await?;
+
+        for spelling in [
+            "HoshimachiSuiseiVEVO",
+            "hoshimachi suisei",
+            "HOSHIMACHI SUISEI - Topic",
         ] {
-            let tb = format!("https://i.ytimg.com/vi_webp/{yt_id}/maxresdefault.webp");
-            self.insert_song(
-                title.to_owned(),
-                Some(yt_id.to_owned()),
-                Some(tb.to_owned()),
+            assert_eq!(db.insert_artist(spelling.to_string()).await?, artist);
+        }
+        // artists nothing was merged into keep telling spellings apart
+        let other = db.insert_artist("Shirakami Fubuki".to_string()).await?;
+        assert_ne!(
+            db.insert_artist("shirakami fubuki".to_string()).await?,
+            other
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn undoing_a_merge_brings_the_duplicates_back() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let before = db.snapshot_song(1).await?;
+        let artists = db.get_song_gui(1, PathBuf::new()).await?.get_artists_vec();
+        let canonical = db.insert_artist("Canonical".to_string()).await?;
+        let duplicates = {
+            let mut ids = vec![];
+            for name in &artists {
+                ids.push(db.find_artist(name).await?.expect("song artist exists").id);
+            }
+            ids
+        };
+        db.merge_artists(canonical, duplicates, ChangeOrigin::Cli, PathBuf::new())
+            .await?;
+        assert_eq!(
+            db.get_song_gui(1, PathBuf::new()).await?.get_artists_vec(),
+            ["Canonical"]
+        );
+
+        let (change_set, changes) = db.get_change_sets(1).await?.remove(0);
+        assert_eq!(change_set.origin, ChangeOrigin::Cli);
+        assert!(changes
+            .iter()
+            .all(|change| change.field == SongField::Artists));
+        db.undo_change_set(change_set.id, PathBuf::new()).await?;
+
+        assert_eq!(
+            db.get_song_gui(1, PathBuf::new()).await?.get_artists_vec(),
+            artists
+        );
+        assert_eq!(db.snapshot_song(1).await?.values, before.values);
+        assert!(db.get_artist_aliases(canonical).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_transaction_rolls_back() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let song_count = SongEntity::find().count(db.ref_db()?).await?;
+        let result = db
+            .transaction(|db| async move {
+                db.insert_from_app_song(
+                    AppSong::new()
+                        .with_title(Some("Rolled Back".to_string()))
+                        .with_artists_string("Nobody".to_string()),
+                )
+                .await?;
+                Err::<(), _>(DatabaseError::NoSongFound)
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(SongEntity::find().count(db.ref_db()?).await?, song_count);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn using_the_connection_inside_its_transaction_fails() {
+        let db = library().await;
+        let result = db
+            .transaction(|_| async { db.insert_artist("Nobody".to_owned()).await })
+            .await;
+        assert!(matches!(result, Err(DatabaseError::UsedInsideTransaction)));
+    }
+
+    #[tokio::test]
+    async fn transactions_take_the_write_lock_first() -> Result<(), DatabaseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("database.sqlite");
+        let db = DbConnection::with_backups(path.clone(), 0).await?;
+        // another process that fails right away instead of waiting for the lock
+        let other_write = |name: &str| {
+            let options = SqliteConnectOptions::new()
+                .filename(&path)
+                .busy_timeout(std::time::Duration::ZERO);
+            let model = artist::ActiveModel {
+                name: ActiveValue::Set(name.to_owned()),
+                ..Default::default()
+            };
+            async move {
+                let pool = sqlx::SqlitePool::connect_with(options)
+                    .await
+                    .map_err(|e| DbErr::Conn(RuntimeErr::SqlxError(e)))?;
+                let other = SqlxSqliteConnector::from_sqlx_sqlite_pool(pool);
+                let result = Artist::insert(model).exec(&other).await;
+                other.close().await?;
+                result
+            }
+        };
+
+        db.transaction(|db| async move {
+            let artists = Artist::find().count(db.ref_db()?).await?;
+            // nothing was written yet, the transaction holds the lock all the same
+            let blocked = other_write("Shirakami Fubuki")
+                .await
+                .expect_err("the write waits for the lock");
+            assert!(blocked.to_string().contains("database is locked"));
+            db.insert_artist(format!("Artist {artists}")).await?;
+            Ok(())
+        })
+        .await?;
+        other_write("Ookami Mio").await?;
+        assert_eq!(Artist::find().count(db.ref_db()?).await?, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn content_hash_is_kept_while_the_file_is_unchanged() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let stamp = FileStamp {
+            size: 4_000_000,
+            modified_ms: 1_698_000_000_000,
+        };
+        let mut song = db.get_song_gui(1, PathBuf::new()).await?;
+        song.set_path(PathBuf::from("Stellar Stellar.opus"));
+        song.set_content_hash("abc".to_owned());
+        song.set_content_hash_stamp(Some(stamp));
+        db.update_file_info(&song).await?;
+
+        let path = "Stellar Stellar.opus";
+        assert_eq!(
+            db.get_unchanged_content_hash(path, stamp).await?.as_deref(),
+            Some("abc")
+        );
+        let retagged = FileStamp {
+            modified_ms: stamp.modified_ms + 1,
+            ..stamp
+        };
+        assert_eq!(db.get_unchanged_content_hash(path, retagged).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clean_orphans_removes_unused_rows() -> Result<(), DatabaseError> {
+        let db = library().await;
+        // "AYAYA" and "Sui-chan" have no songs
+        let sui_chan = db.insert_artist("Sui-chan".to_string()).await?;
+        db.add_artist_alias(sui_chan, "Suisei".to_string()).await?;
+        let orphans = db.clean_orphans(true).await?;
+        assert_eq!(orphans.albums.len(), 1);
+        assert_eq!(orphans.artists.len(), 1);
+        // a dry run changes nothing
+        assert_eq!(db.clean_orphans(true).await?.albums.len(), 1);
+        db.clean_orphans(false).await?;
+        assert!(db.clean_orphans(true).await?.is_empty());
+        assert_eq!(ArtistAlias::find().count(db.ref_db()?).await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn orphans_are_removed_after_deletes_and_relinks() -> Result<(), DatabaseError> {
+        let db = library().await.with_auto_clean_orphans(true);
+        let removed = db
+            .insert_from_app_song(
+                AppSong::new()
+                    .with_title(Some("Removed".to_string()))
+                    .with_artists_string("Nobody".to_string()),
             )
             .await?;
+        db.delete_song_from_app_song(removed).await?;
+        assert_eq!(db.find_artist("Nobody").await?, None);
+
+        let song = SongEntity::find_by_id(1)
+            .one(db.ref_db()?)
+            .await?
+            .ok_or(DatabaseError::NoSongFound)?;
+        let previous = db.get_song_gui(1, PathBuf::new()).await?.get_artists_vec();
+        let replaced = ArtistModel {
+            name: "Replaced".to_string(),
+            ..Default::default()
+        };
+        db.update_song_artists_links(song, vec![replaced]).await?;
+        for name in previous {
+            assert_eq!(db.find_artist(&name).await?, None);
         }
+        assert!(db.clean_orphans(true).await?.is_empty());
+        Ok(())
+    }
 
-        for artist in ["Hoshimashi Suisei", "Comet-chan"] {
-            if let Some(artist_id) = Artist::find()
-                .filter(artist::Column::Name.eq(artist))
-                .one(self.ref_db())
-                .await?
-            {
-                self.insert_song_artist(artist_id.id, 1).await?;
-            }
+    #[tokio::test]
+    async fn song_links_cascade_on_delete() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let removed = db
+            .insert_from_app_song(
+                AppSong::new()
+                    .with_title(Some("Removed".to_string()))
+                    .with_artists_string("Nobody".to_string()),
+            )
+            .await?
+            .id
+            .ok_or(DatabaseError::NoSongId)?;
+        let nobody = db.insert_artist("Nobody".to_string()).await?;
+        assert!(matches!(
+            db.remove_song_artist(1, nobody).await,
+            Err(DatabaseError::SongArtistNotFound(1, _))
+        ));
+
+        let playlist_id = db.create_playlist("Removed".to_string(), None).await?;
+        db.add_song_to_playlist(playlist_id, removed).await?;
+        db.set_lyrics(removed, &SongLyrics::from_lrc("[00:01.00]gone"))
+            .await?;
+        db.set_download_provenance(removed, &provenance()).await?;
+
+        db.delete_song_from_app_song(AppSong::new().with_id(Some(removed)))
+            .await?;
+        assert_eq!(
+            SongArtistJunction::find()
+                .filter(song_artist_junction::Column::SongId.eq(removed))
+                .count(db.ref_db()?)
+                .await?,
+            0
+        );
+        assert!(db.get_playlist_songs(playlist_id).await?.is_empty());
+        assert!(db.get_lyrics(removed).await?.is_none());
+        assert!(db.get_download_provenance(removed).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn playlist_songs_can_be_reordered() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let playlist_id = db.create_playlist("Hololive".to_string(), None).await?;
+        for song_id in [1, 2] {
+            db.add_song_to_playlist(playlist_id, song_id).await?;
         }
+        db.move_playlist_song(playlist_id, 1, 0).await?;
+        let songs = db.get_playlist_songs(playlist_id).await?;
+        assert_eq!(songs.iter().map(|song| song.id).collect::<Vec<_>>(), [2, 1]);
+
+        assert!(matches!(
+            db.add_song_to_playlist(playlist_id, 100).await,
+            Err(DatabaseError::NoSongFound)
+        ));
+        assert!(matches!(
+            db.add_song_to_playlist(playlist_id + 1, 1).await,
+            Err(DatabaseError::NoPlaylistFound)
+        ));
+        Ok(())
+    }
 
-        for artist in ["Minato Aqua"] {
-            if let Some(artist_id) = Artist::find()
-                .filter(artist::Column::Name.eq(artist))
-                .one(self.ref_db())
-                .await?
-            {
-                self.insert_song_artist(artist_id.id, 2).await?;
-            }
+    #[tokio::test]
+    async fn m3u8_export_reports_songs_without_a_file() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let music_dir = tempfile::tempdir()?;
+        let mut song: song::ActiveModel = SongEntity::find_by_id(2)
+            .one(db.ref_db()?)
+            .await?
+            .unwrap()
+            .into();
+        song.path = ActiveValue::Set(Some("aquairo.mp3".to_owned()));
+        song.update(db.ref_db()?).await?;
+        let playlist_id = db.create_playlist("Hololive".to_string(), None).await?;
+        for song_id in [1, 2] {
+            db.add_song_to_playlist(playlist_id, song_id).await?;
         }
 
-        for album in ["Still Still Stellar"] {
-            if let Some(album_model) = Album::find()
-                .filter(album::Column::Name.eq(album))
-                .one(self.ref_db())
-                .await?
-            {
-                self.insert_song_album(album_model.id, 1).await?;
-            }
+        let export = db
+            .export_playlist_m3u8(playlist_id, music_dir.path().to_owned())
+            .await?;
+        assert_eq!(
+            export
+                .skipped
+                .iter()
+                .map(|song| song.id)
+                .collect::<Vec<_>>(),
+            [1]
+        );
+        let m3u8 = std::fs::read_to_string(export.path)?;
+        assert!(m3u8.ends_with("aquairo.mp3\n"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn youtube_playlist_positions_close_gaps() -> Result<(), DatabaseError> {
+        let db = library().await;
+        for song_id in [1, 2] {
+            db.add_song_to_youtube_playlist(song_id, "PLhololive".to_string())
+                .await?;
         }
+        let youtube_playlist = db
+            .get_youtube_playlist_id("PLhololive")
+            .await?
+            .ok_or(DatabaseError::NoPlaylistFound)?;
+        db.remove_song_from_youtube_playlist(1, youtube_playlist.id)
+            .await?;
+        let entries = db.get_youtube_playlist_songs(youtube_playlist.id).await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].0.position, entries[0].1.id), (0, 2));
+        Ok(())
+    }
 
-        for album in ["Minato Aqua Originals"] {
-            if let Some(album_model) = Album::find()
-                .filter(album::Column::Name.eq(album))
-                .one(self.ref_db())
-                .await?
-            {
-                self.insert_song_album(album_model.id, 2).await?;
-            }
+    #[tokio::test]
+    async fn play_stats_are_stored() -> Result<(), DatabaseError> {
+        let db = library().await;
+        db.set_rating(2, Some(4), PathBuf::new()).await?;
+        assert!(matches!(
+            db.set_rating(2, Some(6), PathBuf::new()).await,
+            Err(DatabaseError::InvalidRating(6))
+        ));
+        db.set_favourite(2, true, PathBuf::new()).await?;
+        db.record_play(2, PathBuf::new()).await?;
+        let favourites = db.get_favourite_songs().await?;
+        assert_eq!(favourites.len(), 1);
+        assert_eq!(
+            (favourites[0].rating, favourites[0].play_count),
+            (Some(4), 1)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn synced_lyrics_round_trip() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let lrc = "[ar:Hoshimachi Suisei]\n[00:01.50]Stellar\n[00:12.00][00:03.25]Stellar Stellar";
+        db.set_lyrics(2, &SongLyrics::from_lrc(lrc)).await?;
+        let lyrics = db.get_lyrics(2).await?.ok_or(DatabaseError::NoSongFound)?;
+        assert_eq!(lyrics.synced.as_ref().map(Vec::len), Some(3));
+        assert_eq!(
+            lyrics.to_lrc().as_deref(),
+            Some("[00:01.50]Stellar\n[00:03.25]Stellar Stellar\n[00:12.00]Stellar Stellar")
+        );
+        assert!(!db.set_lyrics_if_missing(2, &lyrics).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn lrc_keeps_untimed_lines() {
+        let lrc = "[ti:Stellar Stellar]\n[Intro]\n[00:01.50]Stellar\n(Stellar)\n\n[00:03.25]Stellar Stellar";
+        let lyrics = SongLyrics::from_lrc(lrc);
+        assert_eq!(
+            lyrics.plain,
+            "[Intro]\nStellar\n(Stellar)\n\nStellar Stellar"
+        );
+        assert_eq!(lyrics.synced.as_ref().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn sidecar_fills_in_missing_lyrics() {
+        let embedded = SongLyrics::from_plain("Stellar Stellar".to_owned());
+        let sidecar = SongLyrics::from_lrc("[00:01.00]Stellar");
+        let lyrics = embedded.fill_from(sidecar.clone());
+        assert_eq!(lyrics.plain, "Stellar Stellar");
+        assert_eq!(lyrics.synced, sidecar.synced);
+
+        let lyrics = SongLyrics::default().fill_from(sidecar.clone());
+        assert_eq!(lyrics, sidecar);
+    }
+
+    fn provenance() -> Provenance {
+        Provenance {
+            url: Source::Youtube.url_for("xGihoycGivE"),
+            uploader_id: Some("UC1opHUrw8rvnsadT-iGp7Cg".to_owned()),
+            upload_date: Some("20200815".to_owned()),
+            format_id: Some("251".to_owned()),
+            extractor: Some("Youtube".to_owned()),
+            ytdlp_version: Some("2023.10.13".to_owned()),
+            downloaded_at: unix_now(),
         }
+    }
 
-        for genre in ["Vtuber", "Jpop"] {
-            if let Some(genre_model) = Genre::find()
-                .filter(genre::Column::Genre.eq(genre))
-                .one(self.ref_db())
-                .await?
-            {
-                self.insert_song_genre(genre_model.id, 1).await?;
-                self.insert_song_genre(genre_model.id, 2).await?;
-            }
+    #[tokio::test]
+    async fn download_provenance_is_stored() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let provenance = provenance();
+        db.set_download_provenance(2, &provenance).await?;
+        assert_eq!(
+            db.get_download_provenance(2).await?.as_ref(),
+            Some(&provenance)
+        );
+        assert_eq!(db.get_download_provenance(1).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn labels_are_trimmed_and_deduplicated() -> Result<(), DatabaseError> {
+        let db = library().await;
+        db.add_song_label(2, " live ".to_owned(), None).await?;
+        db.add_song_label(2, "live".to_owned(), None).await?;
+        assert_eq!(db.get_labels().await?.len(), 1);
+        let labelled = db.get_label_songs("live", PathBuf::new()).await?;
+        assert_eq!(labelled.len(), 1);
+        assert_eq!(labelled[0].get_labels_vec(), vec!["live".to_owned()]);
+        assert!(matches!(
+            db.remove_song_label(1, "live", None).await,
+            Err(DatabaseError::SongLabelNotFound(1, _))
+        ));
+        assert!(matches!(
+            db.add_song_label(1, " ".to_owned(), None).await,
+            Err(DatabaseError::EmptyLabel)
+        ));
+        assert!(matches!(
+            db.add_song_label(1, "gym; focus".to_owned(), None).await,
+            Err(DatabaseError::LabelSeparator(_))
+        ));
+        assert!(matches!(
+            db.add_song_label(99, "focus".to_owned(), None).await,
+            Err(DatabaseError::NoSongFound)
+        ));
+        assert_eq!(db.get_labels().await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn song_relationships_link_both_ways() -> Result<(), DatabaseError> {
+        let db = library().await;
+        db.link_songs(2, 1, RelationshipKind::Live).await?;
+        // linking again in the other direction replaces the link
+        db.link_songs(1, 2, RelationshipKind::Remix).await?;
+        assert!(matches!(
+            db.link_songs(1, 1, RelationshipKind::Cover).await,
+            Err(DatabaseError::SelfRelationship(1))
+        ));
+        let related = db.get_song_relationships(2).await?;
+        assert_eq!(related.len(), 1);
+        assert_eq!(
+            (related[0].song_id, related[0].kind, related[0].original),
+            (1, RelationshipKind::Remix, false)
+        );
+        assert!(db.get_song_relationships(1).await?[0].original);
+        assert_eq!(db.get_song_versions(2, PathBuf::new()).await?.len(), 2);
+        assert!(matches!(
+            db.unlink_songs(2, 99).await,
+            Err(DatabaseError::SongRelationshipNotFound(2, 99))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn versions_linked_through_another_song_are_described() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let live = db
+            .insert_from_app_song(AppSong::new().with_title(Some("Aquairo Palette (Live)".into())))
+            .await?
+            .id
+            .ok_or(DatabaseError::NoSongId)?;
+        db.link_songs(live, 2, RelationshipKind::Live).await?;
+        db.link_songs(2, 1, RelationshipKind::Remix).await?;
+
+        assert_eq!(
+            db.describe_song_versions(1).await?,
+            [
+                (2, "remix: Aquairo Palette".to_string()),
+                (live, "version: Aquairo Palette (Live)".to_string()),
+            ]
+        );
+        assert_eq!(db.describe_song_versions(2).await?.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn export_import_round_trip() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let lrc = "[00:01.50]Stellar";
+        db.set_lyrics(2, &SongLyrics::from_lrc(lrc)).await?;
+        db.set_download_provenance(2, &provenance()).await?;
+        db.add_song_label(2, "live".to_owned(), None).await?;
+        db.link_songs(1, 2, RelationshipKind::Remix).await?;
+        let playlist_id = db.create_playlist("Hololive".to_string(), None).await?;
+        for song_id in [1, 2] {
+            db.add_song_to_playlist(playlist_id, song_id).await?;
         }
+        let album = db.get_song_albums(1).await?.remove(0);
+        let mut png = vec![];
+        image::RgbImage::new(1, 1)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("can encode png");
+        db.set_album_cover(album.id, &png).await?;
+        let aqua = db.insert_artist("Minato Aqua".to_owned()).await?;
+        db.add_artist_alias(aqua, "Aqua".to_owned()).await?;
+
+        let mut json = vec![];
+        db.export_library()
+            .await?
+            .write_json(&mut json)
+            .expect("can write json");
+        let export = LibraryExport::read_json(json.as_slice()).expect("can read json");
+        let imported = DbConnection::open_in_memory().await;
+        crate::migrator::Migrator::up(imported.ref_db()?, None).await?;
+        let report = imported.import_library(&export, PathBuf::new()).await?;
+        assert_eq!(report.songs_inserted, export.songs.len());
+
+        let reexport = imported.export_library().await?;
+        assert_eq!(reexport.songs.len(), export.songs.len());
+        assert_eq!(reexport.songs[1].lyrics, export.songs[1].lyrics);
+        assert_eq!(reexport.songs[1].provenance, export.songs[1].provenance);
+        assert_eq!(reexport.songs[1].labels, vec!["live".to_owned()]);
+        assert_eq!(reexport.relationships, export.relationships);
+        assert_eq!(reexport.playlists[0].song_ids.len(), 2);
+        assert_eq!(reexport.album_covers, export.album_covers);
+        assert_eq!(reexport.album_covers.len(), 1);
+        assert_eq!(reexport.artist_aliases, export.artist_aliases);
+        assert_eq!(reexport.artist_aliases.len(), 1);
+
+        // importing again finds every song
+        let report = imported.import_library(&export, PathBuf::new()).await?;
+        assert_eq!(report.songs_inserted, 0);
+        Ok(())
+    }
 
-        self.insert_song_youtube_playlist_id(youtube_playlist_id_res, 1)
-            .await?;
+    #[tokio::test]
+    async fn import_finds_songs_by_audio_then_title_and_artists() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let export = db.export_library().await?;
+        let mut moved = export.songs[0].clone();
+        moved.content_hash = Some("same audio".to_owned());
+        let mut song: song::ActiveModel = SongEntity::find_by_id(moved.id)
+            .one(db.ref_db()?)
+            .await?
+            .unwrap()
+            .into();
+        song.content_hash = ActiveValue::Set(moved.content_hash.clone());
+        song.update(db.ref_db()?).await?;
+
+        // the file was renamed and the video taken down since
+        moved.path = Some("renamed.mp3".to_owned());
+        moved.youtube_id = None;
+        let found = db.find_imported_song(&moved).await?;
+        assert_eq!(found.map(|song| song.id), Some(moved.id));
+
+        // nothing to go by but the title and artists
+        let mut typed = export.songs[1].clone();
+        typed.youtube_id = None;
+        let found = db.find_imported_song(&typed).await?;
+        assert_eq!(found.map(|song| song.id), Some(typed.id));
+        typed.artists.push("Minato Aqua".to_owned());
+        assert!(db.find_imported_song(&typed).await?.is_none());
+        typed.artists.pop();
+        typed.content_hash = Some("other audio".to_owned());
+        assert!(db.find_imported_song(&typed).await?.is_some());
+        typed.youtube_id = Some("another upload".to_owned());
+        assert!(db.find_imported_song(&typed).await?.is_none());
+        Ok(())
+    }
 
-        let vvec = self
-            .get_all_songs(PathBuf::from("/home/luqman/Music"))
+    #[tokio::test]
+    async fn move_refuses_songs_already_in_target() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let export = db.export_library().await?;
+        let target = DbConnection::open_in_memory().await;
+        crate::migrator::Migrator::up(target.ref_db()?, None).await?;
+        target.import_library(&export, PathBuf::new()).await?;
+        assert!(matches!(
+            db.move_song_to_library(1, PathBuf::new(), &target, PathBuf::new())
+                .await,
+            Err(DatabaseError::SongInTargetLibrary)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn move_carries_the_song_and_its_file() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let (music_dir, target_music_dir) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        std::fs::write(music_dir.path().join("song.mp3"), b"audio")?;
+        let mut song: song::ActiveModel = SongEntity::find_by_id(2)
+            .one(db.ref_db()?)
+            .await?
+            .unwrap()
+            .into();
+        song.path = ActiveValue::Set(Some("song.mp3".to_owned()));
+        song.update(db.ref_db()?).await?;
+        db.add_song_label(2, "live".to_owned(), None).await?;
+        db.link_songs(1, 2, RelationshipKind::Remix).await?;
+        let target = DbConnection::open_in_memory().await;
+        crate::migrator::Migrator::up(target.ref_db()?, None).await?;
+        let dirs = (
+            music_dir.path().to_owned(),
+            target_music_dir.path().to_owned(),
+        );
+
+        // the remix would lose its original
+        assert!(matches!(
+            db.move_song_to_library(2, dirs.0.clone(), &target, dirs.1.clone())
+                .await,
+            Err(DatabaseError::RelatedSongNotInTarget(1))
+        ));
+        db.unlink_songs(1, 2).await?;
+
+        // a file in the way leaves everything as it was
+        std::fs::write(target_music_dir.path().join("song.mp3"), b"other")?;
+        assert!(db
+            .move_song_to_library(2, dirs.0.clone(), &target, dirs.1.clone())
+            .await
+            .is_err());
+        assert!(music_dir.path().join("song.mp3").exists());
+        assert!(SongEntity::find_by_id(2).one(db.ref_db()?).await?.is_some());
+        assert!(SongEntity::find().all(target.ref_db()?).await?.is_empty());
+        std::fs::remove_file(target_music_dir.path().join("song.mp3"))?;
+
+        let new_id = db.move_song_to_library(2, dirs.0, &target, dirs.1).await?;
+        assert!(!music_dir.path().join("song.mp3").exists());
+        assert_eq!(
+            std::fs::read(target_music_dir.path().join("song.mp3"))?,
+            b"audio"
+        );
+        assert!(SongEntity::find_by_id(2).one(db.ref_db()?).await?.is_none());
+        let labels = target.get_song_labels(new_id).await?;
+        assert_eq!(labels[0].name, "live");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn undo_reverts_an_edit() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let song = db
+            .get_song_gui(1, PathBuf::new())
+            .await?
+            .set_title("Stellar Stellar (Remix)".to_string());
+        db.update_all_from_gui_song(song, ChangeOrigin::Cli).await?;
+        let change_sets = db.get_change_sets(1).await?;
+        assert_eq!(change_sets.len(), 1);
+        let (change_set, changes) = &change_sets[0];
+        assert_eq!(changes.len(), 1);
+
+        db.undo_change_set(change_set.id, PathBuf::new()).await?;
+        let song = db.get_song_gui(1, PathBuf::new()).await?;
+        assert_eq!(song.title.as_deref(), Some("Stellar Stellar"));
+        assert!(db.get_change_sets(1).await?[0].0.undone);
+        assert!(matches!(
+            db.undo_change_set(change_set.id, PathBuf::new()).await,
+            Err(DatabaseError::ChangeSetAlreadyUndone)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn undo_restores_albums_by_album_artist() -> Result<(), DatabaseError> {
+        let db = library().await;
+        let album = db
+            .insert_album_with_artist(
+                "Still Still Stellar".to_string(),
+                Some("Suisei".to_string()),
+            )
             .await?;
-        dbg!(vvec);
+        // an album of the same name without an album artist
+        db.insert_album("Still Still Stellar".to_string()).await?;
+        SongAlbumJunction::delete_many()
+            .filter(song_album_junction::Column::SongId.eq(1))
+            .exec(db.ref_db()?)
+            .await?;
+        db.insert_song_album(album, 1).await?;
+
+        let song = db
+            .get_song_gui(1, PathBuf::new())
+            .await?
+            .set_albums(vec![AlbumModel {
+                name: "Specialz".to_string(),
+                ..Default::default()
+            }]);
+        db.update_all_from_gui_song(song, ChangeOrigin::Cli).await?;
+        let (change_set, changes) = &db.get_change_sets(1).await?[0];
+        assert_eq!(
+            changes[0].old_value.as_deref(),
+            Some(r#"[{"name":"Still Still Stellar","album_artist":"Suisei"}]"#)
+        );
 
+        db.undo_change_set(change_set.id, PathBuf::new()).await?;
+        let albums = db.get_song_albums(1).await?;
+        assert_eq!(albums.iter().map(|a| a.id).collect::<Vec<_>>(), [album]);
         Ok(())
     }
-}
 
-pub mod error {
-    use miette::Diagnostic;
-    use thiserror::Error;
+    #[tokio::test]
+    async fn test_backup_restore() -> Result<(), DatabaseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("database.sqlite");
+
+        // a new database has nothing to back up
+        let db = DbConnection::with_backups(path.clone(), 2).await?;
+        assert!(DbConnection::list_backups(&path)?.is_empty());
+        db.insert_artist("Minato Aqua".to_owned()).await?;
+        crate::migrator::Migrator::down(db.ref_db()?, Some(1)).await?;
+        drop(db);
+
+        // reopening applies the reverted migration again, after a backup
+        let db = DbConnection::with_backups(path.clone(), 2).await?;
+        let backups = DbConnection::list_backups(&path)?;
+        assert_eq!(backups.len(), 1);
+        db.insert_artist("Hoshimashi Suisei".to_owned()).await?;
+        drop(db);
+
+        let previous = DbConnection::restore(&path, &backups[0], 2).await?;
+        assert!(previous.is_some());
+        let db = DbConnection::with_backups(path.clone(), 2).await?;
+        let artists = Artist::find().all(db.ref_db()?).await?;
+        assert_eq!(artists.len(), 1);
+
+        // the restored database was migrated again, only the newest backups are kept
+        assert_eq!(DbConnection::list_backups(&path)?.len(), 2);
+        let newest = db.backup(2).await?;
+        let backups = DbConnection::list_backups(&path)?;
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0], newest);
+        Ok(())
+    }
 
-    #[derive(Error, Diagnostic, Debug)]
-    pub enum DatabaseError {
-        #[error(transparent)]
-        SeaOrm(#[from] sea_orm::DbErr),
-        #[error("No song id was given")]
-        NoSongId,
-        #[error("No song was found with the associated ID")]
-        NoSongFound,
+    #[tokio::test]
+    async fn two_connections_share_a_database() -> Result<(), DatabaseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("database.sqlite");
+        let db = DbConnection::with_backups(path.clone(), 0).await?;
+
+        // another process may have the library open, in WAL mode neither blocks the other
+        let journal_mode = db
+            .ref_db()?
+            .query_one(Statement::from_string(
+                DbBackend::Sqlite,
+                "PRAGMA journal_mode;",
+            ))
+            .await?
+            .and_then(|row| row.try_get::<String>("", "journal_mode").ok());
+        assert_eq!(journal_mode.as_deref(), Some("wal"));
+        let other = DbConnection::with_backups(path, 0).await?;
+        let artists = Artist::find().count(other.ref_db()?).await?;
+        other.insert_artist("Shirakami Fubuki".to_owned()).await?;
+        db.insert_artist("Houshou Marine".to_owned()).await?;
+        assert_eq!(Artist::find().count(db.ref_db()?).await?, artists + 2);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::database::DbConnection;
+    #[tokio::test]
+    async fn restore_refuses_an_open_database() -> Result<(), DatabaseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("database.sqlite");
+        let db = DbConnection::with_backups(path.clone(), 2).await?;
+        let backup = db.backup(2).await?;
+
+        assert!(matches!(
+            DbConnection::restore(&path, &backup, 2).await,
+            Err(DatabaseError::Lock(_))
+        ));
+        drop(db);
+
+        // nor can it be opened while being restored
+        let lock = DatabaseLock::try_exclusive(&path, "restoring it")?;
+        assert!(matches!(
+            DbConnection::with_backups(path.clone(), 2).await,
+            Err(DatabaseError::Lock(_))
+        ));
+        drop(lock);
+        DbConnection::restore(&path, &backup, 2).await?;
+        Ok(())
+    }
 
     #[tokio::test]
-    async fn test_db() {
-        DbConnection::in_memory_test().await.unwrap();
+    async fn test_db_refuses_a_database_file() -> Result<(), DatabaseError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("database.sqlite");
+        let db = DbConnection::with_backups(path, 0).await?;
+        db.insert_artist("Minato Aqua".to_owned()).await?;
+
+        assert!(matches!(
+            db.test_db().await,
+            Err(DatabaseError::NotInMemory(_))
+        ));
+        assert_eq!(Artist::find().count(db.ref_db()?).await?, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn relocate_refuses_an_open_database() -> Result<(), DatabaseError> {
+        let dir = tempfile::tempdir()?;
+        let from = dir.path().join("music").join("database.sqlite");
+        let to = dir.path().join("data").join("database.sqlite");
+        let db = DbConnection::with_backups(from.clone(), 0).await?;
+        db.insert_artist("Minato Aqua".to_owned()).await?;
+
+        assert!(matches!(
+            DbConnection::relocate(&from, &to),
+            Err(DatabaseError::Lock(_))
+        ));
+        drop(db);
+
+        assert!(DbConnection::relocate(&from, &to)?);
+        assert!(!DatabaseLock::path(&from).exists());
+        let db = DbConnection::with_backups(to, 0).await?;
+        assert_eq!(Artist::find().count(db.ref_db()?).await?, 1);
+        Ok(())
     }
 }
@@ -10,6 +10,8 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub name: String,
+    pub album_artist: Option<String>,
+    pub year: Option<i32>,
 }
 
 impl Default for Model {
@@ -17,12 +19,16 @@ impl Default for Model {
         Self {
             id: 0,
             name: "Unknown".to_string(),
+            album_artist: None,
+            year: None,
         }
     }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_one = "super::album_cover::Entity")]
+    AlbumCover,
     #[sea_orm(has_many = "super::song_album_junction::Entity")]
     SongAlbumJunction,
     #[sea_orm(has_many = "super::song::Entity")]
@@ -45,4 +51,10 @@ impl Related<super::song_album_junction::Entity> for Entity {
     }
 }
 
+impl Related<super::album_cover::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AlbumCover.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
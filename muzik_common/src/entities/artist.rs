@@ -26,6 +26,8 @@ impl Default for Model {
 pub enum Relation {
     #[sea_orm(has_many = "super::song_artist_junction::Entity")]
     SongArtistJunction,
+    #[sea_orm(has_many = "super::artist_alias::Entity")]
+    ArtistAlias,
 }
 
 impl Related<super::song::Entity> for Entity {
@@ -44,4 +46,10 @@ impl Related<super::song_artist_junction::Entity> for Entity {
     }
 }
 
+impl Related<super::artist_alias::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ArtistAlias.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -3,11 +3,22 @@
 pub mod prelude;
 
 pub mod album;
+pub mod album_cover;
 pub mod artist;
+pub mod artist_alias;
+pub mod change_set;
+pub mod field_change;
 pub mod genre;
+pub mod label;
+pub mod lyrics;
+pub mod playlist;
+pub mod playlist_song;
 pub mod song;
 pub mod song_album_junction;
 pub mod song_artist_junction;
+pub mod song_download;
 pub mod song_genre_junction;
+pub mod song_label_junction;
+pub mod song_relationship;
 pub mod song_youtube_playlist_id_junction;
 pub mod youtube_playlist_id;
@@ -1,11 +1,22 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
 pub use super::album::Entity as Album;
+pub use super::album_cover::Entity as AlbumCover;
 pub use super::artist::Entity as Artist;
+pub use super::artist_alias::Entity as ArtistAlias;
+pub use super::change_set::Entity as ChangeSet;
+pub use super::field_change::Entity as FieldChange;
 pub use super::genre::Entity as Genre;
+pub use super::label::Entity as Label;
+pub use super::lyrics::Entity as Lyrics;
+pub use super::playlist::Entity as Playlist;
+pub use super::playlist_song::Entity as PlaylistSong;
 pub use super::song::Entity as SongEntity;
 pub use super::song_album_junction::Entity as SongAlbumJunction;
 pub use super::song_artist_junction::Entity as SongArtistJunction;
+pub use super::song_download::Entity as SongDownload;
 pub use super::song_genre_junction::Entity as SongGenreJunction;
+pub use super::song_label_junction::Entity as SongLabelJunction;
+pub use super::song_relationship::Entity as SongRelationship;
 pub use super::song_youtube_playlist_id_junction::Entity as SongYoutubePlaylistIdJunction;
 pub use super::youtube_playlist_id::Entity as YoutubePlaylistId;
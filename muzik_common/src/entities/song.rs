@@ -5,6 +5,73 @@ use sea_orm::entity::prelude::*;
 pub type SongModel = Model;
 pub type SongEntity = Entity;
 
+/// Where the song file came from. Everything but local files is a download source
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    EnumIter,
+    DeriveActiveEnum,
+    strum::Display,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Downloaded from Youtube / Youtube Music
+    #[sea_orm(string_value = "youtube")]
+    Youtube,
+    /// Downloaded from SoundCloud
+    #[sea_orm(string_value = "soundcloud")]
+    #[serde(rename = "soundcloud")]
+    SoundCloud,
+    /// Downloaded from Bandcamp
+    #[sea_orm(string_value = "bandcamp")]
+    Bandcamp,
+    /// Downloaded by yt-dlp from any other site it supports
+    #[sea_orm(string_value = "yt_dlp")]
+    YtDlp,
+    /// Local file
+    #[default]
+    #[sea_orm(string_value = "local")]
+    Local,
+}
+
+impl Source {
+    /// Guesses the source from the host of a download url
+    pub fn from_url(url: &str) -> Self {
+        let host = url
+            .split_once("://")
+            .map_or(url, |(_, rest)| rest)
+            .split(['/', '?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let is = |domain: &str| host == domain || host.ends_with(&format!(".{domain}"));
+        if is("youtube.com") || is("youtu.be") {
+            Self::Youtube
+        } else if is("soundcloud.com") {
+            Self::SoundCloud
+        } else if is("bandcamp.com") {
+            Self::Bandcamp
+        } else {
+            Self::YtDlp
+        }
+    }
+
+    /// The page of a song on the site, for sources where the id is enough to build it
+    pub fn url_for(&self, source_id: &str) -> Option<String> {
+        match self {
+            Self::Youtube => Some(format!("https://www.youtube.com/watch?v={source_id}")),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "song")]
 pub struct Model {
@@ -14,6 +81,35 @@ pub struct Model {
     pub youtube_id: Option<String>,
     pub thumbnail_url: Option<String>,
     pub path: Option<String>,
+    pub track_number: Option<i32>,
+    pub track_total: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub year: Option<i32>,
+    pub release_date: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub bitrate: Option<i32>,
+    pub codec: Option<String>,
+    pub sample_rate: Option<i32>,
+    pub file_size: Option<i64>,
+    pub content_hash: Option<String>,
+    /// 0 to 5 stars, `None` if unrated
+    pub rating: Option<i32>,
+    pub favourite: bool,
+    pub play_count: i32,
+    /// Unix timestamp in seconds
+    pub last_played: Option<i64>,
+    /// Unix timestamp in seconds, `None` for songs inserted before it was recorded
+    pub added_at: Option<i64>,
+    pub source: Source,
+    /// Page the song was downloaded from
+    pub source_url: Option<String>,
+    /// Id of the song on the source site, the youtube id for youtube songs
+    pub source_id: Option<String>,
+    /// Size of the file in bytes when `content_hash` was computed
+    pub hashed_size: Option<i64>,
+    /// Modification time of the file when `content_hash` was computed, as a unix timestamp
+    /// in milliseconds
+    pub hashed_modified: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -27,6 +123,14 @@ pub enum Relation {
     SongGenreJunction,
     #[sea_orm(has_many = "super::song_youtube_playlist_id_junction::Entity")]
     SongYoutubePlaylistIdJunction,
+    #[sea_orm(has_many = "super::playlist_song::Entity")]
+    PlaylistSong,
+    #[sea_orm(has_one = "super::lyrics::Entity")]
+    Lyrics,
+    #[sea_orm(has_one = "super::song_download::Entity")]
+    SongDownload,
+    #[sea_orm(has_many = "super::song_label_junction::Entity")]
+    SongLabelJunction,
 }
 
 impl Related<super::album::Entity> for Entity {
@@ -59,6 +163,15 @@ impl Related<super::genre::Entity> for Entity {
     }
 }
 
+impl Related<super::label::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::song_label_junction::Relation::Label.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::song_label_junction::Relation::Song.def().rev())
+    }
+}
+
 impl Related<super::youtube_playlist_id::Entity> for Entity {
     fn to() -> RelationDef {
         super::song_youtube_playlist_id_junction::Relation::YoutubePlaylistId.def()
@@ -72,6 +185,15 @@ impl Related<super::youtube_playlist_id::Entity> for Entity {
     }
 }
 
+impl Related<super::playlist::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::playlist_song::Relation::Playlist.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::playlist_song::Relation::Song.def().rev())
+    }
+}
+
 impl Related<super::song_album_junction::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::SongAlbumJunction.def()
@@ -96,4 +218,28 @@ impl Related<super::song_youtube_playlist_id_junction::Entity> for Entity {
     }
 }
 
+impl Related<super::playlist_song::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PlaylistSong.def()
+    }
+}
+
+impl Related<super::lyrics::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Lyrics.def()
+    }
+}
+
+impl Related<super::song_download::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SongDownload.def()
+    }
+}
+
+impl Related<super::song_label_junction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SongLabelJunction.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
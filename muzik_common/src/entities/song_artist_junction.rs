@@ -21,7 +21,7 @@ pub enum Relation {
         from = "Column::ArtistId",
         to = "super::artist::Column::Id",
         on_update = "NoAction",
-        on_delete = "NoAction"
+        on_delete = "Cascade"
     )]
     Artist,
     #[sea_orm(
@@ -29,7 +29,7 @@ pub enum Relation {
         from = "Column::SongId",
         to = "super::song::Column::Id",
         on_update = "NoAction",
-        on_delete = "NoAction"
+        on_delete = "Cascade"
     )]
     Song,
 }
@@ -18,7 +18,7 @@ pub enum Relation {
         from = "Column::GenreId",
         to = "super::genre::Column::Id",
         on_update = "NoAction",
-        on_delete = "NoAction"
+        on_delete = "Cascade"
     )]
     Genre,
     #[sea_orm(
@@ -26,7 +26,7 @@ pub enum Relation {
         from = "Column::SongId",
         to = "super::song::Column::Id",
         on_update = "NoAction",
-        on_delete = "NoAction"
+        on_delete = "Cascade"
     )]
     Song,
 }
@@ -2,6 +2,8 @@
 
 use sea_orm::entity::prelude::*;
 
+pub type SongYoutubePlaylistIdJunctionModel = Model;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "song_youtube_playlist_id_junction")]
 pub struct Model {
@@ -9,6 +11,10 @@ pub struct Model {
     pub key: i32,
     pub song_id: i32,
     pub youtube_playlist_id_id: i32,
+    /// Position of the song in the playlist, starting at 0
+    pub position: i32,
+    /// Unix timestamp in seconds of when the song was added
+    pub added_at: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -18,7 +24,7 @@ pub enum Relation {
         from = "Column::SongId",
         to = "super::song::Column::Id",
         on_update = "NoAction",
-        on_delete = "NoAction"
+        on_delete = "Cascade"
     )]
     Song,
     #[sea_orm(
@@ -26,7 +32,7 @@ pub enum Relation {
         from = "Column::YoutubePlaylistIdId",
         to = "super::youtube_playlist_id::Column::Id",
         on_update = "NoAction",
-        on_delete = "NoAction"
+        on_delete = "Cascade"
     )]
     YoutubePlaylistId,
 }
@@ -0,0 +1,408 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{Lyrics, PlayStats, Provenance, Song, Source},
+    database::AppSong,
+    entities::{album, artist, genre, song_relationship::RelationshipKind, youtube_playlist_id},
+};
+
+use self::error::ExportError;
+
+/// Bumped whenever the format changes in a way older importers can't read
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Everything in the library that can't be read back from the music files alone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryExport {
+    pub version: u32,
+    /// Unix timestamp in seconds
+    pub exported_at: i64,
+    pub songs: Vec<ExportedSong>,
+    pub playlists: Vec<ExportedPlaylist>,
+    /// Missing in exports made before songs could be linked
+    #[serde(default)]
+    pub relationships: Vec<ExportedRelationship>,
+    /// Missing in exports made before covers were exported
+    #[serde(default)]
+    pub album_covers: Vec<ExportedCover>,
+    /// Missing in exports made before aliases were exported
+    #[serde(default)]
+    pub artist_aliases: Vec<ExportedAlias>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportedSong {
+    /// Id in the exported database, only used to link playlists to songs
+    pub id: i32,
+    pub title: String,
+    /// Relative to the music dir
+    pub path: Option<String>,
+    pub artists: Vec<String>,
+    pub albums: Vec<ExportedAlbum>,
+    pub genres: Vec<String>,
+    /// Missing in exports made before labels existed
+    #[serde(default)]
+    pub labels: Vec<String>,
+    pub youtube_id: Option<String>,
+    pub youtube_playlists: Vec<String>,
+    pub thumbnail_url: Option<String>,
+    pub track_number: Option<i32>,
+    pub track_total: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub year: Option<i32>,
+    pub release_date: Option<String>,
+    pub content_hash: Option<String>,
+    pub rating: Option<i32>,
+    pub favourite: bool,
+    pub play_count: i32,
+    pub last_played: Option<i64>,
+    /// Missing in exports made before it was recorded
+    #[serde(default)]
+    pub added_at: Option<i64>,
+    /// Missing in exports made before it was recorded, youtube songs are then told apart
+    /// by their youtube id
+    #[serde(default)]
+    pub source: Option<Source>,
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub source_id: Option<String>,
+    /// How the file was downloaded, missing in exports made before it was recorded
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    pub lyrics: Option<String>,
+    /// Time synced lyrics in LRC format
+    pub synced_lyrics: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportedAlbum {
+    pub name: String,
+    pub album_artist: Option<String>,
+    pub year: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportedPlaylist {
+    pub name: String,
+    pub description: Option<String>,
+    /// `ExportedSong::id`s in playlist order
+    pub song_ids: Vec<i32>,
+}
+
+/// `song_id` is a `kind` of `related_song_id`, both are `ExportedSong::id`s
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedRelationship {
+    pub song_id: i32,
+    pub related_song_id: i32,
+    pub kind: RelationshipKind,
+}
+
+/// The cover of the album with this name and album artist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedCover {
+    pub album: String,
+    pub album_artist: Option<String>,
+    /// Base64 encoded PNG
+    pub png: String,
+}
+
+impl ExportedCover {
+    pub fn new(album: &album::Model, png: &[u8]) -> Self {
+        Self {
+            album: album.name.clone(),
+            album_artist: album.album_artist.clone(),
+            png: STANDARD.encode(png),
+        }
+    }
+
+    pub fn decode_png(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        STANDARD.decode(&self.png)
+    }
+}
+
+/// `alias` resolves to the artist named `artist`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedAlias {
+    pub artist: String,
+    pub alias: String,
+}
+
+/// What an import added to the database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub songs_inserted: usize,
+    /// Songs that were already in the database, see `DbConnection::import_library`
+    pub songs_skipped: usize,
+    pub playlists_created: usize,
+}
+
+impl ImportReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} songs imported, {} already present, {} playlists created",
+            self.songs_inserted, self.songs_skipped, self.playlists_created
+        )
+    }
+}
+
+/// One row per song, lists are joined with `;` like `AppSong::get_artists_string`
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    id: i32,
+    title: &'a str,
+    artists: String,
+    albums: String,
+    album_artist: Option<&'a str>,
+    genres: String,
+    labels: String,
+    path: Option<&'a str>,
+    youtube_id: Option<&'a str>,
+    youtube_playlists: String,
+    track_number: Option<i32>,
+    track_total: Option<i32>,
+    disc_number: Option<i32>,
+    year: Option<i32>,
+    release_date: Option<&'a str>,
+    rating: Option<i32>,
+    favourite: bool,
+    play_count: i32,
+    last_played: Option<i64>,
+    added_at: Option<i64>,
+    source: Option<Source>,
+    source_url: Option<&'a str>,
+}
+
+impl LibraryExport {
+    pub fn write_json(&self, writer: impl Write) -> Result<(), ExportError> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Reads an export, refusing versions newer than this build understands
+    pub fn read_json(reader: impl Read) -> Result<Self, ExportError> {
+        let export: Self = serde_json::from_reader(reader)?;
+        if export.version > EXPORT_VERSION {
+            return Err(ExportError::UnsupportedVersion(export.version));
+        }
+        Ok(export)
+    }
+
+    /// Flat export of the songs only, meant for spreadsheets and diffs. It can't be
+    /// imported back, use the JSON export for that.
+    pub fn write_csv(&self, writer: impl Write) -> Result<(), ExportError> {
+        let mut csv = csv::Writer::from_writer(writer);
+        for song in &self.songs {
+            csv.serialize(CsvRow {
+                id: song.id,
+                title: &song.title,
+                artists: song.artists.join("; "),
+                albums: song
+                    .albums
+                    .iter()
+                    .map(|album| album.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                album_artist: song
+                    .albums
+                    .iter()
+                    .find_map(|album| album.album_artist.as_deref()),
+                genres: song.genres.join("; "),
+                labels: song.labels.join("; "),
+                path: song.path.as_deref(),
+                youtube_id: song.youtube_id.as_deref(),
+                youtube_playlists: song.youtube_playlists.join("; "),
+                track_number: song.track_number,
+                track_total: song.track_total,
+                disc_number: song.disc_number,
+                year: song.year,
+                release_date: song.release_date.as_deref(),
+                rating: song.rating,
+                favourite: song.favourite,
+                play_count: song.play_count,
+                last_played: song.last_played,
+                added_at: song.added_at,
+                source: song.source,
+                source_url: song.source_url.as_deref(),
+            })?;
+        }
+        csv.flush()?;
+        Ok(())
+    }
+
+    /// Writes JSON, or CSV when the path ends in `.csv`
+    pub fn write_to_path(&self, path: &Path) -> Result<(), ExportError> {
+        let file = std::fs::File::create(path)?;
+        if path.extension().is_some_and(|extension| extension == "csv") {
+            self.write_csv(file)
+        } else {
+            self.write_json(std::io::BufWriter::new(file))
+        }
+    }
+
+    pub fn read_from_path(path: &Path) -> Result<Self, ExportError> {
+        Self::read_json(std::io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+impl ExportedSong {
+    /// `song` has to be loaded with an empty music dir so its path stays relative
+    pub fn from_song(song: &Song) -> Self {
+        let play_stats = song.play_stats.clone().unwrap_or_default();
+        Self {
+            id: song.id.unwrap_or_default(),
+            title: song.get_title_string(),
+            path: Some(song.get_database_path()).filter(|path| !path.is_empty()),
+            artists: song
+                .artists
+                .iter()
+                .flatten()
+                .map(|artist| artist.name.clone())
+                .collect(),
+            albums: song
+                .albums
+                .iter()
+                .flatten()
+                .map(|album| ExportedAlbum {
+                    name: album.name.clone(),
+                    album_artist: album.album_artist.clone(),
+                    year: album.year,
+                })
+                .collect(),
+            genres: song
+                .genres
+                .iter()
+                .flatten()
+                .map(|genre| genre.genre.clone())
+                .collect(),
+            labels: song.get_labels_vec(),
+            youtube_id: song.youtube_id.clone(),
+            youtube_playlists: song
+                .get_youtube_playlists()
+                .into_iter()
+                .map(|playlist| playlist.youtube_playlist_id)
+                .collect(),
+            thumbnail_url: song.thumbnail_url.clone(),
+            track_number: song.track_number,
+            track_total: song.track_total,
+            disc_number: song.disc_number,
+            year: song.year,
+            release_date: song.release_date.clone(),
+            content_hash: song.content_hash.clone(),
+            rating: play_stats.rating,
+            favourite: play_stats.favourite,
+            play_count: play_stats.play_count,
+            last_played: play_stats.last_played,
+            added_at: song.added_at,
+            source: Some(song.source),
+            source_url: song.get_source_url(),
+            source_id: song.get_source_id(),
+            provenance: song.provenance.clone(),
+            lyrics: song.lyrics.as_ref().map(|lyrics| lyrics.plain.clone()),
+            synced_lyrics: song.lyrics.as_ref().and_then(Lyrics::to_lrc),
+        }
+    }
+
+    /// The song as it is inserted by `DbConnection::insert_from_app_song`
+    pub fn to_app_song(&self, music_dir: PathBuf) -> AppSong {
+        let mut song = AppSong::new()
+            .with_music_dir(Some(music_dir.clone()))
+            .with_title(Some(self.title.clone()))
+            .with_yt_id(self.youtube_id.clone())
+            .with_tb_url(self.thumbnail_url.clone())
+            .with_source(self.source.unwrap_or(match self.youtube_id {
+                Some(_) => Source::Youtube,
+                None => Source::Local,
+            }))
+            .with_source_url(self.source_url.clone())
+            .with_source_id(self.source_id.clone())
+            .with_track_number(self.track_number)
+            .with_track_total(self.track_total)
+            .with_disc_number(self.disc_number)
+            .with_year(self.year)
+            .with_release_date(self.release_date.clone())
+            .with_play_stats(Some(PlayStats {
+                rating: self.rating,
+                favourite: self.favourite,
+                play_count: self.play_count,
+                last_played: self.last_played,
+            }));
+        song.path = self.path.as_ref().map(|path| music_dir.join(path));
+        song.artist = Some(
+            self.artists
+                .iter()
+                .map(|name| artist::Model {
+                    name: name.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        song.album = Some(
+            self.albums
+                .iter()
+                .map(|album| album::Model {
+                    name: album.name.clone(),
+                    album_artist: album.album_artist.clone(),
+                    year: album.year,
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        song.genre = Some(
+            self.genres
+                .iter()
+                .map(|genre| genre::Model {
+                    genre: genre.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        song.yt_playlist = Some(
+            self.youtube_playlists
+                .iter()
+                .map(|playlist| youtube_playlist_id::Model {
+                    id: 0,
+                    youtube_playlist_id: playlist.clone(),
+                })
+                .collect(),
+        );
+        song
+    }
+
+    pub fn get_lyrics(&self) -> Option<Lyrics> {
+        match (&self.lyrics, &self.synced_lyrics) {
+            (plain, Some(lrc)) => {
+                let mut lyrics = Lyrics::from_lrc(lrc);
+                if let Some(plain) = plain {
+                    lyrics.plain = plain.clone();
+                }
+                Some(lyrics)
+            }
+            (Some(plain), None) => Some(Lyrics::from_plain(plain.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+pub mod error {
+    use miette::Diagnostic;
+    use thiserror::Error;
+
+    #[derive(Error, Diagnostic, Debug)]
+    pub enum ExportError {
+        #[error(transparent)]
+        IoError(#[from] std::io::Error),
+        #[error(transparent)]
+        JsonError(#[from] serde_json::Error),
+        #[error(transparent)]
+        CsvError(#[from] csv::Error),
+        #[error("Export version {0} is newer than this version of muzik supports")]
+        UnsupportedVersion(u32),
+    }
+}
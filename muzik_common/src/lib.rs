@@ -2,7 +2,10 @@ pub mod config;
 pub mod data;
 pub mod database;
 pub mod entities;
+pub mod export;
+pub mod lock;
 pub mod migrator;
+pub mod query;
 pub mod tags;
 pub mod util;
 
@@ -0,0 +1,159 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+};
+
+use self::error::LockError;
+
+/// Name of the lockfile in the music dir
+pub const LOCK_FILE: &str = ".muzik.lock";
+
+/// Locks this process holds, shared by everything in it working on the same music dir
+static HELD: Mutex<Vec<(PathBuf, Weak<File>)>> = Mutex::new(Vec::new());
+
+/// Advisory lock on a music dir, held while songs in it are renamed, moved or downloaded.
+///
+/// The GUI, TUI and CLI take it before touching files, so two processes sharing a library
+/// can't rename a file the other is downloading or tagging. Within one process the lock is
+/// shared, concurrent downloads of the same frontend don't refuse each other. It is
+/// released once every clone is dropped, or by the OS when the process dies, so a crash
+/// can't leave the library locked.
+#[derive(Debug, Clone)]
+pub struct MusicDirLock {
+    _file: Arc<File>,
+}
+
+impl MusicDirLock {
+    /// Locks `music_dir` for `operation`, e.g. "downloading", refusing with
+    /// `LockError::Locked` while another process holds the lock
+    pub fn try_acquire(music_dir: &Path, operation: &str) -> Result<Self, LockError> {
+        std::fs::create_dir_all(music_dir)?;
+        let music_dir = music_dir.canonicalize()?;
+        let mut held = HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        held.retain(|(_, file)| file.strong_count() > 0);
+        if let Some(file) = held
+            .iter()
+            .find(|(dir, _)| *dir == music_dir)
+            .and_then(|(_, file)| file.upgrade())
+        {
+            return Ok(Self { _file: file });
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(music_dir.join(LOCK_FILE))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                // the holder may not have written itself yet, and windows doesn't allow
+                // reading a locked file
+                let mut holder = String::new();
+                let _ = file.read_to_string(&mut holder);
+                let holder = match holder.trim() {
+                    "" => "another muzik process is using it".to_owned(),
+                    holder => holder.to_owned(),
+                };
+                return Err(LockError::Locked(music_dir, holder));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        // tells refused processes what they are waiting on
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "muzik process {} is {operation}", std::process::id())?;
+
+        let file = Arc::new(file);
+        held.push((music_dir, Arc::downgrade(&file)));
+        Ok(Self { _file: file })
+    }
+}
+
+/// Lock on a database file, next to it as `<database>.lock`.
+///
+/// Every process with the database open holds it shared. Replacing or moving the database,
+/// which removes its journal files, takes it exclusively so it can't happen under a frontend that
+/// is still using them. Like `MusicDirLock` it is released when dropped or when the
+/// process dies.
+#[derive(Debug, Clone)]
+pub struct DatabaseLock {
+    _file: Arc<File>,
+}
+
+impl DatabaseLock {
+    /// Locks the database at `database` for use, refusing while it is being replaced
+    pub fn try_shared(database: &Path) -> Result<Self, LockError> {
+        let file = Self::open(database)?;
+        match file.try_lock_shared() {
+            Ok(()) => Ok(Self {
+                _file: Arc::new(file),
+            }),
+            Err(TryLockError::WouldBlock) => Err(LockError::Locked(
+                database.to_path_buf(),
+                "it is being replaced".to_owned(),
+            )),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Locks the database at `database` for `operation`, refusing while anything,
+    /// this process included, has it open
+    pub fn try_exclusive(database: &Path, operation: &str) -> Result<Self, LockError> {
+        let file = Self::open(database)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Self {
+                _file: Arc::new(file),
+            }),
+            Err(TryLockError::WouldBlock) => Err(LockError::Locked(
+                database.to_path_buf(),
+                format!("it is open elsewhere, close it before {operation}"),
+            )),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    fn open(database: &Path) -> Result<File, LockError> {
+        if let Some(parent) = database
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(Self::path(database))?)
+    }
+
+    /// The lock file of the database at `database`
+    pub fn path(database: &Path) -> PathBuf {
+        let mut path = database.as_os_str().to_owned();
+        path.push(".lock");
+        PathBuf::from(path)
+    }
+}
+
+pub mod error {
+    use std::path::PathBuf;
+
+    use miette::Diagnostic;
+    use thiserror::Error;
+
+    #[derive(Error, Diagnostic, Debug)]
+    pub enum LockError {
+        #[error(transparent)]
+        IoError(#[from] std::io::Error),
+        #[error(
+            "{} is in use: {}. Try again once it is done",
+            .0.display(),
+            .1
+        )]
+        Locked(PathBuf, String),
+    }
+}
@@ -133,10 +133,14 @@ pub enum Artist {
 }
 
 #[derive(Iden)]
+#[allow(clippy::enum_variant_names)]
 pub enum Album {
     Table,
     Id,
     Name,
+    // Added on 23-10-2023
+    AlbumArtist,
+    Year,
 }
 
 #[derive(Iden)]
@@ -163,4 +167,32 @@ pub enum Song {
     ThumbnailUrl,
     // Added on 26-08-2023
     Path,
+    // Added on 17-10-2023
+    TrackNumber,
+    TrackTotal,
+    DiscNumber,
+    Year,
+    ReleaseDate,
+    // Added on 18-10-2023
+    DurationMs,
+    Bitrate,
+    Codec,
+    SampleRate,
+    FileSize,
+    // Added on 19-10-2023
+    ContentHash,
+    // Added on 26-10-2023
+    Rating,
+    Favourite,
+    PlayCount,
+    LastPlayed,
+    // Added on 28-10-2023
+    AddedAt,
+    // Added on 29-10-2023
+    Source,
+    SourceUrl,
+    SourceId,
+    // Added on 03-11-2023
+    HashedSize,
+    HashedModified,
 }
@@ -220,4 +220,7 @@ pub enum SongYoutubePlaylistIdJunction {
     Key,
     SongId,
     YoutubePlaylistIdId,
+    // Added on 25-10-2023
+    Position,
+    AddedAt,
 }
@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231017_000004_alter_song_table_add_track_info"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only allows one alteration per statement
+        for mut column in [
+            ColumnDef::new(Song::TrackNumber).integer().to_owned(),
+            ColumnDef::new(Song::TrackTotal).integer().to_owned(),
+            ColumnDef::new(Song::DiscNumber).integer().to_owned(),
+            ColumnDef::new(Song::Year).integer().to_owned(),
+            ColumnDef::new(Song::ReleaseDate).text().to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Song::TrackNumber,
+            Song::TrackTotal,
+            Song::DiscNumber,
+            Song::Year,
+            Song::ReleaseDate,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231018_000005_alter_song_table_add_audio_properties"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only allows one alteration per statement
+        for mut column in [
+            ColumnDef::new(Song::DurationMs).big_integer().to_owned(),
+            ColumnDef::new(Song::Bitrate).integer().to_owned(),
+            ColumnDef::new(Song::Codec).text().to_owned(),
+            ColumnDef::new(Song::SampleRate).integer().to_owned(),
+            ColumnDef::new(Song::FileSize).big_integer().to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            Song::DurationMs,
+            Song::Bitrate,
+            Song::Codec,
+            Song::SampleRate,
+            Song::FileSize,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
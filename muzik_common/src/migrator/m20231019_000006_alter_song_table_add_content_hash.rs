@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231019_000006_alter_song_table_add_content_hash"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .add_column(ColumnDef::new(Song::ContentHash).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        // moved files are looked up by their hash
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-song-content_hash")
+                    .table(Song::Table)
+                    .col(Song::ContentHash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-song-content_hash")
+                    .table(Song::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Song::Table)
+                    .drop_column(Song::ContentHash)
+                    .to_owned(),
+            )
+            .await
+    }
+}
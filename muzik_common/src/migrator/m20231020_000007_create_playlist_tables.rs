@@ -0,0 +1,102 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231020_000007_create_playlist_tables"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Playlist::Table)
+                    .col(
+                        ColumnDef::new(Playlist::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Playlist::Name)
+                            .text()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Playlist::Description).text())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(PlaylistSong::Table)
+                    .col(
+                        ColumnDef::new(PlaylistSong::Key)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PlaylistSong::PlaylistId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PlaylistSong::SongId).integer().not_null())
+                    .col(ColumnDef::new(PlaylistSong::Position).integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-playlist_song-playlist_id")
+                            .from(PlaylistSong::Table, PlaylistSong::PlaylistId)
+                            .to(Playlist::Table, Playlist::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-playlist_song-song_id")
+                            .from(PlaylistSong::Table, PlaylistSong::SongId)
+                            .to(Song::Table, Song::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PlaylistSong::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Playlist::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+pub enum Playlist {
+    Table,
+    Id,
+    Name,
+    Description,
+}
+
+/// Ordered junction between playlists and songs, a song may appear more than once
+#[derive(Iden)]
+pub enum PlaylistSong {
+    Table,
+    Key,
+    PlaylistId,
+    SongId,
+    Position,
+}
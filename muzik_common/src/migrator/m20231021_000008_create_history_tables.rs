@@ -0,0 +1,111 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231021_000008_create_history_tables"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChangeSet::Table)
+                    .col(
+                        ColumnDef::new(ChangeSet::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ChangeSet::Origin).text().not_null())
+                    .col(
+                        ColumnDef::new(ChangeSet::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChangeSet::Undone)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(FieldChange::Table)
+                    .col(
+                        ColumnDef::new(FieldChange::Key)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(FieldChange::ChangeSetId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(FieldChange::SongId).integer().not_null())
+                    .col(ColumnDef::new(FieldChange::Field).text().not_null())
+                    .col(ColumnDef::new(FieldChange::OldValue).text())
+                    .col(ColumnDef::new(FieldChange::NewValue).text())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-field_change-change_set_id")
+                            .from(FieldChange::Table, FieldChange::ChangeSetId)
+                            .to(ChangeSet::Table, ChangeSet::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-field_change-song_id")
+                            .from(FieldChange::Table, FieldChange::SongId)
+                            .to(Song::Table, Song::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FieldChange::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ChangeSet::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+pub enum ChangeSet {
+    Table,
+    Id,
+    Origin,
+    CreatedAt,
+    Undone,
+}
+
+#[derive(Iden)]
+pub enum FieldChange {
+    Table,
+    Key,
+    ChangeSetId,
+    SongId,
+    Field,
+    OldValue,
+    NewValue,
+}
@@ -0,0 +1,184 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231022_000009_create_song_search"
+    }
+}
+
+/// Rebuilds the `song_search` rows of every song whose id matches `condition`,
+/// e.g. `= new.id` or `IN (SELECT ...)`.
+pub(super) fn refresh_songs(condition: &str) -> String {
+    format!(
+        "DELETE FROM song_search WHERE rowid {condition};
+        INSERT INTO song_search (rowid, title, artists, albums, genres)
+        SELECT
+            song.id,
+            song.title,
+            (SELECT group_concat(artist.name, ' ') FROM song_artist_junction
+                JOIN artist ON artist.id = song_artist_junction.artist_id
+                WHERE song_artist_junction.song_id = song.id),
+            (SELECT group_concat(album.name, ' ') FROM song_album_junction
+                JOIN album ON album.id = song_album_junction.album_id
+                WHERE song_album_junction.song_id = song.id),
+            (SELECT group_concat(genre.genre, ' ') FROM song_genre_junction
+                JOIN genre ON genre.id = song_genre_junction.genre_id
+                WHERE song_genre_junction.song_id = song.id)
+        FROM song WHERE song.id {condition};"
+    )
+}
+
+/// Creates the insert, update and delete triggers named `names` that keep the search
+/// entries of the songs linked by `junction` up to date
+pub(super) async fn create_junction_triggers(
+    db: &SchemaManagerConnection<'_>,
+    junction: &str,
+    names: &[&str],
+) -> Result<(), DbErr> {
+    db.execute_unprepared(&trigger(
+        names[0],
+        &format!("INSERT ON {junction}"),
+        &refresh_songs("= new.song_id"),
+    ))
+    .await?;
+    db.execute_unprepared(&trigger(
+        names[1],
+        &format!("UPDATE ON {junction}"),
+        &format!(
+            "{}{}",
+            refresh_songs("= old.song_id"),
+            refresh_songs("= new.song_id")
+        ),
+    ))
+    .await?;
+    db.execute_unprepared(&trigger(
+        names[2],
+        &format!("DELETE ON {junction}"),
+        &refresh_songs("= old.song_id"),
+    ))
+    .await?;
+    Ok(())
+}
+
+pub(super) fn trigger(name: &str, event: &str, body: &str) -> String {
+    format!("CREATE TRIGGER {name} AFTER {event} FOR EACH ROW BEGIN {body} END;")
+}
+
+/// Junction tables whose rows are part of a song's search entry
+pub(super) const JUNCTIONS: [&str; 3] = [
+    "song_artist_junction",
+    "song_album_junction",
+    "song_genre_junction",
+];
+
+pub(super) const TRIGGERS: [&str; 15] = [
+    "song_search_song_insert",
+    "song_search_song_update",
+    "song_search_song_delete",
+    "song_search_artist_junction_insert",
+    "song_search_artist_junction_update",
+    "song_search_artist_junction_delete",
+    "song_search_album_junction_insert",
+    "song_search_album_junction_update",
+    "song_search_album_junction_delete",
+    "song_search_genre_junction_insert",
+    "song_search_genre_junction_update",
+    "song_search_genre_junction_delete",
+    "song_search_artist_update",
+    "song_search_album_update",
+    "song_search_genre_update",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // remove_diacritics makes "Beyonce" match "Beyoncé"
+        db.execute_unprepared(
+            "CREATE VIRTUAL TABLE song_search USING fts5(
+                title, artists, albums, genres,
+                tokenize = 'unicode61 remove_diacritics 2'
+            );",
+        )
+        .await?;
+
+        // keep song titles in sync
+        db.execute_unprepared(&trigger(
+            TRIGGERS[0],
+            "INSERT ON song",
+            &refresh_songs("= new.id"),
+        ))
+        .await?;
+        db.execute_unprepared(&trigger(
+            TRIGGERS[1],
+            "UPDATE OF title ON song",
+            &refresh_songs("= new.id"),
+        ))
+        .await?;
+        db.execute_unprepared(&trigger(
+            TRIGGERS[2],
+            "DELETE ON song",
+            "DELETE FROM song_search WHERE rowid = old.id;",
+        ))
+        .await?;
+
+        // keep artist, album and genre links in sync
+        for (index, junction) in JUNCTIONS.into_iter().enumerate() {
+            create_junction_triggers(db, junction, &TRIGGERS[3 + index * 3..6 + index * 3]).await?;
+        }
+
+        // renaming an artist, album or genre touches every linked song
+        for (name, table, column, junction, key) in [
+            (
+                TRIGGERS[12],
+                "artist",
+                "name",
+                "song_artist_junction",
+                "artist_id",
+            ),
+            (
+                TRIGGERS[13],
+                "album",
+                "name",
+                "song_album_junction",
+                "album_id",
+            ),
+            (
+                TRIGGERS[14],
+                "genre",
+                "genre",
+                "song_genre_junction",
+                "genre_id",
+            ),
+        ] {
+            db.execute_unprepared(&trigger(
+                name,
+                &format!("UPDATE OF {column} ON {table}"),
+                &refresh_songs(&format!(
+                    "IN (SELECT song_id FROM {junction} WHERE {key} = new.id)"
+                )),
+            ))
+            .await?;
+        }
+
+        // index the songs that already exist
+        db.execute_unprepared(&refresh_songs("IN (SELECT id FROM song)"))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for name in TRIGGERS {
+            db.execute_unprepared(&format!("DROP TRIGGER IF EXISTS {name};"))
+                .await?;
+        }
+        db.execute_unprepared("DROP TABLE IF EXISTS song_search;")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use sea_orm::TransactionTrait;
+use sea_orm_migration::prelude::*;
+
+use super::{
+    m20230601_000001_create_basic_table::Album,
+    m20231022_000009_create_song_search::{refresh_songs, trigger},
+};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231023_000010_alter_album_table_add_album_info"
+    }
+}
+
+/// SQLite can't drop the old `UNIQUE (name)` constraint, so the album table is
+/// rebuilt with the same ids. Foreign key checks are deferred until commit, by
+/// which point every junction row points at an album again.
+async fn rebuild_album_table(manager: &SchemaManager<'_>, with_info: bool) -> Result<(), DbErr> {
+    let txn = manager.get_connection().begin().await?;
+    txn.execute_unprepared("PRAGMA defer_foreign_keys = ON;")
+        .await?;
+    txn.execute_unprepared("CREATE TABLE album_old AS SELECT id, name FROM album;")
+        .await?;
+
+    let manager = SchemaManager::new(&txn);
+    manager
+        .drop_table(Table::drop().table(Album::Table).to_owned())
+        .await?;
+
+    let mut table = Table::create();
+    table.table(Album::Table).col(
+        ColumnDef::new(Album::Id)
+            .integer()
+            .not_null()
+            .auto_increment()
+            .primary_key(),
+    );
+    if with_info {
+        table
+            .col(ColumnDef::new(Album::Name).text().not_null())
+            .col(ColumnDef::new(Album::AlbumArtist).text())
+            .col(ColumnDef::new(Album::Year).integer());
+    } else {
+        table.col(ColumnDef::new(Album::Name).text().not_null().unique_key());
+    }
+    manager.create_table(table.to_owned()).await?;
+
+    if with_info {
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-album-name-album_artist")
+                    .table(Album::Table)
+                    .col(Album::Name)
+                    .col(Album::AlbumArtist)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+    }
+
+    txn.execute_unprepared("INSERT INTO album (id, name) SELECT id, name FROM album_old;")
+        .await?;
+    txn.execute_unprepared("DROP TABLE album_old;").await?;
+
+    // dropping the table also dropped the search trigger on it
+    txn.execute_unprepared(&trigger(
+        "song_search_album_update",
+        "UPDATE OF name ON album",
+        &refresh_songs("IN (SELECT song_id FROM song_album_junction WHERE album_id = new.id)"),
+    ))
+    .await?;
+
+    txn.commit().await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rebuild_album_table(manager, true).await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AlbumCover::Table)
+                    .col(
+                        ColumnDef::new(AlbumCover::AlbumId)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AlbumCover::Data).binary().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-album_cover-album_id")
+                            .from(AlbumCover::Table, AlbumCover::AlbumId)
+                            .to(Album::Table, Album::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AlbumCover::Table).to_owned())
+            .await?;
+        rebuild_album_table(manager, false).await?;
+        Ok(())
+    }
+}
+
+/// Covers live in their own table so loading songs doesn't pull in image data
+#[derive(Iden)]
+pub enum AlbumCover {
+    Table,
+    AlbumId,
+    Data,
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Artist;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231024_000011_create_artist_alias_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ArtistAlias::Table)
+                    .col(
+                        ColumnDef::new(ArtistAlias::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ArtistAlias::ArtistId).integer().not_null())
+                    .col(
+                        ColumnDef::new(ArtistAlias::Alias)
+                            .text()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-artist_alias-artist_id")
+                            .from(ArtistAlias::Table, ArtistAlias::ArtistId)
+                            .to(Artist::Table, Artist::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ArtistAlias::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+pub enum ArtistAlias {
+    Table,
+    Id,
+    ArtistId,
+    Alias,
+}
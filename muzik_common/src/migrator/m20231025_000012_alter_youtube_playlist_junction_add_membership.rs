@@ -0,0 +1,98 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000002_create_junction_tables::SongYoutubePlaylistIdJunction;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231025_000012_alter_youtube_playlist_junction_add_membership"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only allows one alteration per statement
+        for mut column in [
+            ColumnDef::new(SongYoutubePlaylistIdJunction::Position)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+            ColumnDef::new(SongYoutubePlaylistIdJunction::AddedAt)
+                .big_integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(SongYoutubePlaylistIdJunction::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        let db = manager.get_connection();
+        // a song is only listed once per playlist
+        db.execute_unprepared(
+            "DELETE FROM song_youtube_playlist_id_junction WHERE key NOT IN (
+                SELECT MIN(key) FROM song_youtube_playlist_id_junction
+                GROUP BY song_id, youtube_playlist_id_id
+            );",
+        )
+        .await?;
+        // existing rows keep their insertion order
+        db.execute_unprepared(
+            "UPDATE song_youtube_playlist_id_junction SET position = (
+                SELECT COUNT(*) FROM song_youtube_playlist_id_junction AS earlier
+                WHERE earlier.youtube_playlist_id_id
+                    = song_youtube_playlist_id_junction.youtube_playlist_id_id
+                AND earlier.key < song_youtube_playlist_id_junction.key
+            );",
+        )
+        .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-song_youtube_playlist_id_junction-song-playlist")
+                    .table(SongYoutubePlaylistIdJunction::Table)
+                    .col(SongYoutubePlaylistIdJunction::SongId)
+                    .col(SongYoutubePlaylistIdJunction::YoutubePlaylistIdId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-song_youtube_playlist_id_junction-song-playlist")
+                    .table(SongYoutubePlaylistIdJunction::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            SongYoutubePlaylistIdJunction::Position,
+            SongYoutubePlaylistIdJunction::AddedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(SongYoutubePlaylistIdJunction::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231027_000014_create_lyrics_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Lyrics::Table)
+                    .col(
+                        ColumnDef::new(Lyrics::SongId)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Lyrics::Plain).text().not_null())
+                    .col(ColumnDef::new(Lyrics::Synced).text())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-lyrics-song_id")
+                            .from(Lyrics::Table, Lyrics::SongId)
+                            .to(Song::Table, Song::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Lyrics::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Lyrics live in their own table so loading songs doesn't pull in the text
+#[derive(Iden)]
+pub enum Lyrics {
+    Table,
+    SongId,
+    Plain,
+    /// Time synced lyrics in LRC format
+    Synced,
+}
@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231029_000016_alter_song_table_add_source"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite only allows one alteration per statement
+        for mut column in [
+            ColumnDef::new(Song::Source)
+                .string()
+                .not_null()
+                .default("local")
+                .to_owned(),
+            ColumnDef::new(Song::SourceUrl).string().to_owned(),
+            ColumnDef::new(Song::SourceId).string().to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-song-source")
+                    .table(Song::Table)
+                    .col(Song::Source)
+                    .to_owned(),
+            )
+            .await?;
+
+        // the source used to be inferred from the youtube id, only youtube songs have one
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE song SET
+                    source = 'youtube',
+                    source_id = youtube_id,
+                    source_url = 'https://www.youtube.com/watch?v=' || youtube_id
+                WHERE youtube_id IS NOT NULL;",
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-song-source")
+                    .table(Song::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [Song::Source, Song::SourceUrl, Song::SourceId] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Song::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
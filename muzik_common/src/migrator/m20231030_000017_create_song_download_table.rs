@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231030_000017_create_song_download_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // songs downloaded before this migration have no provenance
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongDownload::Table)
+                    .col(
+                        ColumnDef::new(SongDownload::SongId)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(SongDownload::Url).string())
+                    .col(ColumnDef::new(SongDownload::UploaderId).string())
+                    .col(ColumnDef::new(SongDownload::UploadDate).string())
+                    .col(ColumnDef::new(SongDownload::FormatId).string())
+                    .col(ColumnDef::new(SongDownload::Extractor).string())
+                    .col(ColumnDef::new(SongDownload::YtdlpVersion).string())
+                    .col(
+                        ColumnDef::new(SongDownload::DownloadedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-song_download-song_id")
+                            .from(SongDownload::Table, SongDownload::SongId)
+                            .to(Song::Table, Song::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SongDownload::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Where and how a downloaded song was fetched, one row per song
+#[derive(Iden)]
+pub enum SongDownload {
+    Table,
+    SongId,
+    Url,
+    /// Channel id, or the uploader id where the site has no channels
+    UploaderId,
+    UploadDate,
+    FormatId,
+    Extractor,
+    YtdlpVersion,
+    DownloadedAt,
+}
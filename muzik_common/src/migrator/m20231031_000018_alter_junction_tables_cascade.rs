@@ -0,0 +1,286 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20231022_000009_create_song_search::{
+    create_junction_triggers, refresh_songs, JUNCTIONS, TRIGGERS,
+};
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231031_000018_alter_junction_tables_cascade"
+    }
+}
+
+/// A table with rows that belong to a song, or to an album or artist, which should go
+/// when the row they belong to is deleted
+struct Dependent {
+    table: &'static str,
+    /// Adds the columns, in the order of the existing table
+    columns: fn(&mut TableCreateStatement),
+    /// Foreign keys as `(column, parent, cascade)`, the ones not cascaded are kept as
+    /// they were
+    foreign_keys: &'static [(&'static str, &'static str, bool)],
+    /// A unique index on the columns, recreated with the table
+    unique_index: Option<(&'static str, &'static [&'static str])>,
+}
+
+const TABLES: [Dependent; 10] = [
+    Dependent {
+        table: "song_artist_junction",
+        columns: |table| junction_columns(table, "artist_id"),
+        foreign_keys: &[("song_id", "song", true), ("artist_id", "artist", true)],
+        unique_index: None,
+    },
+    Dependent {
+        table: "song_album_junction",
+        columns: |table| junction_columns(table, "album_id"),
+        foreign_keys: &[("song_id", "song", true), ("album_id", "album", true)],
+        unique_index: None,
+    },
+    Dependent {
+        table: "song_genre_junction",
+        columns: |table| junction_columns(table, "genre_id"),
+        foreign_keys: &[("song_id", "song", true), ("genre_id", "genre", true)],
+        unique_index: None,
+    },
+    Dependent {
+        table: "song_youtube_playlist_id_junction",
+        // with the `position` and `added_at` columns from m20231025_000012
+        columns: |table| {
+            junction_columns(table, "youtube_playlist_id_id");
+            table
+                .col(
+                    ColumnDef::new(Alias::new("position"))
+                        .integer()
+                        .not_null()
+                        .default(0),
+                )
+                .col(
+                    ColumnDef::new(Alias::new("added_at"))
+                        .big_integer()
+                        .not_null()
+                        .default(0),
+                );
+        },
+        foreign_keys: &[
+            ("song_id", "song", true),
+            ("youtube_playlist_id_id", "youtube_playlist_id", true),
+        ],
+        unique_index: Some((
+            "idx-song_youtube_playlist_id_junction-song-playlist",
+            &["song_id", "youtube_playlist_id_id"],
+        )),
+    },
+    Dependent {
+        table: "playlist_song",
+        columns: |table| {
+            table
+                .col(&mut key("key"))
+                .col(&mut integer("playlist_id"))
+                .col(&mut integer("song_id"))
+                .col(&mut integer("position"));
+        },
+        foreign_keys: &[
+            ("playlist_id", "playlist", false),
+            ("song_id", "song", true),
+        ],
+        unique_index: None,
+    },
+    Dependent {
+        table: "field_change",
+        columns: |table| {
+            table
+                .col(&mut key("key"))
+                .col(&mut integer("change_set_id"))
+                .col(&mut integer("song_id"))
+                .col(ColumnDef::new(Alias::new("field")).text().not_null())
+                .col(ColumnDef::new(Alias::new("old_value")).text())
+                .col(ColumnDef::new(Alias::new("new_value")).text());
+        },
+        foreign_keys: &[
+            ("change_set_id", "change_set", false),
+            ("song_id", "song", true),
+        ],
+        unique_index: None,
+    },
+    Dependent {
+        table: "lyrics",
+        columns: |table| {
+            table
+                .col(integer("song_id").primary_key())
+                .col(ColumnDef::new(Alias::new("plain")).text().not_null())
+                .col(ColumnDef::new(Alias::new("synced")).text());
+        },
+        foreign_keys: &[("song_id", "song", true)],
+        unique_index: None,
+    },
+    Dependent {
+        table: "song_download",
+        columns: |table| {
+            table.col(integer("song_id").primary_key());
+            for column in [
+                "url",
+                "uploader_id",
+                "upload_date",
+                "format_id",
+                "extractor",
+                "ytdlp_version",
+            ] {
+                table.col(ColumnDef::new(Alias::new(column)).string());
+            }
+            table.col(
+                ColumnDef::new(Alias::new("downloaded_at"))
+                    .big_integer()
+                    .not_null(),
+            );
+        },
+        foreign_keys: &[("song_id", "song", true)],
+        unique_index: None,
+    },
+    Dependent {
+        table: "album_cover",
+        columns: |table| {
+            table
+                .col(integer("album_id").primary_key())
+                .col(ColumnDef::new(Alias::new("data")).binary().not_null());
+        },
+        foreign_keys: &[("album_id", "album", true)],
+        unique_index: None,
+    },
+    Dependent {
+        table: "artist_alias",
+        columns: |table| {
+            table
+                .col(&mut key("id"))
+                .col(&mut integer("artist_id"))
+                .col(
+                    ColumnDef::new(Alias::new("alias"))
+                        .text()
+                        .not_null()
+                        .unique_key(),
+                );
+        },
+        foreign_keys: &[("artist_id", "artist", true)],
+        unique_index: None,
+    },
+];
+
+fn key(name: &str) -> ColumnDef {
+    ColumnDef::new(Alias::new(name))
+        .integer()
+        .not_null()
+        .auto_increment()
+        .primary_key()
+        .to_owned()
+}
+
+fn integer(name: &str) -> ColumnDef {
+    ColumnDef::new(Alias::new(name))
+        .integer()
+        .not_null()
+        .to_owned()
+}
+
+fn junction_columns(table: &mut TableCreateStatement, column: &str) {
+    table
+        .col(&mut key("key"))
+        .col(&mut integer("song_id"))
+        .col(&mut integer(column));
+}
+
+impl Dependent {
+    fn create(&self, name: &str, cascade: bool) -> TableCreateStatement {
+        let mut table = Table::create();
+        table.table(Alias::new(name));
+        (self.columns)(&mut table);
+        for (column, parent, cascaded) in self.foreign_keys {
+            let mut foreign_key = ForeignKey::create();
+            foreign_key
+                .name(format!("fk-{}-{column}", self.table))
+                .from(Alias::new(name), Alias::new(*column))
+                .to(Alias::new(*parent), Alias::new("id"));
+            if cascade && *cascaded {
+                foreign_key.on_delete(ForeignKeyAction::Cascade);
+            }
+            table.foreign_key(&mut foreign_key);
+        }
+        table.to_owned()
+    }
+
+    /// sqlite can't alter constraints, so the table is copied into a new one with the
+    /// wanted foreign keys. Rows pointing at deleted rows are left behind, they would
+    /// fail the foreign key checks.
+    async fn rebuild(&self, manager: &SchemaManager<'_>, cascade: bool) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let staged = format!("{}_new", self.table);
+        let table = self.create(&staged, cascade);
+        let columns = table
+            .get_columns()
+            .iter()
+            .map(|column| column.get_column_name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        manager.create_table(table).await?;
+
+        let existing = self
+            .foreign_keys
+            .iter()
+            .map(|(column, parent, _)| format!("{column} IN (SELECT id FROM {parent})"))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        db.execute_unprepared(&format!(
+            "INSERT INTO {staged} ({columns}) SELECT {columns} FROM {table}
+            WHERE {existing};",
+            table = self.table,
+        ))
+        .await?;
+        // dropping the table drops its triggers and indexes as well
+        db.execute_unprepared(&format!("DROP TABLE {};", self.table))
+            .await?;
+        // the song_search triggers on other tables name the dropped table, the legacy
+        // rename leaves them as they are instead of failing on the missing table
+        db.execute_unprepared("PRAGMA legacy_alter_table = ON;")
+            .await?;
+        db.execute_unprepared(&format!("ALTER TABLE {staged} RENAME TO {};", self.table))
+            .await?;
+        db.execute_unprepared("PRAGMA legacy_alter_table = OFF;")
+            .await?;
+
+        if let Some(index) = JUNCTIONS.iter().position(|table| *table == self.table) {
+            create_junction_triggers(db, self.table, &TRIGGERS[3 + index * 3..6 + index * 3])
+                .await?;
+        }
+        if let Some((name, columns)) = self.unique_index {
+            let mut index = Index::create();
+            index.name(name).table(Alias::new(self.table)).unique();
+            for column in columns {
+                index.col(Alias::new(*column));
+            }
+            manager.create_index(index.to_owned()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in TABLES {
+            table.rebuild(manager, true).await?;
+        }
+        // links to songs that no longer exist were dropped
+        manager
+            .get_connection()
+            .execute_unprepared(&refresh_songs("IN (SELECT id FROM song)"))
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in TABLES {
+            table.rebuild(manager, false).await?;
+        }
+        Ok(())
+    }
+}
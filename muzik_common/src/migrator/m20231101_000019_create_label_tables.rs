@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231101_000019_create_label_tables"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Label::Table)
+                    .col(
+                        ColumnDef::new(Label::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Label::Name).text().not_null().unique_key())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongLabelJunction::Table)
+                    .col(
+                        ColumnDef::new(SongLabelJunction::Key)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SongLabelJunction::SongId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SongLabelJunction::LabelId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-song_label_junction-song_id")
+                            .from(SongLabelJunction::Table, SongLabelJunction::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-song_label_junction-label_id")
+                            .from(SongLabelJunction::Table, SongLabelJunction::LabelId)
+                            .to(Label::Table, Label::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // a song has a label at most once
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-song_label_junction-song-label")
+                    .table(SongLabelJunction::Table)
+                    .col(SongLabelJunction::SongId)
+                    .col(SongLabelJunction::LabelId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SongLabelJunction::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Label::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Free-form user labels like moods or occasions, unlike genres they aren't read from
+/// the files
+#[derive(Iden)]
+pub enum Label {
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(Iden)]
+pub enum SongLabelJunction {
+    Table,
+    Key,
+    SongId,
+    LabelId,
+}
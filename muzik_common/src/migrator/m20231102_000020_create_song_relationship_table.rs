@@ -0,0 +1,98 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20230601_000001_create_basic_table::Song;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20231102_000020_create_song_relationship_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SongRelationship::Table)
+                    .col(
+                        ColumnDef::new(SongRelationship::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SongRelationship::SongId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SongRelationship::RelatedSongId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SongRelationship::Kind).text().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-song_relationship-song_id")
+                            .from(SongRelationship::Table, SongRelationship::SongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-song_relationship-related_song_id")
+                            .from(SongRelationship::Table, SongRelationship::RelatedSongId)
+                            .to(Song::Table, Song::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // two songs are related at most once, `DbConnection::link_songs` also keeps the
+        // reversed pair out
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-song_relationship-song-related_song")
+                    .table(SongRelationship::Table)
+                    .col(SongRelationship::SongId)
+                    .col(SongRelationship::RelatedSongId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-song_relationship-related_song")
+                    .table(SongRelationship::Table)
+                    .col(SongRelationship::RelatedSongId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SongRelationship::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+/// `song_id` is a `kind` of `related_song_id`, e.g. a remix of it
+#[derive(Iden)]
+pub enum SongRelationship {
+    Table,
+    Id,
+    SongId,
+    RelatedSongId,
+    Kind,
+}
@@ -3,6 +3,24 @@ use sea_orm_migration::prelude::*;
 mod m20230601_000001_create_basic_table;
 mod m20230601_000002_create_junction_tables;
 mod m20230826_000003_alter_song_table_add_path;
+mod m20231017_000004_alter_song_table_add_track_info;
+mod m20231018_000005_alter_song_table_add_audio_properties;
+mod m20231019_000006_alter_song_table_add_content_hash;
+mod m20231020_000007_create_playlist_tables;
+mod m20231021_000008_create_history_tables;
+mod m20231022_000009_create_song_search;
+mod m20231023_000010_alter_album_table_add_album_info;
+mod m20231024_000011_create_artist_alias_table;
+mod m20231025_000012_alter_youtube_playlist_junction_add_membership;
+mod m20231026_000013_alter_song_table_add_play_stats;
+mod m20231027_000014_create_lyrics_table;
+mod m20231028_000015_alter_song_table_add_added_at;
+mod m20231029_000016_alter_song_table_add_source;
+mod m20231030_000017_create_song_download_table;
+mod m20231031_000018_alter_junction_tables_cascade;
+mod m20231101_000019_create_label_tables;
+mod m20231102_000020_create_song_relationship_table;
+mod m20231103_000021_alter_song_table_add_hashed_stamp;
 
 pub struct Migrator;
 
@@ -12,6 +30,24 @@ impl MigratorTrait for Migrator {
             Box::new(m20230601_000001_create_basic_table::Migration),
             Box::new(m20230601_000002_create_junction_tables::Migration),
             Box::new(m20230826_000003_alter_song_table_add_path::Migration),
+            Box::new(m20231017_000004_alter_song_table_add_track_info::Migration),
+            Box::new(m20231018_000005_alter_song_table_add_audio_properties::Migration),
+            Box::new(m20231019_000006_alter_song_table_add_content_hash::Migration),
+            Box::new(m20231020_000007_create_playlist_tables::Migration),
+            Box::new(m20231021_000008_create_history_tables::Migration),
+            Box::new(m20231022_000009_create_song_search::Migration),
+            Box::new(m20231023_000010_alter_album_table_add_album_info::Migration),
+            Box::new(m20231024_000011_create_artist_alias_table::Migration),
+            Box::new(m20231025_000012_alter_youtube_playlist_junction_add_membership::Migration),
+            Box::new(m20231026_000013_alter_song_table_add_play_stats::Migration),
+            Box::new(m20231027_000014_create_lyrics_table::Migration),
+            Box::new(m20231028_000015_alter_song_table_add_added_at::Migration),
+            Box::new(m20231029_000016_alter_song_table_add_source::Migration),
+            Box::new(m20231030_000017_create_song_download_table::Migration),
+            Box::new(m20231031_000018_alter_junction_tables_cascade::Migration),
+            Box::new(m20231101_000019_create_label_tables::Migration),
+            Box::new(m20231102_000020_create_song_relationship_table::Migration),
+            Box::new(m20231103_000021_alter_song_table_add_hashed_stamp::Migration),
         ]
     }
 }
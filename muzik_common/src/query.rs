@@ -0,0 +1,273 @@
+use sea_orm::{
+    sea_query::{Expr, Query, SelectStatement},
+    ColumnTrait, Condition, EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect, Select,
+};
+
+use crate::{
+    data::Source,
+    entities::{
+        album, artist, genre, label, playlist_song, song, song_album_junction,
+        song_artist_junction, song_genre_junction, song_label_junction,
+        song_youtube_playlist_id_junction, youtube_playlist_id,
+    },
+};
+
+/// What songs are sorted by, ties are broken by id
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SongSort {
+    #[default]
+    Id,
+    Title,
+    Year,
+    AddedAt,
+    LastPlayed,
+    PlayCount,
+    Rating,
+}
+
+/// Filters, order and paging for `DbConnection::query_songs`, run as a single SQL query.
+/// Every filter that is set has to match, names are compared exactly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SongQuery {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub label: Option<String>,
+    /// Looked up through the unique index on `song.youtube_id`
+    pub youtube_id: Option<String>,
+    /// Id of the youtube playlist, not the database id
+    pub youtube_playlist: Option<String>,
+    /// Database id of a local playlist
+    pub playlist: Option<i32>,
+    pub source: Option<Source>,
+    /// Songs with (`true`) or without (`false`) a file path in the database. Whether
+    /// the file is still on disk is left to the file scan.
+    pub has_path: Option<bool>,
+    /// Unix timestamps in seconds, inclusive
+    pub added_after: Option<i64>,
+    pub added_before: Option<i64>,
+    pub sort: SongSort,
+    pub descending: bool,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+impl SongQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_artist(mut self, artist: impl Into<String>) -> Self {
+        self.artist = Some(artist.into());
+        self
+    }
+
+    pub fn with_album(mut self, album: impl Into<String>) -> Self {
+        self.album = Some(album.into());
+        self
+    }
+
+    pub fn with_genre(mut self, genre: impl Into<String>) -> Self {
+        self.genre = Some(genre.into());
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_youtube_id(mut self, youtube_id: impl Into<String>) -> Self {
+        self.youtube_id = Some(youtube_id.into());
+        self
+    }
+
+    pub fn with_youtube_playlist(mut self, youtube_playlist: impl Into<String>) -> Self {
+        self.youtube_playlist = Some(youtube_playlist.into());
+        self
+    }
+
+    pub fn with_playlist(mut self, playlist_id: i32) -> Self {
+        self.playlist = Some(playlist_id);
+        self
+    }
+
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_has_path(mut self, has_path: bool) -> Self {
+        self.has_path = Some(has_path);
+        self
+    }
+
+    pub fn with_added_after(mut self, timestamp: i64) -> Self {
+        self.added_after = Some(timestamp);
+        self
+    }
+
+    pub fn with_added_before(mut self, timestamp: i64) -> Self {
+        self.added_before = Some(timestamp);
+        self
+    }
+
+    pub fn with_sort(mut self, sort: SongSort, descending: bool) -> Self {
+        self.sort = sort;
+        self.descending = descending;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// The filters as a condition on the song table
+    fn condition(&self) -> Condition {
+        let mut condition = Condition::all();
+        if let Some(name) = self.artist.as_ref() {
+            condition = condition.add(song::Column::Id.in_subquery(songs_linked_to(
+                song_artist_junction::Entity,
+                song_artist_junction::Column::SongId,
+                song_artist_junction::Column::ArtistId,
+                artist::Entity,
+                artist::Column::Id,
+                Expr::col((artist::Entity, artist::Column::Name)).eq(name.clone()),
+            )));
+        }
+        if let Some(name) = self.album.as_ref() {
+            condition = condition.add(song::Column::Id.in_subquery(songs_linked_to(
+                song_album_junction::Entity,
+                song_album_junction::Column::SongId,
+                song_album_junction::Column::AlbumId,
+                album::Entity,
+                album::Column::Id,
+                Expr::col((album::Entity, album::Column::Name)).eq(name.clone()),
+            )));
+        }
+        if let Some(name) = self.genre.as_ref() {
+            condition = condition.add(song::Column::Id.in_subquery(songs_linked_to(
+                song_genre_junction::Entity,
+                song_genre_junction::Column::SongId,
+                song_genre_junction::Column::GenreId,
+                genre::Entity,
+                genre::Column::Id,
+                Expr::col((genre::Entity, genre::Column::Genre)).eq(name.clone()),
+            )));
+        }
+        if let Some(name) = self.label.as_ref() {
+            condition = condition.add(song::Column::Id.in_subquery(songs_linked_to(
+                song_label_junction::Entity,
+                song_label_junction::Column::SongId,
+                song_label_junction::Column::LabelId,
+                label::Entity,
+                label::Column::Id,
+                Expr::col((label::Entity, label::Column::Name)).eq(name.clone()),
+            )));
+        }
+        if let Some(youtube_playlist) = self.youtube_playlist.as_ref() {
+            condition = condition.add(
+                song::Column::Id.in_subquery(songs_linked_to(
+                    song_youtube_playlist_id_junction::Entity,
+                    song_youtube_playlist_id_junction::Column::SongId,
+                    song_youtube_playlist_id_junction::Column::YoutubePlaylistIdId,
+                    youtube_playlist_id::Entity,
+                    youtube_playlist_id::Column::Id,
+                    Expr::col((
+                        youtube_playlist_id::Entity,
+                        youtube_playlist_id::Column::YoutubePlaylistId,
+                    ))
+                    .eq(youtube_playlist.clone()),
+                )),
+            );
+        }
+        if let Some(playlist_id) = self.playlist {
+            condition = condition.add(
+                song::Column::Id.in_subquery(
+                    Query::select()
+                        .column(playlist_song::Column::SongId)
+                        .from(playlist_song::Entity)
+                        .and_where(playlist_song::Column::PlaylistId.eq(playlist_id))
+                        .to_owned(),
+                ),
+            );
+        }
+        if let Some(youtube_id) = self.youtube_id.as_ref() {
+            condition = condition.add(song::Column::YoutubeId.eq(youtube_id.clone()));
+        }
+        if let Some(source) = self.source {
+            condition = condition.add(song::Column::Source.eq(source));
+        }
+        condition = match self.has_path {
+            Some(true) => condition.add(song::Column::Path.is_not_null()),
+            Some(false) => condition.add(song::Column::Path.is_null()),
+            None => condition,
+        };
+        if let Some(timestamp) = self.added_after {
+            condition = condition.add(song::Column::AddedAt.gte(timestamp));
+        }
+        if let Some(timestamp) = self.added_before {
+            condition = condition.add(song::Column::AddedAt.lte(timestamp));
+        }
+        condition
+    }
+
+    /// The query as a select on the song table
+    pub(crate) fn select(&self) -> Select<song::Entity> {
+        let order = if self.descending {
+            Order::Desc
+        } else {
+            Order::Asc
+        };
+        let column = match self.sort {
+            SongSort::Id => song::Column::Id,
+            SongSort::Title => song::Column::Title,
+            SongSort::Year => song::Column::Year,
+            SongSort::AddedAt => song::Column::AddedAt,
+            SongSort::LastPlayed => song::Column::LastPlayed,
+            SongSort::PlayCount => song::Column::PlayCount,
+            SongSort::Rating => song::Column::Rating,
+        };
+        let mut select = song::Entity::find()
+            .filter(self.condition())
+            .order_by(column, order);
+        if self.sort != SongSort::Id {
+            select = select.order_by_asc(song::Column::Id);
+        }
+        // sqlite only takes an offset after a limit
+        if self.limit.is_some() || self.offset.is_some() {
+            select = select.limit(self.limit.unwrap_or(i64::MAX as u64));
+        }
+        if let Some(offset) = self.offset {
+            select = select.offset(offset);
+        }
+        select
+    }
+}
+
+/// Ids of the songs a junction table links to rows of `table` matching `filter`
+fn songs_linked_to<J, T>(
+    junction: J,
+    song_id: J::Column,
+    foreign_id: J::Column,
+    table: T,
+    id: T::Column,
+    filter: sea_orm::sea_query::SimpleExpr,
+) -> SelectStatement
+where
+    J: EntityTrait,
+    T: EntityTrait,
+{
+    Query::select()
+        .column((junction, song_id))
+        .from(junction)
+        .inner_join(table, Expr::col((table, id)).equals((junction, foreign_id)))
+        .and_where(filter)
+        .to_owned()
+}
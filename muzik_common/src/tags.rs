@@ -1,21 +1,373 @@
-use std::{io::Cursor, path::PathBuf};
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
-use lofty::{Accessor, ItemKey, ItemValue, Picture, Probe, Tag, TagExt, TagItem, TaggedFileExt};
+use lofty::{
+    Accessor, AudioFile, FileType, ItemKey, ItemValue, Picture, Probe, Tag, TagExt, TagItem,
+    TagType, TaggedFile, TaggedFileExt,
+};
+use sea_orm::ActiveEnum;
+use sha2::{Digest, Sha256};
 use tracing::error;
 
 use crate::{
-    data::Song,
+    data::{AudioProperties, LyricLine, Lyrics, PlayStats, Song},
     database::AppSong,
-    entities::{album::AlbumModel, artist::ArtistModel, genre::GenreModel},
+    entities::{
+        album::AlbumModel, artist::ArtistModel, genre::GenreModel, label::LabelModel, song::Source,
+    },
 };
 
 use self::error::TagError;
 
-pub async fn write_tags_async(path: PathBuf, song: &AppSong) -> Result<(), TagError> {
+pub async fn write_tags_async(path: PathBuf, song: &AppSong) -> Result<AudioProperties, TagError> {
     write_tags(path, song).await
 }
 
-pub async fn write_tags(path: PathBuf, song: &AppSong) -> Result<(), TagError> {
+/// Collects the audio properties of an already read file
+fn audio_properties(tagged_file: &TaggedFile, path: &Path) -> AudioProperties {
+    let properties = tagged_file.properties();
+    AudioProperties {
+        duration_ms: Some(properties.duration().as_millis() as i64),
+        bitrate: properties.overall_bitrate().map(|b| b as i32),
+        codec: Some(format!("{:?}", tagged_file.file_type())),
+        sample_rate: properties.sample_rate().map(|s| s as i32),
+        file_size: std::fs::metadata(path).ok().map(|m| m.len() as i64),
+    }
+}
+
+/// Written as the POPM email so other players' ratings are left alone
+const POPM_EMAIL: &str = "muzik";
+/// POPM ratings of 0 to 5 stars, as used by Windows Media Player and most players
+const POPM_RATINGS: [u8; 6] = [0, 1, 64, 128, 196, 255];
+/// Custom tag holding whether the song is a favourite, `1` or `0`. A TXXX frame for ID3v2
+const FAVOURITE_KEY: &str = "FAVOURITE";
+
+/// FMPS_Rating, 0.0 to 1.0. ID3v2 TXXX descriptions are case sensitive and the spec
+/// spells them in mixed case there
+fn fmps_rating_key(tag_type: TagType) -> &'static str {
+    match tag_type {
+        TagType::Id3v2 => "FMPS_Rating",
+        _ => "FMPS_RATING",
+    }
+}
+
+/// Whether the item is the POPM frame written by `write_play_stats`
+fn is_own_popm(item: &TagItem) -> bool {
+    item.key() == &ItemKey::Popularimeter
+        && item
+            .value()
+            .binary()
+            .and_then(|popm| popm.split(|b| *b == 0).next())
+            == Some(POPM_EMAIL.as_bytes())
+}
+
+/// Writes the rating and play count to the standard tags of the format: POPM and
+/// FMPS_Rating for ID3v2, RATING and FMPS_RATING / FMPS_PLAYCOUNT for the others. The
+/// favourite flag has no standard tag and is written as FAVOURITE.
+fn write_play_stats(tag: &mut Tag, play_stats: &PlayStats) {
+    let tag_type = tag.tag_type();
+    let rating_key = fmps_rating_key(tag_type);
+    let mut items = vec![(FAVOURITE_KEY, (play_stats.favourite as u8).to_string())];
+    if let Some(rating) = play_stats.rating {
+        items.push((rating_key, (rating as f32 / 5.0).to_string()));
+    }
+
+    if tag_type == TagType::Id3v2 {
+        let rating = play_stats
+            .rating
+            .map_or(0, |rating| POPM_RATINGS[rating.clamp(0, 5) as usize]);
+        let mut popm = POPM_EMAIL.as_bytes().to_vec();
+        popm.push(0);
+        popm.push(rating);
+        popm.extend_from_slice(&(play_stats.play_count.max(0) as u32).to_be_bytes());
+
+        // POPM frames are kept per email, only replace ours
+        tag.retain(|item| !is_own_popm(item));
+        tag.push_unchecked(TagItem::new(
+            ItemKey::Popularimeter,
+            ItemValue::Binary(popm),
+        ));
+        for key in [rating_key, FAVOURITE_KEY] {
+            tag.remove_key(&ItemKey::Unknown(key.to_string()));
+        }
+    } else {
+        for key in ["RATING", rating_key, "FMPS_PLAYCOUNT", FAVOURITE_KEY] {
+            tag.remove_key(&ItemKey::Unknown(key.to_string()));
+        }
+        items.push(("FMPS_PLAYCOUNT", play_stats.play_count.to_string()));
+        if let Some(rating) = play_stats.rating {
+            // RATING is commonly read as 0 to 100
+            items.push(("RATING", (rating * 20).to_string()));
+        }
+    }
+
+    for (key, value) in items {
+        tag.insert_unchecked(TagItem::new(
+            ItemKey::Unknown(key.to_string()),
+            ItemValue::Text(value),
+        ));
+    }
+}
+
+/// Reads the rating and play count of our POPM frame: email, 0, rating, play counter of
+/// 4 or more bytes. A rating of 0 means unrated
+fn parse_popm(popm: &[u8]) -> Option<(Option<i32>, i32)> {
+    let email_end = popm.iter().position(|b| *b == 0)?;
+    let rating = *popm.get(email_end + 1)?;
+    let play_count = popm
+        .get(email_end + 2..)
+        .unwrap_or_default()
+        .iter()
+        .fold(0u64, |count, b| (count << 8) | *b as u64);
+    let rating = (rating != 0).then(|| {
+        POPM_RATINGS
+            .iter()
+            .rposition(|popm_rating| *popm_rating <= rating)
+            .unwrap_or(0) as i32
+    });
+    Some((rating, play_count.min(i32::MAX as u64) as i32))
+}
+
+/// Reads what `write_play_stats` writes, `None` if the tag has none of it
+fn read_play_stats(tag: &Tag) -> Option<PlayStats> {
+    let get = |key: &str| tag.get_string(&ItemKey::Unknown(key.to_string()));
+    let fmps_rating = get(fmps_rating_key(tag.tag_type()))
+        .and_then(|rating| rating.parse::<f32>().ok())
+        .map(|rating| (rating * 5.0).round() as i32);
+    let favourite = get(FAVOURITE_KEY).map(|favourite| favourite == "1");
+
+    let (rating, play_count) = if tag.tag_type() == TagType::Id3v2 {
+        let (popm_rating, play_count) = tag
+            .get_items(&ItemKey::Popularimeter)
+            .find(|item| is_own_popm(item))
+            .and_then(|item| item.value().binary())
+            .and_then(parse_popm)
+            .unzip();
+        // POPM can't tell 0 stars from unrated, FMPS_Rating can
+        (fmps_rating.or(popm_rating.flatten()), play_count)
+    } else {
+        // some players write RATING as 0 to 5, others as 0 to 100
+        let rating = fmps_rating.or_else(|| {
+            get("RATING")
+                .and_then(|rating| rating.parse::<i32>().ok())
+                .map(|rating| if rating > 5 { rating / 20 } else { rating })
+        });
+        let play_count = get("FMPS_PLAYCOUNT")
+            .and_then(|count| count.parse::<f32>().ok())
+            .map(|count| count as i32);
+        (rating, play_count)
+    };
+    if rating.is_none() && play_count.is_none() && favourite.is_none() {
+        return None;
+    }
+    Some(PlayStats {
+        rating: rating.map(|rating| rating.clamp(0, 5)),
+        favourite: favourite.unwrap_or(false),
+        play_count: play_count.unwrap_or(0),
+        last_played: None,
+    })
+}
+
+/// Writes only the rating, favourite flag and play count to the file, the other tags
+/// are left as they are. Used after every play, so nothing is fetched or recomputed.
+pub async fn write_play_stats_to_file(
+    path: PathBuf,
+    play_stats: &PlayStats,
+) -> Result<(), TagError> {
+    let mut tagged_file = Probe::open(path.clone())?.read()?;
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+    }
+    let Some(tag) = tagged_file.primary_tag_mut() else {
+        return Ok(());
+    };
+    write_play_stats(tag, play_stats);
+    tag.save_to_path(&path)?;
+    Ok(())
+}
+
+/// Custom tag holding the user labels of a song, joined with `; `
+const LABELS_KEY: &str = "MUZIK_LABELS";
+
+/// Reads what `write_labels` writes
+fn read_labels(tag: &Tag) -> Vec<LabelModel> {
+    tag.get_string(&ItemKey::Unknown(LABELS_KEY.to_string()))
+        .map(|labels| {
+            labels
+                .split(';')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| LabelModel {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Writes the labels to the `MUZIK_LABELS` tag of the file, removing it when there are
+/// none. The other tags are left as they are.
+pub async fn write_labels(path: PathBuf, labels: &[String]) -> Result<(), TagError> {
+    let mut tagged_file = Probe::open(path.clone())?.read()?;
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+    }
+    let Some(tag) = tagged_file.primary_tag_mut() else {
+        return Ok(());
+    };
+    let key = ItemKey::Unknown(LABELS_KEY.to_string());
+    tag.remove_key(&key);
+    if !labels.is_empty() {
+        tag.insert_unchecked(TagItem::new(key, ItemValue::Text(labels.join("; "))));
+    }
+    tag.save_to_path(&path)?;
+    Ok(())
+}
+
+/// Custom tags holding where a song was downloaded from, as stored in the database
+const SOURCE_KEY: &str = "MUZIK_SOURCE";
+const SOURCE_URL_KEY: &str = "MUZIK_SOURCE_URL";
+
+/// Writes the source of a downloaded song, local files have none
+fn write_source(tag: &mut Tag, source: Source, source_url: Option<String>) {
+    for key in [SOURCE_KEY, SOURCE_URL_KEY] {
+        tag.remove_key(&ItemKey::Unknown(key.to_string()));
+    }
+    if source == Source::Local {
+        return;
+    }
+    let items = [
+        (SOURCE_KEY, Some(source.to_value())),
+        (SOURCE_URL_KEY, source_url),
+    ];
+    for (key, value) in items {
+        if let Some(value) = value {
+            tag.insert_unchecked(TagItem::new(
+                ItemKey::Unknown(key.to_string()),
+                ItemValue::Text(value),
+            ));
+        }
+    }
+}
+
+/// Reads what `write_source` writes, `None` for files without a known source
+fn read_source(tag: &Tag) -> Option<(Source, Option<String>)> {
+    let source = tag.get_string(&ItemKey::Unknown(SOURCE_KEY.to_string()))?;
+    let source = Source::try_from_value(&source.to_string()).ok()?;
+    let source_url = tag
+        .get_string(&ItemKey::Unknown(SOURCE_URL_KEY.to_string()))
+        .map(str::to_string);
+    Some((source, source_url))
+}
+
+/// Only one album artist per file, taken from the first album that has one. Without one
+/// the tag is removed, so a cleared album artist doesn't stay in the file.
+fn write_album_artist(tag: &mut Tag, albums: &[AlbumModel]) {
+    tag.remove_key(&ItemKey::AlbumArtist);
+    if let Some(album_artist) = albums
+        .iter()
+        .filter_map(|album| album.album_artist.as_deref())
+        .find(|album_artist| !album_artist.is_empty())
+    {
+        tag.insert_text(ItemKey::AlbumArtist, album_artist.to_owned());
+    }
+}
+
+/// Writes plain lyrics as USLT and synced lines as SYLT for ID3v2. Other formats get
+/// a single LYRICS tag, holding LRC when the lyrics are synced.
+fn write_lyrics(tag: &mut Tag, lyrics: &Lyrics) {
+    tag.remove_key(&ItemKey::Lyrics);
+    if tag.tag_type() != TagType::Id3v2 {
+        tag.insert_text(
+            ItemKey::Lyrics,
+            lyrics.to_lrc().unwrap_or_else(|| lyrics.plain.clone()),
+        );
+        return;
+    }
+
+    tag.insert_text(ItemKey::Lyrics, lyrics.plain.clone());
+    tag.remove_key(&ItemKey::Unknown("SYLT".to_string()));
+    if let Some(lines) = &lyrics.synced {
+        // UTF-8, unknown language, timestamps in milliseconds, content type lyrics,
+        // empty description
+        let mut sylt = vec![3];
+        sylt.extend_from_slice(b"XXX");
+        sylt.extend_from_slice(&[2, 1, 0]);
+        for line in lines {
+            sylt.extend_from_slice(line.text.as_bytes());
+            sylt.push(0);
+            sylt.extend_from_slice(&line.time_ms.to_be_bytes());
+        }
+        tag.insert_unchecked(TagItem::new(
+            ItemKey::Unknown("SYLT".to_string()),
+            ItemValue::Binary(sylt),
+        ));
+    }
+}
+
+/// Reads what `write_lyrics` writes, LYRICS and USLT may hold LRC as well
+fn read_lyrics(tag: &Tag) -> Option<Lyrics> {
+    let mut lyrics = tag.get_string(&ItemKey::Lyrics).map(Lyrics::from_lrc);
+    if tag.tag_type() == TagType::Id3v2 {
+        if let Some(lines) = tag
+            .get_binary(&ItemKey::Unknown("SYLT".to_string()), false)
+            .and_then(parse_sylt)
+        {
+            let lyrics = lyrics.get_or_insert_with(Lyrics::default);
+            if lyrics.plain.is_empty() {
+                lyrics.plain = lines
+                    .iter()
+                    .map(|line| line.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+            lyrics.synced = Some(lines);
+        }
+    }
+    lyrics.filter(|lyrics| !lyrics.plain.is_empty() || lyrics.synced.is_some())
+}
+
+/// Parses a SYLT frame with millisecond timestamps, only single byte encodings are supported
+fn parse_sylt(sylt: &[u8]) -> Option<Vec<LyricLine>> {
+    let (&encoding, rest) = sylt.split_first()?;
+    // skip the language
+    let (&timestamp_format, rest) = rest.get(3..)?.split_first()?;
+    if !matches!(encoding, 0 | 3) || timestamp_format != 2 {
+        return None;
+    }
+    // skip the content type and the description
+    let rest = rest.get(1..)?;
+    let mut rest = &rest[rest.iter().position(|b| *b == 0)? + 1..];
+
+    let mut lines = vec![];
+    while let Some(end) = rest.iter().position(|b| *b == 0) {
+        let time = rest.get(end + 1..end + 5)?;
+        lines.push(LyricLine {
+            time_ms: u32::from_be_bytes(time.try_into().ok()?),
+            text: String::from_utf8_lossy(&rest[..end]).to_string(),
+        });
+        rest = &rest[end + 5..];
+    }
+    Some(lines)
+}
+
+/// Reads the `.lrc` file next to the song, if there is one
+pub async fn read_lrc_sidecar(path: &Path) -> Result<Option<Lyrics>, TagError> {
+    let lrc_path = path.with_extension("lrc");
+    if !lrc_path.exists() {
+        return Ok(None);
+    }
+    let lrc = std::fs::read_to_string(lrc_path)?;
+    Ok(Some(Lyrics::from_lrc(&lrc)))
+}
+
+/// Writes the tags of the song, returning the audio properties of the written file
+pub async fn write_tags(path: PathBuf, song: &AppSong) -> Result<AudioProperties, TagError> {
     match Probe::open(path.clone())?.read() {
         Ok(mut tagged_file) => {
             let tag = match tagged_file.primary_tag_mut() {
@@ -72,6 +424,8 @@ pub async fn write_tags(path: PathBuf, song: &AppSong) -> Result<(), TagError> {
 
                     tag_items.push(tag_item);
                 }
+
+                write_album_artist(tag, albums);
             }
 
             if let Some(genre) = &song.genre {
@@ -88,6 +442,33 @@ pub async fn write_tags(path: PathBuf, song: &AppSong) -> Result<(), TagError> {
                 }
             }
 
+            if let Some(track_number) = song.track_number {
+                tag.set_track(track_number as u32);
+            }
+
+            if let Some(track_total) = song.track_total {
+                tag.set_track_total(track_total as u32);
+            }
+
+            if let Some(disc_number) = song.disc_number {
+                tag.set_disk(disc_number as u32);
+            }
+
+            if let Some(year) = song.year {
+                tag.set_year(year as u32);
+            }
+
+            if let Some(release_date) = &song.release_date {
+                tag.remove_key(&ItemKey::RecordingDate);
+                if let Some(tag_item) = TagItem::new_checked(
+                    tag.tag_type(),
+                    ItemKey::RecordingDate,
+                    ItemValue::Text(release_date.clone()),
+                ) {
+                    tag_items.push(tag_item);
+                }
+            }
+
             if let Some(yt_id) = &song.yt_id {
                 tag.remove_key(&ItemKey::Unknown("YTID".to_string()));
                 let tag_item = TagItem::new(
@@ -106,6 +487,16 @@ pub async fn write_tags(path: PathBuf, song: &AppSong) -> Result<(), TagError> {
                 tag.insert_unchecked(tag_item);
             }
 
+            write_source(tag, song.source, song.get_source_url());
+
+            if let Some(play_stats) = &song.play_stats {
+                write_play_stats(tag, play_stats);
+            }
+
+            if let Some(lyrics) = &song.lyrics {
+                write_lyrics(tag, lyrics);
+            }
+
             // TODO: write database id
 
             if let Some(picture_url) = &song.tb_url {
@@ -137,14 +528,15 @@ pub async fn write_tags(path: PathBuf, song: &AppSong) -> Result<(), TagError> {
                 tag.push(tag_item);
             }
 
-            tag.save_to_path(path)?;
-            Ok(())
+            tag.save_to_path(&path)?;
+            Ok(audio_properties(&tagged_file, &path))
         }
         Err(e) => Err(TagError::LoftyError(e)),
     }
 }
 
-pub async fn write_tags_song(path: PathBuf, song: &Song) -> Result<(), TagError> {
+/// Writes the tags of the song, returning the audio properties of the written file
+pub async fn write_tags_song(path: PathBuf, song: &Song) -> Result<AudioProperties, TagError> {
     match Probe::open(path.clone())?.read() {
         Ok(mut tagged_file) => {
             let tag = match tagged_file.primary_tag_mut() {
@@ -201,6 +593,8 @@ pub async fn write_tags_song(path: PathBuf, song: &Song) -> Result<(), TagError>
 
                     tag_items.push(tag_item);
                 }
+
+                write_album_artist(tag, albums);
             }
 
             if let Some(genre) = &song.genres {
@@ -217,6 +611,33 @@ pub async fn write_tags_song(path: PathBuf, song: &Song) -> Result<(), TagError>
                 }
             }
 
+            if let Some(track_number) = song.track_number {
+                tag.set_track(track_number as u32);
+            }
+
+            if let Some(track_total) = song.track_total {
+                tag.set_track_total(track_total as u32);
+            }
+
+            if let Some(disc_number) = song.disc_number {
+                tag.set_disk(disc_number as u32);
+            }
+
+            if let Some(year) = song.year {
+                tag.set_year(year as u32);
+            }
+
+            if let Some(release_date) = &song.release_date {
+                tag.remove_key(&ItemKey::RecordingDate);
+                if let Some(tag_item) = TagItem::new_checked(
+                    tag.tag_type(),
+                    ItemKey::RecordingDate,
+                    ItemValue::Text(release_date.clone()),
+                ) {
+                    tag_items.push(tag_item);
+                }
+            }
+
             if let Some(yt_id) = &song.youtube_id {
                 tag.remove_key(&ItemKey::Unknown("YTID".to_string()));
                 let tag_item = TagItem::new(
@@ -235,6 +656,16 @@ pub async fn write_tags_song(path: PathBuf, song: &Song) -> Result<(), TagError>
                 tag.insert_unchecked(tag_item);
             }
 
+            write_source(tag, song.source, song.get_source_url());
+
+            if let Some(play_stats) = &song.play_stats {
+                write_play_stats(tag, play_stats);
+            }
+
+            if let Some(lyrics) = &song.lyrics {
+                write_lyrics(tag, lyrics);
+            }
+
             if let Some(picture_url) = &song.thumbnail_url {
                 tag.remove_picture_type(lofty::PictureType::CoverFront);
                 if picture_url.contains("http") {
@@ -258,14 +689,23 @@ pub async fn write_tags_song(path: PathBuf, song: &Song) -> Result<(), TagError>
                         }
                     }
                 }
+            } else if let Some(thumbnail) = &song.thumbnail {
+                // local thumbnails, like album covers, are already PNG encoded
+                tag.remove_picture_type(lofty::PictureType::CoverFront);
+                tag.push_picture(Picture::new_unchecked(
+                    lofty::PictureType::CoverFront,
+                    lofty::MimeType::Png,
+                    None,
+                    thumbnail.clone(),
+                ));
             }
 
             for tag_item in tag_items {
                 tag.push(tag_item);
             }
 
-            tag.save_to_path(path)?;
-            Ok(())
+            tag.save_to_path(&path)?;
+            Ok(audio_properties(&tagged_file, &path))
         }
         Err(e) => Err(TagError::LoftyError(e)),
     }
@@ -297,10 +737,12 @@ pub async fn read_tags_to_gui_song(path: PathBuf) -> Result<Song, TagError> {
                     ..Default::default()
                 })
                 .collect::<Vec<_>>();
+            let album_artist = tag.get_string(&ItemKey::AlbumArtist).map(str::to_string);
             let albums = tag
                 .get_strings(&ItemKey::AlbumTitle)
                 .map(|a| AlbumModel {
                     name: a.to_string(),
+                    album_artist: album_artist.clone(),
                     ..Default::default()
                 })
                 .collect::<Vec<_>>();
@@ -313,7 +755,7 @@ pub async fn read_tags_to_gui_song(path: PathBuf) -> Result<Song, TagError> {
                 .collect::<Vec<_>>();
 
             let mut song = Song::new()
-                .set_path(path)
+                .set_path(path.clone())
                 .set_title(title)
                 .set_artists(artists)
                 .set_albums(albums)
@@ -321,17 +763,194 @@ pub async fn read_tags_to_gui_song(path: PathBuf) -> Result<Song, TagError> {
 
             if let Some(youtube_id) = tag.get_string(&ItemKey::Unknown("YTID".to_string())) {
                 song.set_youtube_id(youtube_id.to_string());
-                song.set_source(crate::data::Source::Youtube);
+                song.set_source(Source::Youtube);
+            }
+            if let Some((source, source_url)) = read_source(tag) {
+                song.set_source(source);
+                song.set_source_url(source_url);
             }
             if let Some(id) = tag.get_string(&ItemKey::Unknown("DBID".to_string())) {
                 song.set_id(id.parse::<i32>().expect("no fail"));
             };
 
+            if let Some(track_number) = tag.track() {
+                song.set_track_number(track_number as i32);
+            }
+            if let Some(track_total) = tag.track_total() {
+                song.set_track_total(track_total as i32);
+            }
+            if let Some(disc_number) = tag.disk() {
+                song.set_disc_number(disc_number as i32);
+            }
+            if let Some(year) = tag.year() {
+                song.set_year(year as i32);
+            }
+            if let Some(release_date) = tag.get_string(&ItemKey::RecordingDate) {
+                song.set_release_date(release_date.to_string());
+            }
+            song.set_play_stats(read_play_stats(tag));
+            song.set_lyrics(read_lyrics(tag));
+            song.set_labels(read_labels(tag));
+
+            song.set_audio_properties(Some(audio_properties(&tagged_file, &path)));
+
             Ok(song)
         }
         Err(e) => Err(TagError::LoftyError(e)),
     }
 }
+/// Reads `N` bytes at `at`
+fn read_array<const N: usize>(file: &mut File, at: u64) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    file.seek(SeekFrom::Start(at))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Where the data after an ID3v2 tag starting at `at` begins, `at` if there is none
+fn skip_id3v2(file: &mut File, at: u64, len: u64) -> std::io::Result<u64> {
+    if at + 10 > len {
+        return Ok(at);
+    }
+    let header = read_array::<10>(file, at)?;
+    if &header[..3] != b"ID3" {
+        return Ok(at);
+    }
+    // the size is syncsafe, 7 bits per byte, and excludes the header and footer
+    let size = header[6..]
+        .iter()
+        .fold(0u64, |size, b| (size << 7) | (*b & 0x7F) as u64);
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Ok((at + 10 + size + footer).min(len))
+}
+
+/// Where the data before trailing ID3v1 and APEv2 tags ends
+fn skip_trailing_tags(file: &mut File, start: u64, end: u64) -> std::io::Result<u64> {
+    let mut end = end;
+    if end >= start + 128 && &read_array::<3>(file, end - 128)? == b"TAG" {
+        end -= 128;
+    }
+    if end >= start + 32 {
+        let footer = read_array::<32>(file, end - 32)?;
+        if &footer[..8] == b"APETAGEX" {
+            // the size includes the footer but not the optional header
+            let size = u32::from_le_bytes(footer[12..16].try_into().expect("4 bytes")) as u64;
+            let flags = u32::from_le_bytes(footer[20..24].try_into().expect("4 bytes"));
+            let header = if flags & 0x8000_0000 != 0 { 32 } else { 0 };
+            end = end.saturating_sub(size + header).max(start);
+        }
+    }
+    Ok(end)
+}
+
+/// The byte ranges holding the audio of a file, everything but its tags
+fn audio_ranges(
+    file: &mut File,
+    file_type: Option<FileType>,
+    len: u64,
+) -> std::io::Result<Vec<Range<u64>>> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed file");
+    let mut ranges = vec![];
+    match file_type {
+        Some(FileType::Flac) => {
+            let mut at = skip_id3v2(file, 0, len)?;
+            if &read_array::<4>(file, at)? == b"fLaC" {
+                at += 4;
+                // metadata blocks, the vorbis comments and pictures among them
+                loop {
+                    let header = read_array::<4>(file, at)?;
+                    at += 4 + u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+                    if header[0] & 0x80 != 0 {
+                        break;
+                    }
+                }
+            }
+            ranges.push(at..skip_trailing_tags(file, at, len)?);
+        }
+        Some(FileType::Opus | FileType::Vorbis | FileType::Speex) => {
+            // page headers hold sequence numbers and checksums that change when the
+            // comment header grows, only the packet data of the audio pages is hashed
+            let mut at = 0;
+            while at + 27 <= len {
+                let header = read_array::<27>(file, at)?;
+                if &header[..4] != b"OggS" {
+                    return Err(invalid());
+                }
+                let mut segments = vec![0; header[26] as usize];
+                file.read_exact(&mut segments)?;
+                let start = at + 27 + segments.len() as u64;
+                let end = start + segments.iter().map(|size| *size as u64).sum::<u64>();
+                // the header packets, tags included, are on pages with granule position 0
+                let granule = u64::from_le_bytes(header[6..14].try_into().expect("8 bytes"));
+                if granule != 0 {
+                    ranges.push(start..end.min(len));
+                }
+                at = end;
+            }
+        }
+        Some(FileType::Mp4) => {
+            // the tags are in moov, the audio in mdat
+            let mut at = 0;
+            while at + 8 <= len {
+                let header = read_array::<8>(file, at)?;
+                let (header_size, size) =
+                    match u32::from_be_bytes(header[..4].try_into().expect("4 bytes")) {
+                        0 => (8, len - at),
+                        1 => (16, u64::from_be_bytes(read_array::<8>(file, at + 8)?)),
+                        size => (8, size as u64),
+                    };
+                if size < header_size {
+                    return Err(invalid());
+                }
+                if &header[4..] == b"mdat" {
+                    ranges.push(at + header_size..(at + size).min(len));
+                }
+                at += size;
+            }
+        }
+        Some(file_type @ (FileType::Wav | FileType::Aiff)) => {
+            // RIFF and AIFF chunks after the 12 byte file header, sizes are little endian
+            // for RIFF and big endian for AIFF
+            let mut at = 12;
+            while at + 8 <= len {
+                let header = read_array::<8>(file, at)?;
+                let size_bytes = header[4..].try_into().expect("4 bytes");
+                let size = match file_type {
+                    FileType::Wav => u32::from_le_bytes(size_bytes),
+                    _ => u32::from_be_bytes(size_bytes),
+                } as u64;
+                if matches!(&header[..4], b"data" | b"SSND") {
+                    ranges.push(at + 8..(at + 8 + size).min(len));
+                }
+                // chunks are padded to an even size
+                at += 8 + size + size % 2;
+            }
+        }
+        // MPEG, AAC, APE, Musepack and WavPack streams with tags before or after them
+        _ => {
+            let start = skip_id3v2(file, 0, len)?;
+            ranges.push(start..skip_trailing_tags(file, start, len)?);
+        }
+    }
+    Ok(ranges)
+}
+
+/// Hashes the audio stream only, so retagging a file keeps its hash
+///
+/// The file is read in place, skipping the bytes that hold its tags
+pub async fn read_content_hash(path: PathBuf) -> Result<String, TagError> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let file_type = Probe::new(&mut file).guess_file_type()?.file_type();
+
+    let mut hasher = Sha256::new();
+    for range in audio_ranges(&mut file, file_type, len)? {
+        file.seek(SeekFrom::Start(range.start))?;
+        std::io::copy(&mut (&mut file).take(range.end - range.start), &mut hasher)?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 pub async fn read_picture(path: PathBuf) -> Result<Vec<u8>, TagError> {
     match Probe::open(path.clone())?.read() {
         Ok(mut tagged_file) => {
@@ -384,3 +1003,275 @@ pub mod error {
         ImageError(#[from] image::ImageError),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Seek, SeekFrom},
+        path::{Path, PathBuf},
+    };
+
+    use lofty::{FileType, ItemKey, ItemValue, Probe, Tag, TagExt, TagItem, TaggedFileExt};
+
+    use super::{
+        audio_ranges, read_content_hash, read_tags_to_gui_song, write_lyrics,
+        write_play_stats_to_file, write_source,
+    };
+    use crate::{
+        data::{Lyrics, PlayStats},
+        entities::song::Source,
+    };
+
+    /// Ten silent MPEG-1 layer III frames, 128 kbit/s at 44.1 kHz, without tags
+    fn mp3_file(dir: &Path) -> PathBuf {
+        let mut frame = vec![0u8; 417];
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        let path = dir.join("song.mp3");
+        std::fs::write(&path, frame.repeat(10)).unwrap();
+        path
+    }
+
+    /// A FLAC stream info block for a second of 16 bit stereo at 44.1 kHz, without tags
+    fn flac_file(dir: &Path) -> PathBuf {
+        let mut flac = b"fLaC".to_vec();
+        // last metadata block, stream info, 34 bytes long
+        flac.extend_from_slice(&[0x80, 0x00, 0x00, 0x22]);
+        // block sizes, then the unknown frame sizes
+        flac.extend_from_slice(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+        let sample_rate = 44_100u64;
+        let info = (sample_rate << 44) | (1 << 41) | (15 << 36) | sample_rate;
+        flac.extend_from_slice(&info.to_be_bytes());
+        // no MD5 of the audio
+        flac.extend_from_slice(&[0; 16]);
+        let path = dir.join("song.flac");
+        std::fs::write(&path, flac).unwrap();
+        path
+    }
+
+    async fn round_trip(path: PathBuf, play_stats: PlayStats) -> Option<PlayStats> {
+        write_play_stats_to_file(path.clone(), &play_stats)
+            .await
+            .unwrap();
+        read_tags_to_gui_song(path).await.unwrap().play_stats
+    }
+
+    #[tokio::test]
+    async fn play_stats_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [mp3_file(dir.path()), flac_file(dir.path())] {
+            for play_stats in [
+                PlayStats {
+                    rating: Some(4),
+                    favourite: true,
+                    play_count: 12,
+                    last_played: None,
+                },
+                // 0 stars is a rating, unlike no rating
+                PlayStats {
+                    rating: Some(0),
+                    favourite: false,
+                    play_count: 1,
+                    last_played: None,
+                },
+                PlayStats {
+                    rating: None,
+                    favourite: true,
+                    play_count: 0,
+                    last_played: None,
+                },
+            ] {
+                assert_eq!(
+                    round_trip(path.clone(), play_stats.clone()).await,
+                    Some(play_stats),
+                    "{}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn source_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = "https://artist.bandcamp.com/track/song";
+        for path in [mp3_file(dir.path()), flac_file(dir.path())] {
+            let mut tagged_file = Probe::open(&path).unwrap().read().unwrap();
+            tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+            let tag = tagged_file.primary_tag_mut().unwrap();
+            write_source(tag, Source::Bandcamp, Some(url.to_string()));
+            tag.save_to_path(&path).unwrap();
+
+            let song = read_tags_to_gui_song(path.clone()).await.unwrap();
+            assert_eq!(song.source, Source::Bandcamp, "{}", path.display());
+            assert_eq!(song.source_url.as_deref(), Some(url));
+        }
+    }
+
+    #[tokio::test]
+    async fn synced_lyrics_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let lyrics = Lyrics::from_lrc("[00:01.50]Stellar\n[00:03.25]Stellar Stellar");
+        // SYLT for ID3v2, LRC in the lyrics tag for the others
+        for path in [mp3_file(dir.path()), flac_file(dir.path())] {
+            let mut tagged_file = Probe::open(&path).unwrap().read().unwrap();
+            tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+            let tag = tagged_file.primary_tag_mut().unwrap();
+            write_lyrics(tag, &lyrics);
+            tag.save_to_path(&path).unwrap();
+
+            let song = read_tags_to_gui_song(path.clone()).await.unwrap();
+            assert_eq!(song.lyrics.as_ref(), Some(&lyrics), "{}", path.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn play_stats_only_read_own_popm_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = mp3_file(dir.path());
+        let mut tagged_file = Probe::open(&path).unwrap().read().unwrap();
+        tagged_file.insert_tag(lofty::Tag::new(lofty::TagType::Id3v2));
+        let tag = tagged_file.primary_tag_mut().unwrap();
+        // another player rated it 1 star and played it 200 times
+        let mut popm = b"player@example.org\0\x01".to_vec();
+        popm.extend_from_slice(&200u32.to_be_bytes());
+        tag.push_unchecked(TagItem::new(
+            ItemKey::Popularimeter,
+            ItemValue::Binary(popm),
+        ));
+        tag.save_to_path(&path).unwrap();
+
+        let play_stats = PlayStats {
+            rating: Some(5),
+            favourite: false,
+            play_count: 3,
+            last_played: None,
+        };
+        assert_eq!(round_trip(path, play_stats.clone()).await, Some(play_stats));
+    }
+
+    /// The bytes `read_content_hash` hashes for a file of `file_type`
+    fn audio_of(path: &Path, file_type: FileType) -> Vec<u8> {
+        let mut file = File::open(path).unwrap();
+        let len = file.metadata().unwrap().len();
+        let mut audio = vec![];
+        for range in audio_ranges(&mut file, Some(file_type), len).unwrap() {
+            file.seek(SeekFrom::Start(range.start)).unwrap();
+            (&mut file)
+                .take(range.end - range.start)
+                .read_to_end(&mut audio)
+                .unwrap();
+        }
+        audio
+    }
+
+    #[tokio::test]
+    async fn content_hash_ignores_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = mp3_file(dir.path());
+        let audio = std::fs::read(&path).unwrap();
+        let hash = read_content_hash(path.clone()).await.unwrap();
+
+        // ID3v2 with 20 bytes of frames
+        let mut tagged = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        tagged.extend_from_slice(&[b'x'; 20]);
+        tagged.extend_from_slice(&audio);
+        // APEv2 with 16 bytes of items and no header
+        tagged.extend_from_slice(&[b'y'; 16]);
+        tagged.extend_from_slice(b"APETAGEX");
+        for value in [2000u32, 48, 1, 0] {
+            tagged.extend_from_slice(&value.to_le_bytes());
+        }
+        tagged.extend_from_slice(&[0; 8]);
+        // ID3v1
+        tagged.extend_from_slice(b"TAG");
+        tagged.extend_from_slice(&[b'z'; 125]);
+        std::fs::write(&path, tagged).unwrap();
+
+        assert_eq!(read_content_hash(path).await.unwrap(), hash);
+    }
+
+    #[test]
+    fn flac_audio_skips_metadata_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = flac_file(dir.path());
+        let mut flac = std::fs::read(&path).unwrap();
+        // the stream info is no longer the last block
+        flac[4] = 0x00;
+        // vorbis comments, 5 bytes long
+        flac.extend_from_slice(&[0x84, 0x00, 0x00, 0x05]);
+        flac.extend_from_slice(b"tags!");
+        let audio = (1..=50).collect::<Vec<u8>>();
+        flac.extend_from_slice(&audio);
+        std::fs::write(&path, flac).unwrap();
+
+        assert_eq!(audio_of(&path, FileType::Flac), audio);
+    }
+
+    #[test]
+    fn ogg_audio_skips_header_pages() {
+        let page = |sequence: u32, granule: u64, payload: &[u8]| {
+            let mut page = b"OggS\x00\x00".to_vec();
+            page.extend_from_slice(&granule.to_le_bytes());
+            page.extend_from_slice(&1u32.to_le_bytes());
+            page.extend_from_slice(&sequence.to_le_bytes());
+            // the checksum isn't verified
+            page.extend_from_slice(&[0; 4]);
+            page.push(1);
+            page.push(payload.len() as u8);
+            page.extend_from_slice(payload);
+            page
+        };
+        let ogg = [
+            page(0, 0, b"OpusHead"),
+            page(1, 0, b"OpusTags with a comment"),
+            page(2, 960, b"first packet"),
+            page(3, 1920, b"second packet"),
+        ]
+        .concat();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.opus");
+        std::fs::write(&path, ogg).unwrap();
+
+        assert_eq!(
+            audio_of(&path, FileType::Opus),
+            b"first packetsecond packet"
+        );
+    }
+
+    #[test]
+    fn mp4_audio_is_mdat() {
+        let atom = |name: &[u8], data: &[u8]| {
+            let mut atom = ((data.len() + 8) as u32).to_be_bytes().to_vec();
+            atom.extend_from_slice(name);
+            atom.extend_from_slice(data);
+            atom
+        };
+        let mp4 = [
+            atom(b"ftyp", b"M4A \x00\x00\x00\x00"),
+            atom(b"moov", b"udta meta ilst"),
+            atom(b"mdat", b"audio frames"),
+        ]
+        .concat();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.m4a");
+        std::fs::write(&path, mp4).unwrap();
+
+        assert_eq!(audio_of(&path, FileType::Mp4), b"audio frames");
+    }
+
+    #[test]
+    fn wav_audio_is_data_chunk() {
+        let mut wav = b"RIFF\x00\x00\x00\x00WAVE".to_vec();
+        wav.extend_from_slice(b"fmt \x10\x00\x00\x00");
+        wav.extend_from_slice(&[0; 16]);
+        // an odd sized chunk is padded
+        wav.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+        wav.extend_from_slice(b"data\x06\x00\x00\x00samples");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("song.wav");
+        std::fs::write(&path, wav).unwrap();
+
+        assert_eq!(audio_of(&path, FileType::Wav), b"sample");
+    }
+}
@@ -2,10 +2,15 @@ pub use youtube_dl;
 
 use youtube_dl::{SearchOptions, SingleVideo, YoutubeDl, YoutubeDlOutput};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::{data::Provenance, entities::song::Source, lock::MusicDirLock};
 
 use self::error::YoutubeError;
 
+/// yt-dlp format downloads are made in, opus audio on youtube
+pub const DOWNLOAD_FORMAT: &str = "251";
+
 pub fn search_youtube(
     kw: String,
     cookies: Option<PathBuf>,
@@ -105,41 +110,45 @@ pub fn search_youtube_playlist(
     }
 }
 
+/// Downloads into `output_dir`, refusing while another muzik process works on its files.
+/// Returns the video as yt-dlp downloaded it
 pub fn download_from_youtube(
     id: String,
     output_dir: String,
     format: String,
     cookies: Option<PathBuf>,
-) -> Result<YoutubeDlOutput, youtube_dl::Error> {
-    if let Some(cookie) = cookies {
+) -> Result<SingleVideo, YoutubeError> {
+    let _lock = MusicDirLock::try_acquire(Path::new(&output_dir), "downloading")?;
+    let output = if let Some(cookie) = cookies {
         println!("cookie found");
         YoutubeDl::new(&id)
             .youtube_dl_path("yt-dlp")
             .extra_arg("--audio-format")
             .extra_arg("opus")
-            .format("251")
+            .format(DOWNLOAD_FORMAT)
             .extra_arg("--sponsorblock-remove")
             .extra_arg("all")
             .output_directory(&output_dir)
             .output_template(&format)
             .cookies(cookie.display().to_string())
             .extract_audio(true)
-            // TODO: fix downloads
+            .download(true)
             .run()
     } else {
         YoutubeDl::new(id)
             .youtube_dl_path("yt-dlp")
             .extra_arg("--audio-format")
             .extra_arg("opus")
-            .format("251")
+            .format(DOWNLOAD_FORMAT)
             .extra_arg("--sponsorblock-remove")
             .extra_arg("all")
             .output_directory(output_dir)
             .output_template(format)
-            // TODO: fix downloads
             .extract_audio(true)
+            .download(true)
             .run()
-    }
+    };
+    downloaded_video(output?)
 }
 
 pub async fn load_image(url: Option<String>) -> Result<Vec<u8>, YoutubeError> {
@@ -161,37 +170,89 @@ pub async fn load_image(url: Option<String>) -> Result<Vec<u8>, YoutubeError> {
     }
 }
 
+/// Downloads into `music_dir`, refusing while another muzik process works on its files.
+/// Returns the video as yt-dlp downloaded it
 pub async fn download_video(
     id: String,
     music_dir: PathBuf,
     filename_format: String,
     cookies: Option<PathBuf>,
-) -> Result<(), YoutubeError> {
+) -> Result<SingleVideo, YoutubeError> {
+    let _lock = MusicDirLock::try_acquire(&music_dir, "downloading")?;
+    let mut youtube_dl = YoutubeDl::new(id);
+    youtube_dl
+        .extract_audio(true)
+        .extra_arg("--audio-format")
+        .extra_arg("opus")
+        .format(DOWNLOAD_FORMAT)
+        .extra_arg("--sponsorblock-remove")
+        .extra_arg("all")
+        .output_directory(music_dir.display().to_string())
+        .output_template(filename_format)
+        .download(true);
     if let Some(cookies) = cookies {
-        YoutubeDl::new(id)
-            .extract_audio(true)
-            .extra_arg("--audio-format")
-            .extra_arg("opus")
-            .format("251")
-            .extra_arg("--sponsorblock-remove")
-            .extra_arg("all")
-            .cookies(cookies.display().to_string())
-            .output_template(filename_format)
-            .download_to_async(music_dir)
-            .await?
-    } else {
-        YoutubeDl::new(id)
-            .extract_audio(true)
-            .extra_arg("--audio-format")
-            .extra_arg("opus")
-            .format("251")
-            .extra_arg("--sponsorblock-remove")
-            .extra_arg("all")
-            .output_template(filename_format)
-            .download_to_async(music_dir)
-            .await?
+        youtube_dl.cookies(cookies.display().to_string());
+    }
+    downloaded_video(youtube_dl.run_async().await?)
+}
+
+/// Where `video` is from, by the page yt-dlp found it on. Videos without one come from a
+/// youtube search
+pub fn source_of_video(video: &SingleVideo) -> Source {
+    video
+        .webpage_url
+        .as_deref()
+        .map_or(Source::Youtube, Source::from_url)
+}
+
+/// Provenance of a finished download, `video` being what yt-dlp reported for it. Call
+/// it once the download completed, that is the time it is stamped with.
+pub async fn provenance_from_download(video: &SingleVideo) -> Provenance {
+    Provenance {
+        url: video.webpage_url.clone(),
+        uploader_id: video
+            .channel_id
+            .clone()
+            .or_else(|| video.uploader_id.clone()),
+        upload_date: video.upload_date.clone(),
+        format_id: video.format_id.clone(),
+        extractor: video
+            .extractor_key
+            .clone()
+            .or_else(|| video.extractor.clone()),
+        ytdlp_version: ytdlp_version().await,
+        downloaded_at: crate::database::unix_now(),
+    }
+}
+
+/// Version of the yt-dlp executable downloads run with, `None` if it can't be run. It is
+/// only asked for once, later calls reuse the answer
+pub async fn ytdlp_version() -> Option<String> {
+    static VERSION: tokio::sync::OnceCell<Option<String>> = tokio::sync::OnceCell::const_new();
+    VERSION
+        .get_or_init(|| async {
+            let output = tokio::process::Command::new("yt-dlp")
+                .arg("--version")
+                .output()
+                .await
+                .ok()?;
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if output.status.success() && !version.is_empty() {
+                Some(version)
+            } else {
+                None
+            }
+        })
+        .await
+        .clone()
+}
+
+/// The video a download reported, yt-dlp only answers with a playlist for playlist urls
+fn downloaded_video(output: YoutubeDlOutput) -> Result<SingleVideo, YoutubeError> {
+    match output {
+        YoutubeDlOutput::SingleVideo(video) => Ok(*video),
+        YoutubeDlOutput::Playlist(_) => Err(YoutubeError::UnexpectedPlaylist),
     }
-    Ok(())
 }
 
 /// attaches the variable extension to the filename
@@ -213,5 +274,9 @@ pub mod error {
 
         #[error(transparent)]
         ReqwestError(#[from] reqwest::Error),
+        #[error(transparent)]
+        Lock(#[from] crate::lock::error::LockError),
+        #[error("yt-dlp answered a download with a playlist")]
+        UnexpectedPlaylist,
     }
 }
@@ -13,8 +13,12 @@ use muzik_common::{
     data::{Song, Source},
     database::DbConnection,
     entities::{album::AlbumModel, artist::ArtistModel, genre::GenreModel},
+    lock::MusicDirLock,
     tags::write_tags_song,
-    util::{download_video, load_image, search_youtube_async, youtube_dl::SingleVideo},
+    util::{
+        download_video, load_image, provenance_from_download, search_youtube_async,
+        source_of_video, youtube_dl::SingleVideo,
+    },
 };
 use strum::{Display, EnumIter, IntoEnumIterator};
 use tracing::{debug, error, info};
@@ -540,7 +544,9 @@ impl Tab for DownloaderTab {
                             song.set_genres(genres_vec);
                         }
                         song.youtube_id = Some(video.id.clone());
-                        song.set_source(Source::Youtube);
+                        song.set_source(source_of_video(video));
+                        song.set_source_id(Some(video.id.clone()));
+                        song.set_source_url(video.webpage_url.clone());
                         if let Some(thumbnail) = video.thumbnail.clone() {
                             song.set_thumbnail_url(thumbnail);
                         };
@@ -568,9 +574,16 @@ impl Tab for DownloaderTab {
                 DownloaderMsg::DownloadAfterInsert((res, song)) => {
                     if res {
                         match song.source {
-                            Source::Youtube => {
+                            Source::Youtube
+                            | Source::SoundCloud
+                            | Source::Bandcamp
+                            | Source::YtDlp => {
                                 let db_id = song.id.expect("youtube_id exists");
-                                let youtube_id = song.youtube_id.clone().expect("exists");
+                                // yt-dlp takes the page for sites other than youtube
+                                let youtube_id = song
+                                    .get_source_url()
+                                    .or_else(|| song.youtube_id.clone())
+                                    .expect("exists");
                                 let title = song.get_title_string();
                                 let artists = song.get_artists_string();
                                 let music_dir = self.config.get_music_dir();
@@ -579,7 +592,8 @@ impl Tab for DownloaderTab {
                                 let filename_format =
                                     format!("{} - {} [{}].%(ext)s", title, artists, db_id);
 
-                                let song = song.clone();
+                                let mut song = song.clone();
+                                let db = self.db.clone();
                                 return Command::perform(
                                     async move {
                                         match download_video(
@@ -590,8 +604,19 @@ impl Tab for DownloaderTab {
                                         )
                                         .await
                                         {
-                                            Ok(_) => {
+                                            Ok(video) => {
                                                 info!("download success!");
+                                                let provenance =
+                                                    provenance_from_download(&video).await;
+                                                if let Err(e) = db
+                                                    .set_download_provenance(db_id, &provenance)
+                                                    .await
+                                                {
+                                                    error!(
+                                                        "failed to store download provenance: {e}"
+                                                    );
+                                                }
+                                                song.set_provenance(Some(provenance));
                                                 return (true, song);
                                             }
                                             Err(e) => {
@@ -603,7 +628,7 @@ impl Tab for DownloaderTab {
                                     |res| Msg::Downloader(DownloaderMsg::TagAfterDownload(res)),
                                 );
                             }
-                            _ => {}
+                            Source::Local => {}
                         }
                     } else {
                     }
@@ -625,12 +650,29 @@ impl Tab for DownloaderTab {
                         song.set_path(path.clone());
 
                         let song_write = song.clone();
+                        let db_write = self.db.clone();
+                        let music_dir = self.config.get_music_dir();
                         return Command::batch(vec![
                             Command::perform(
                                 async move {
+                                    let _lock =
+                                        match MusicDirLock::try_acquire(&music_dir, "writing tags")
+                                        {
+                                            Ok(lock) => lock,
+                                            Err(e) => {
+                                                error!("failed to write tags to file: {e}");
+                                                return false;
+                                            }
+                                        };
                                     match write_tags_song(path, &song_write).await {
-                                        Ok(_) => {
+                                        Ok(audio_properties) => {
                                             info!("successfully wrote tags to file");
+                                            if let Err(e) = db_write
+                                                .update_audio_properties(db_id, &audio_properties)
+                                                .await
+                                            {
+                                                error!("failed to store audio properties: {e}");
+                                            }
                                             true
                                         }
                                         Err(e) => {
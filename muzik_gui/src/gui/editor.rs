@@ -14,8 +14,11 @@ use tracing::{debug, error, info, trace};
 use muzik_common::{
     config::Config,
     data::{self, load_songs, Song},
-    database::DbConnection,
-    entities::{album::AlbumModel, artist::ArtistModel, genre::GenreModel},
+    database::{AlbumWithTracks, DbConnection, OrphanReport, DEFAULT_SEARCH_LIMIT},
+    entities::{
+        album::AlbumModel, artist::ArtistModel, change_set::ChangeOrigin, genre::GenreModel,
+    },
+    lock::MusicDirLock,
     tags::{self, write_tags_song},
 };
 
@@ -42,6 +45,11 @@ pub enum EditorMessage {
     AlbumTextInput((usize, MultiStringInputMessage)),
     AddAlbumButton,
     RemoveLastAlbumButton,
+    /// first album of the current song with its cover, if it has one
+    LoadAlbum(Option<(AlbumWithTracks, Option<Vec<u8>>)>),
+    AlbumCoverPathInput(String),
+    SetAlbumCoverButton,
+    AlbumCoverSet,
 
     GenreTextInput((usize, MultiStringInputMessage)),
     AddGenreButton,
@@ -50,6 +58,26 @@ pub enum EditorMessage {
     SubmitChanges(),
     WriteAfterInsertSong((bool, Song)),
     AfterTagWrite(bool),
+
+    CleanOrphansButton,
+    LoadOrphanReport(OrphanReport),
+    CleanOrphansConfirm,
+
+    LabelFilterInput(String),
+    ApplyLabelFilter,
+    /// ids of the songs with the filtered label, `None` shows all songs
+    LoadLabelFilter(Option<Vec<i32>>),
+    SearchInput(String),
+    ApplySearch,
+    /// ids of the songs matching the search, best match first, `None` shows all songs
+    LoadSearch(Option<Vec<i32>>),
+    LoadSongLabels(Vec<String>),
+    LabelTextInput(String),
+    AddLabelButton,
+    RemoveLabelButton(String),
+    LabelsChanged,
+    /// other versions of the current song, or why they couldn't be loaded
+    LoadSongVersions(Result<Vec<(i32, String)>, String>),
 }
 
 pub struct EditorTab {
@@ -61,11 +89,30 @@ pub struct EditorTab {
 
     current_app_song: Option<Song>,
     current_app_song_image: Option<Vec<u8>>,
+    /// first album of the current song, with its tracks and cover
+    current_album: Option<(AlbumWithTracks, Option<Vec<u8>>)>,
+    album_cover_path_input: String,
 
     title_text_input: Option<String>,
     artist_text_input: Option<Vec<MultiStringInput<Msg>>>,
     album_text_input: Option<Vec<MultiStringInput<Msg>>>,
     genre_text_input: Option<Vec<MultiStringInput<Msg>>>,
+
+    /// what a cleanup would remove, shown until confirmed or reloaded
+    orphan_report: Option<OrphanReport>,
+
+    label_filter_input: String,
+    /// ids of the songs with the filtered label, songs without an id are hidden too
+    label_filter: Option<Vec<i32>>,
+    search_input: String,
+    /// ids of the songs matching the search, best match first
+    search_results: Option<Vec<i32>>,
+    /// labels of the current song as stored in the database
+    song_labels: Option<Vec<String>>,
+    label_text_input: String,
+    /// covers, remixes, live versions and alternate uploads of the current song, or why
+    /// they couldn't be loaded
+    song_versions: Option<Result<Vec<(i32, String)>, String>>,
 }
 
 impl EditorTab {
@@ -82,11 +129,23 @@ impl EditorTab {
 
                 current_app_song: None,
                 current_app_song_image: None,
+                current_album: None,
+                album_cover_path_input: String::new(),
 
                 title_text_input: None,
                 artist_text_input: None,
                 album_text_input: None,
                 genre_text_input: None,
+
+                orphan_report: None,
+
+                label_filter_input: String::new(),
+                label_filter: None,
+                search_input: String::new(),
+                search_results: None,
+                song_labels: None,
+                label_text_input: String::new(),
+                song_versions: None,
             },
             Command::perform(async { load_songs(music_dir, db_conn).await }, |result| {
                 Msg::Editor(EditorMessage::LoadSongs(result))
@@ -152,6 +211,77 @@ impl EditorTab {
     }
 }
 
+impl EditorTab {
+    /// Loads the labels of the current song from the database
+    fn load_song_labels(&self) -> Command<Msg> {
+        let Some(song_id) = self.current_app_song.as_ref().and_then(|song| song.id) else {
+            return Command::none();
+        };
+        let db = self.db.clone();
+        Command::perform(
+            async move {
+                match db.get_song_labels(song_id).await {
+                    Ok(labels) => labels.into_iter().map(|label| label.name).collect(),
+                    Err(e) => {
+                        error!("failed to load labels of song {song_id}: {e}");
+                        vec![]
+                    }
+                }
+            },
+            |labels| Msg::Editor(EditorMessage::LoadSongLabels(labels)),
+        )
+    }
+
+    /// Loads the other versions of the current song from the database
+    fn load_song_versions(&self) -> Command<Msg> {
+        let Some(song_id) = self.current_app_song.as_ref().and_then(|song| song.id) else {
+            return Command::none();
+        };
+        let db = self.db.clone();
+        Command::perform(
+            async move {
+                db.describe_song_versions(song_id)
+                    .await
+                    .map_err(|e| e.to_string())
+            },
+            |versions| Msg::Editor(EditorMessage::LoadSongVersions(versions)),
+        )
+    }
+
+    /// Loads the first album of the current song, its tracks and its cover
+    fn load_song_album(&self) -> Command<Msg> {
+        let Some(album_id) = self
+            .current_app_song
+            .as_ref()
+            .filter(|song| song.id.is_some())
+            .and_then(|song| song.albums.as_ref()?.first().map(|album| album.id))
+        else {
+            return Command::none();
+        };
+        let db = self.db.clone();
+        let music_dir = self.config.get_music_dir();
+        Command::perform(
+            async move {
+                let album = db.get_album_with_tracks(album_id, music_dir).await;
+                let cover = db.get_album_cover(album_id).await;
+                match (album, cover) {
+                    (Ok(album), Ok(cover)) => Some((album, cover)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        error!("failed to load album {album_id}: {e}");
+                        None
+                    }
+                }
+            },
+            |album| Msg::Editor(EditorMessage::LoadAlbum(album)),
+        )
+    }
+
+    /// Music dir to rewrite the label tags in, `None` unless `label_tags` is set
+    fn label_music_dir(&self) -> Option<std::path::PathBuf> {
+        self.config.label_tags.then(|| self.config.get_music_dir())
+    }
+}
+
 impl Tab for EditorTab {
     type Message = Msg;
 
@@ -168,16 +298,44 @@ impl Tab for EditorTab {
         let reload_button = Button::new("Reload")
             .on_press(Self::Message::Editor(EditorMessage::ReloadButton))
             .into();
+        let clean_button = Button::new("Clean Up Database")
+            .on_press(Self::Message::Editor(EditorMessage::CleanOrphansButton))
+            .into();
+        let orphan_report: Element<_> = match self.orphan_report.as_ref() {
+            Some(report) if !report.is_empty() => row(vec![
+                text(format!("would remove:\n{}", report.summary())).into(),
+                Button::new("Delete")
+                    .on_press(Self::Message::Editor(EditorMessage::CleanOrphansConfirm))
+                    .into(),
+            ])
+            .spacing(10)
+            .into(),
+            Some(report) => text(report.summary()).into(),
+            None => column(vec![]).into(),
+        };
         let songs: Element<_> = {
             let mut songs = vec![];
 
             if let Some(local_songs) = self.songs_vec.as_ref() {
+                let local_songs: Vec<_> = match &self.search_results {
+                    Some(ids) => ids
+                        .iter()
+                        .filter_map(|id| local_songs.iter().find(|song| song.id == Some(*id)))
+                        .collect(),
+                    None => local_songs.iter().collect(),
+                };
+                let local_songs = local_songs
+                    .into_iter()
+                    .filter(|song| match &self.label_filter {
+                        Some(ids) => song.id.is_some_and(|id| ids.contains(&id)),
+                        None => true,
+                    });
                 if self.db_songs_visibility {
-                    for item in local_songs.into_iter().map(|msongs| msongs.view()) {
+                    for item in local_songs.map(|msongs| msongs.view()) {
                         songs.push(item);
                     }
                 } else {
-                    for item in local_songs.into_iter().filter_map(|msongs| {
+                    for item in local_songs.filter_map(|msongs| {
                         if msongs.is_database_only() {
                             None
                         } else {
@@ -306,6 +464,44 @@ impl Tab for EditorTab {
                 .push(album_col)
                 .push(horizontal_rule(1));
 
+            if let Some((album, cover)) = self.current_album.as_ref() {
+                let AlbumWithTracks { album, tracks } = album;
+                let mut heading = album.name.clone();
+                if let Some(album_artist) = album.album_artist.as_ref() {
+                    heading.push_str(&format!(" by {album_artist}"));
+                }
+                if let Some(year) = album.year {
+                    heading.push_str(&format!(" ({year})"));
+                }
+                let cover: Element<_> = match cover {
+                    Some(cover) => Image::new(Handle::from_memory(cover.clone()))
+                        .width(150)
+                        .height(150)
+                        .into(),
+                    None => text("no cover").into(),
+                };
+                let mut tracks_col = Column::new().spacing(2);
+                for track in tracks {
+                    let number = track
+                        .track_number
+                        .map_or_else(String::new, |number| format!("{number}. "));
+                    tracks_col =
+                        tracks_col.push(text(format!("{number}{}", track.get_title_string())));
+                }
+                let cover_input =
+                    iced::widget::TextInput::new("cover image path", &self.album_cover_path_input)
+                        .on_input(|input| Msg::Editor(EditorMessage::AlbumCoverPathInput(input)))
+                        .on_submit(Msg::Editor(EditorMessage::SetAlbumCoverButton));
+                let set_cover_button = Button::new("Set Cover")
+                    .on_press(Msg::Editor(EditorMessage::SetAlbumCoverButton))
+                    .into();
+                sp_col = sp_col
+                    .push(Text::new(heading))
+                    .push(row(vec![cover, tracks_col.into()]).spacing(10))
+                    .push(row(vec![cover_input.into(), set_cover_button]).spacing(10))
+                    .push(horizontal_rule(1));
+            }
+
             let genre_header = Text::new("Genre");
             let add_genre_button = Button::new("Add Genre")
                 .on_press(Msg::Editor(EditorMessage::AddGenreButton))
@@ -337,8 +533,64 @@ impl Tab for EditorTab {
                 .push(genre_col)
                 .push(horizontal_rule(1));
 
+            // labels are stored right away, they don't wait for submit
+            if song.id.is_some() {
+                let label_header = Text::new("Labels");
+                let label_input = iced::widget::TextInput::new("new label", &self.label_text_input)
+                    .on_input(|input| Msg::Editor(EditorMessage::LabelTextInput(input)))
+                    .on_submit(Msg::Editor(EditorMessage::AddLabelButton));
+                let add_label_button = Button::new("Add Label")
+                    .on_press(Msg::Editor(EditorMessage::AddLabelButton))
+                    .into();
+                let mut label_row = row(vec![]).spacing(5);
+                for label in self.song_labels.iter().flatten() {
+                    label_row = label_row.push(
+                        Button::new(text(format!("{label} x")))
+                            .on_press(Msg::Editor(EditorMessage::RemoveLabelButton(label.clone()))),
+                    );
+                }
+                sp_col = sp_col
+                    .push(
+                        container(
+                            row(vec![
+                                label_header.into(),
+                                label_input.into(),
+                                add_label_button,
+                            ])
+                            .spacing(10),
+                        )
+                        .align_y(alignment::Vertical::Bottom),
+                    )
+                    .push(label_row)
+                    .push(horizontal_rule(1));
+            }
+
+            match self.song_versions.as_ref() {
+                Some(Ok(versions)) if !versions.is_empty() => {
+                    let mut versions_col = column(vec![Text::new("Versions").into()]).spacing(5);
+                    for (_, description) in versions {
+                        versions_col = versions_col.push(text(description));
+                    }
+                    sp_col = sp_col.push(versions_col).push(horizontal_rule(1));
+                }
+                Some(Err(e)) => {
+                    sp_col = sp_col
+                        .push(text(format!("failed to load versions: {e}")))
+                        .push(horizontal_rule(1));
+                }
+                _ => {}
+            }
+
+            if let Some(audio_properties) = song.audio_properties.as_ref() {
+                let audio_disp = text(format!("Audio: {}", audio_properties.summary()));
+                sp_col = sp_col.push(audio_disp).push(horizontal_rule(1));
+            }
+
             let source_disp = text(format!("Source: {}", song.source));
             sp_col = sp_col.push(source_disp);
+            if let Some(source_url) = song.get_source_url() {
+                sp_col = sp_col.push(text(format!("Source URL: {source_url}")));
+            }
 
             // only show this if the source is Youtube
             if song.source == data::Source::Youtube {
@@ -368,7 +620,23 @@ impl Tab for EditorTab {
                 Self::Message::Editor(EditorMessage::DbVisibleToggle(b))
             })
             .into(),
-            reload_button,
+            row(vec![
+                reload_button,
+                clean_button,
+                iced::widget::TextInput::new("filter by label", &self.label_filter_input)
+                    .on_input(|input| Self::Message::Editor(EditorMessage::LabelFilterInput(input)))
+                    .on_submit(Self::Message::Editor(EditorMessage::ApplyLabelFilter))
+                    .width(200)
+                    .into(),
+                iced::widget::TextInput::new("search", &self.search_input)
+                    .on_input(|input| Self::Message::Editor(EditorMessage::SearchInput(input)))
+                    .on_submit(Self::Message::Editor(EditorMessage::ApplySearch))
+                    .width(200)
+                    .into(),
+            ])
+            .spacing(10)
+            .into(),
+            orphan_report,
             container(Split::new(
                 songs,
                 second_panel,
@@ -401,9 +669,17 @@ impl Tab for EditorTab {
                 EditorMessage::SongButton(song) => {
                     self.current_app_song = Some(song.clone());
                     self.current_app_song_image = None;
+                    self.current_album = None;
+                    self.song_labels = None;
+                    self.song_versions = None;
                     self.reset_input_fields();
+                    let mut commands = vec![
+                        self.load_song_labels(),
+                        self.load_song_versions(),
+                        self.load_song_album(),
+                    ];
                     if let Some(path) = song.path.clone() {
-                        return Command::perform(
+                        commands.push(Command::perform(
                             async {
                                 match tags::read_picture(path).await {
                                     Ok(pic) => pic,
@@ -414,9 +690,37 @@ impl Tab for EditorTab {
                                 }
                             },
                             |res| Msg::Editor(EditorMessage::LoadSongImage(res)),
-                        );
+                        ));
                     }
+                    return Command::batch(commands);
+                }
+                EditorMessage::LoadAlbum(album) => self.current_album = album,
+                EditorMessage::AlbumCoverPathInput(input) => self.album_cover_path_input = input,
+                EditorMessage::SetAlbumCoverButton => {
+                    let Some(album_id) =
+                        self.current_album.as_ref().map(|(album, _)| album.album.id)
+                    else {
+                        return Command::none();
+                    };
+                    let path = std::mem::take(&mut self.album_cover_path_input);
+                    let db = self.db.clone();
+                    return Command::perform(
+                        async move {
+                            let image = match tokio::fs::read(path.trim()).await {
+                                Ok(image) => image,
+                                Err(e) => {
+                                    error!("failed to read cover {path}: {e}");
+                                    return;
+                                }
+                            };
+                            if let Err(e) = db.set_album_cover(album_id, &image).await {
+                                error!("failed to set cover of album {album_id}: {e}");
+                            }
+                        },
+                        |_| Msg::Editor(EditorMessage::AlbumCoverSet),
+                    );
                 }
+                EditorMessage::AlbumCoverSet => return self.load_song_album(),
                 EditorMessage::LoadSongImage(pic) => {
                     self.current_app_song_image = Some(pic);
                 }
@@ -518,7 +822,13 @@ impl Tab for EditorTab {
                                 let db = self.db.clone();
                                 return Command::perform(
                                     async move {
-                                        match db.update_all_from_gui_song(song.clone()).await {
+                                        match db
+                                            .update_all_from_gui_song(
+                                                song.clone(),
+                                                ChangeOrigin::Gui,
+                                            )
+                                            .await
+                                        {
                                             Ok(_) => {
                                                 info!("update database entries successfully");
                                                 (true, song)
@@ -591,11 +901,38 @@ impl Tab for EditorTab {
                 EditorMessage::WriteAfterInsertSong((res, song)) => match res {
                     true => {
                         let path = song.path.clone().expect("inserted song has path");
+                        let db = self.db.clone();
+                        let music_dir = self.config.get_music_dir();
                         return Command::perform(
                             async move {
+                                let _lock =
+                                    match MusicDirLock::try_acquire(&music_dir, "writing tags") {
+                                        Ok(lock) => lock,
+                                        Err(e) => {
+                                            error!("failed to write tags to file: {e}");
+                                            return false;
+                                        }
+                                    };
+                                let mut song = song;
+                                match db
+                                    .get_song_album_cover(song.id.expect("inserted song has id"))
+                                    .await
+                                {
+                                    Ok(Some(cover)) => {
+                                        song.set_thumbnail(cover);
+                                    }
+                                    Ok(None) => {}
+                                    Err(e) => error!("failed to load album cover: {e}"),
+                                }
                                 match write_tags_song(path, &song).await {
-                                    Ok(_) => {
+                                    Ok(audio_properties) => {
                                         info!("successfully wrote tags to file");
+                                        let id = song.id.expect("inserted song has id");
+                                        if let Err(e) =
+                                            db.update_audio_properties(id, &audio_properties).await
+                                        {
+                                            error!("failed to store audio properties: {e}");
+                                        }
                                         true
                                     }
                                     Err(e) => {
@@ -612,6 +949,130 @@ impl Tab for EditorTab {
                 EditorMessage::AfterTagWrite(_res) => {
                     return Command::perform(async {}, |_| Msg::Editor(EditorMessage::ReloadButton))
                 }
+                EditorMessage::CleanOrphansButton => {
+                    let db = self.db.clone();
+                    return Command::perform(
+                        async move {
+                            db.clean_orphans(true).await.unwrap_or_else(|e| {
+                                error!("failed to look for orphaned entries: {e}");
+                                OrphanReport::default()
+                            })
+                        },
+                        |report| Msg::Editor(EditorMessage::LoadOrphanReport(report)),
+                    );
+                }
+                EditorMessage::LoadOrphanReport(report) => self.orphan_report = Some(report),
+                EditorMessage::LabelFilterInput(input) => self.label_filter_input = input,
+                EditorMessage::ApplyLabelFilter => {
+                    let label = self.label_filter_input.trim().to_string();
+                    if label.is_empty() {
+                        self.label_filter = None;
+                        return Command::none();
+                    }
+                    let db = self.db.clone();
+                    let music_dir = self.config.get_music_dir();
+                    return Command::perform(
+                        async move {
+                            match db.get_label_songs(&label, music_dir).await {
+                                Ok(songs) => {
+                                    Some(songs.iter().filter_map(|song| song.id).collect())
+                                }
+                                Err(e) => {
+                                    error!("failed to filter by label {label}: {e}");
+                                    None
+                                }
+                            }
+                        },
+                        |ids| Msg::Editor(EditorMessage::LoadLabelFilter(ids)),
+                    );
+                }
+                EditorMessage::LoadLabelFilter(ids) => self.label_filter = ids,
+                EditorMessage::SearchInput(input) => self.search_input = input,
+                EditorMessage::ApplySearch => {
+                    let search = self.search_input.trim().to_string();
+                    if search.is_empty() {
+                        self.search_results = None;
+                        return Command::none();
+                    }
+                    let db = self.db.clone();
+                    let music_dir = self.config.get_music_dir();
+                    return Command::perform(
+                        async move {
+                            match db
+                                .search(&search, Some(DEFAULT_SEARCH_LIMIT), music_dir)
+                                .await
+                            {
+                                Ok(songs) => {
+                                    Some(songs.iter().filter_map(|song| song.id).collect())
+                                }
+                                Err(e) => {
+                                    error!("failed to search for {search}: {e}");
+                                    None
+                                }
+                            }
+                        },
+                        |ids| Msg::Editor(EditorMessage::LoadSearch(ids)),
+                    );
+                }
+                EditorMessage::LoadSearch(ids) => self.search_results = ids,
+                EditorMessage::LoadSongLabels(labels) => self.song_labels = Some(labels),
+                EditorMessage::LabelTextInput(input) => self.label_text_input = input,
+                EditorMessage::AddLabelButton => {
+                    let label = std::mem::take(&mut self.label_text_input);
+                    if let Some(song_id) = self.current_app_song.as_ref().and_then(|song| song.id) {
+                        let db = self.db.clone();
+                        let music_dir = self.label_music_dir();
+                        return Command::perform(
+                            async move {
+                                if let Err(e) = db.add_song_label(song_id, label, music_dir).await {
+                                    error!("failed to label song {song_id}: {e}");
+                                }
+                            },
+                            |_| Msg::Editor(EditorMessage::LabelsChanged),
+                        );
+                    }
+                }
+                EditorMessage::RemoveLabelButton(label) => {
+                    if let Some(song_id) = self.current_app_song.as_ref().and_then(|song| song.id) {
+                        let db = self.db.clone();
+                        let music_dir = self.label_music_dir();
+                        return Command::perform(
+                            async move {
+                                if let Err(e) =
+                                    db.remove_song_label(song_id, &label, music_dir).await
+                                {
+                                    error!("failed to remove label {label} of song {song_id}: {e}");
+                                }
+                            },
+                            |_| Msg::Editor(EditorMessage::LabelsChanged),
+                        );
+                    }
+                }
+                EditorMessage::LoadSongVersions(versions) => self.song_versions = Some(versions),
+                EditorMessage::LabelsChanged => {
+                    let mut commands = vec![self.load_song_labels()];
+                    if self.label_filter.is_some() {
+                        commands.push(Command::perform(async {}, |_| {
+                            Msg::Editor(EditorMessage::ApplyLabelFilter)
+                        }));
+                    }
+                    return Command::batch(commands);
+                }
+                EditorMessage::CleanOrphansConfirm => {
+                    self.orphan_report = None;
+                    let db = self.db.clone();
+                    return Command::perform(
+                        async move {
+                            match db.clean_orphans(false).await {
+                                Ok(report) => {
+                                    info!("removed orphaned entries: {}", report.summary())
+                                }
+                                Err(e) => error!("failed to remove orphaned entries: {e}"),
+                            }
+                        },
+                        |_| Msg::Editor(EditorMessage::ReloadButton),
+                    );
+                }
             }
             Command::none()
         } else {
@@ -80,7 +80,7 @@ impl Application for GuiMain {
         )
     }
     fn title(&self) -> String {
-        "muzik - music manager".to_string()
+        format!("muzik - music manager [{}]", self.config.library)
     }
 
     fn subscription(&self) -> iced::Subscription<Self::Message> {
@@ -16,7 +16,7 @@ mod gui;
 mod log;
 
 fn main() -> Result<()> {
-    let _args = Cli::parse();
+    let args = Cli::parse();
 
     let mut guards = vec![];
 
@@ -64,7 +64,11 @@ fn main() -> Result<()> {
             .enable_all()
             .build()
             .unwrap()
-            .block_on(async { ReadConfig::read_config(None).await.expect("config exists") })
+            .block_on(async {
+                ReadConfig::read_config(None, args.library)
+                    .await
+                    .expect("config exists")
+            })
     };
     // staticly load icon
     let icon = include_bytes!("./gui/icon.png");
@@ -90,4 +94,7 @@ fn main() -> Result<()> {
 #[command(name = "muzik-gui")]
 struct Cli {
     config: Option<String>,
+    /// Library from the config to open
+    #[arg(short, long)]
+    library: Option<String>,
 }
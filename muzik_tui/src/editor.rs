@@ -34,7 +34,7 @@ pub fn draw_database_editor(tx: Sender<Event>) -> LinearLayout {
         .child(TextView::new("Database Editor").h_align(cursive::align::HAlign::Center))
         .child(hlayout)
         .child(
-            TextView::new("d - Delete | u - Update list | V - verify all | R - download all missing | S - yt sync")
+            TextView::new("d - Delete | u - Update list | V - verify all | R - download all missing | S - yt sync | C - clean orphans | L - filter by label | / - search")
                 .h_align(cursive::align::HAlign::Center)
                 .with_name("help"),
         )
@@ -79,7 +79,7 @@ fn song_selector_view(tx: Sender<Event>) -> impl cursive::View {
     FocusTracker::new(select_song).on_focus(|_view| {
         EventResult::Consumed(Some(Callback::from_fn_mut(|siv: &mut Cursive| {
             siv.call_on_name("help", |view: &mut TextView| 
-                view.set_content("d - Delete | u - Update list | V - verify all | R - download all missing | S - yt sync" ));
+                view.set_content("d - Delete | u - Update list | V - verify all | R - download all missing | S - yt sync | C - clean orphans | L - filter by label | / - search" ));
         })))
     })
 }
@@ -88,7 +88,9 @@ fn metadata_selector_view(tx: Sender<Event>) -> impl cursive::View {
     let artist_add_tx = tx.clone();
     let artist_edit_tx = tx.clone();
     let album_edit_tx = tx.clone();
-    let album_add_tx = tx;
+    let album_add_tx = tx.clone();
+    let label_add_tx = tx.clone();
+    let label_remove_tx = tx;
     let title = TextView::new("Unknown").with_name("metadata_title");
 
     let artist_select = OnEventView::new(
@@ -126,6 +128,7 @@ fn metadata_selector_view(tx: Sender<Event>) -> impl cursive::View {
                     album::Model {
                         id: 0,
                         name: "Empty".to_string(),
+                        ..Default::default()
                     },
                 )
                 .with_name("metadata_album_select_view"),
@@ -158,6 +161,26 @@ fn metadata_selector_view(tx: Sender<Event>) -> impl cursive::View {
         )
         .with_name("metadata_genre_select_view");
 
+    let label_select = OnEventView::new(
+        FocusTracker::new(SelectView::<String>::new().with_name("metadata_label_select_view"))
+            .on_focus(|_| {
+                EventResult::Consumed(Some(Callback::from_fn_mut(|siv: &mut Cursive| {
+                    siv.call_on_name("help", |view: &mut TextView| {
+                        view.set_content("a - add label | r - remove label")
+                    });
+                })))
+            }),
+    )
+    .on_event('a', move |s| {
+        on_label_add_command(s, label_add_tx.clone());
+    })
+    .on_event('r', move |s| {
+        on_label_remove_command(s, label_remove_tx.clone());
+    });
+
+    // covers, remixes, live versions and alternate uploads, linked from the cli
+    let versions_select = SelectView::<i32>::new().with_name("metadata_versions_select_view");
+
     // layouts
     let artist_layout = LinearLayout::vertical()
         .child(artist_select)
@@ -179,6 +202,8 @@ fn metadata_selector_view(tx: Sender<Event>) -> impl cursive::View {
                     .full_width(),
             )
             .child(Panel::new(genre_select).title("Genres"))
+            .child(Panel::new(label_select).title("Labels"))
+            .child(Panel::new(versions_select).title("Versions"))
             .child(DummyView.full_width().full_height()),
     )
     .title("Metadata")
@@ -341,7 +366,7 @@ fn on_album_edit_command(s: &mut Cursive, tx: Sender<Event>) {
 
         debug!("new: {}", new);
         tx.send(Event::MetadataEditorEditAlbum((
-            selection.name.to_string(),
+            selection.id,
             new.to_string(),
         )))
         .unwrap();
@@ -379,3 +404,95 @@ fn on_album_add_command(s: &mut Cursive, tx: Sender<Event>) {
     .title("Edit Album");
     s.add_layer(dialog);
 }
+
+fn on_label_add_command(s: &mut Cursive, tx: Sender<Event>) {
+    let dialog = Dialog::around(
+        LinearLayout::horizontal()
+            .child(TextView::new("new label: "))
+            .child(
+                EditView::new()
+                    .with_name("label_name_edit_view")
+                    .min_width(10),
+            ),
+    )
+    .dismiss_button("Dismiss")
+    .button("Ok", move |s| {
+        let new = s
+            .call_on_name("label_name_edit_view", |view: &mut EditView| {
+                view.get_content()
+            })
+            .unwrap();
+
+        debug!("new: {}", new);
+        tx.send(Event::MetadataEditorAddLabel(new.to_string()))
+            .unwrap();
+        s.pop_layer();
+    })
+    .title("Add Label");
+    s.add_layer(dialog);
+}
+
+fn on_label_remove_command(s: &mut Cursive, tx: Sender<Event>) {
+    let selection = s
+        .call_on_name(
+            "metadata_label_select_view",
+            |view: &mut SelectView<String>| view.selection(),
+        )
+        .unwrap();
+    // nothing to remove when the song has no labels
+    if let Some(label) = selection {
+        tx.send(Event::MetadataEditorRemoveLabel(label.to_string()))
+            .unwrap();
+    }
+}
+
+/// Lists only the songs matching the entered search, best match first. An empty search
+/// lists all songs again
+pub fn on_search_command(s: &mut Cursive, tx: Sender<Event>) {
+    let dialog = Dialog::around(
+        LinearLayout::horizontal()
+            .child(TextView::new("search: "))
+            .child(EditView::new().with_name("search_edit_view").min_width(20)),
+    )
+    .dismiss_button("Dismiss")
+    .button("Ok", move |s| {
+        let search = s
+            .call_on_name("search_edit_view", |view: &mut EditView| view.get_content())
+            .unwrap();
+
+        debug!("search: {}", search);
+        tx.send(Event::FilterEditorBySearch(Some(search.to_string())))
+            .unwrap();
+        s.pop_layer();
+    })
+    .title("Search Library");
+    s.add_layer(dialog);
+}
+
+/// Lists only the songs with the entered label, an empty label lists all songs again
+pub fn on_label_filter_command(s: &mut Cursive, tx: Sender<Event>) {
+    let dialog = Dialog::around(
+        LinearLayout::horizontal()
+            .child(TextView::new("label: "))
+            .child(
+                EditView::new()
+                    .with_name("label_filter_edit_view")
+                    .min_width(10),
+            ),
+    )
+    .dismiss_button("Dismiss")
+    .button("Ok", move |s| {
+        let label = s
+            .call_on_name("label_filter_edit_view", |view: &mut EditView| {
+                view.get_content()
+            })
+            .unwrap();
+
+        debug!("label filter: {}", label);
+        tx.send(Event::FilterEditorByLabel(Some(label.to_string())))
+            .unwrap();
+        s.pop_layer();
+    })
+    .title("Filter by Label");
+    s.add_layer(dialog);
+}
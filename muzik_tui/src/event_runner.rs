@@ -7,10 +7,16 @@ use cursive::{
 };
 use eyre::{Context, Result};
 use muzik_common::{
-    database::AppSong,
+    data::AudioProperties,
+    database::{error::DatabaseError, AppSong, DEFAULT_SEARCH_LIMIT},
     entities::*,
+    lock::MusicDirLock,
+    query::SongQuery,
     tags,
-    util::{download_from_youtube, search_youtube, search_youtube_playlist},
+    util::{
+        download_from_youtube, provenance_from_download, search_youtube, search_youtube_playlist,
+        source_of_video,
+    },
 };
 use tracing::{debug, error, info, instrument, warn};
 use youtube_dl::SingleVideo;
@@ -26,6 +32,10 @@ struct AppState {
     song_list: Option<Vec<AppSong>>,
     song_index: Option<usize>,
     current_selected_song: Option<AppSong>,
+    /// only songs with this label are listed in the editor
+    label_filter: Option<String>,
+    /// only songs matching this search are listed in the editor, best match first
+    search: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -99,7 +109,7 @@ impl EventRunner {
         let filename_format = format!("{} - {} {}.%(ext)s", title, artist, id);
         let filename = format!("{} - {} {}.opus", title, artist, id);
         let filename = song.get_music_dir().join(filename);
-        let _youtube = download_from_youtube(
+        let video = download_from_youtube(
             song.get_yt_id().unwrap(),
             song.get_music_dir().display().to_string(),
             filename_format,
@@ -111,6 +121,12 @@ impl EventRunner {
             let artist = song.get_artists_string();
             let status_text = format!("Download finished for: {} - {}", title, artist);
             self.notify_ui(status_text);
+            if let Some(song_id) = song.id {
+                self.config
+                    .db_new
+                    .set_download_provenance(song_id, &provenance_from_download(&video).await)
+                    .await?;
+            }
         } else {
             println!("File not found after downloading");
         }
@@ -128,9 +144,11 @@ impl EventRunner {
         let artist = song.get_artists_string();
         let status_text = format!("Inserting tags for {} - {}", title, artist);
         self.notify_ui(status_text);
+        let _lock = MusicDirLock::try_acquire(&self.config.music_dir, "writing tags")?;
         match tags::write_tags(filename.into(), &song).await {
-            Ok(_) => {
+            Ok(audio_properties) => {
                 info!("wrote tags to file successfully");
+                self.store_audio_properties(&song, audio_properties).await;
                 let title = song.title.clone().unwrap_or_default();
                 let artist = song.get_artists_string();
                 let status_text = format!("Done inserting tags for {} - {}", title, artist);
@@ -143,19 +161,36 @@ impl EventRunner {
         Ok(EventLoopAction::Continue)
     }
 
+    /// keep the database in sync with the file that was just tagged
+    async fn store_audio_properties(&self, song: &AppSong, audio_properties: AudioProperties) {
+        if let Some(id) = song.id {
+            if let Err(e) = self
+                .config
+                .db_new
+                .update_audio_properties(id, &audio_properties)
+                .await
+            {
+                error!("error storing audio properties: {}", e);
+            }
+        }
+    }
+
     #[instrument(skip_all, fields(song.yt_id))]
     async fn update_tags(&self, song: AppSong) -> Result<EventLoopAction> {
         let filename = song.path.as_ref().unwrap();
+        let _lock = MusicDirLock::try_acquire(&self.config.music_dir, "writing tags")?;
         match tags::write_tags(filename.into(), &song).await {
-            Ok(_) => {
+            Ok(audio_properties) => {
                 info!("wrote tags to file successfully");
+                self.store_audio_properties(&song, audio_properties).await;
                 self.tx.send(Event::ChangeFilename(song))?;
             }
             Err(e) => {
                 if let Some(npath) = song.npath.clone() {
                     match tags::write_tags(npath, &song).await {
-                        Ok(_) => {
+                        Ok(audio_properties) => {
                             info!("wrote tags to file successfully");
+                            self.store_audio_properties(&song, audio_properties).await;
                             self.tx.send(Event::ChangeFilename(song))?;
                         }
                         Err(e) => {
@@ -172,30 +207,38 @@ impl EventRunner {
 
     #[instrument(skip_all, fields(song.yt_id))]
     async fn insert_song_database(&self, song: AppSong) -> Result<EventLoopAction> {
-        self.config
-            .db_new
-            .insert_from_app_song(song.clone())
-            .await?;
+        // the id is needed to store the audio properties after tagging
+        let song = self.config.db_new.insert_from_app_song(song).await?;
 
         self.tx.send(Event::YoutubeDownload(song))?;
         Ok(EventLoopAction::Continue)
     }
 
-    #[instrument(skip_all, fields(song.yt_id))]
-    async fn update_song_database(&self, song: AppSong) -> Result<EventLoopAction> {
+    /// Updates the songs changed by the YouTube sync in one transaction, if one fails none
+    /// of them are changed
+    #[instrument(skip_all, fields(songs = songs.len()))]
+    async fn update_songs_database(&self, songs: Vec<AppSong>) -> Result<EventLoopAction> {
         match self
             .config
             .db_new
-            .update_all_from_app_song(song.clone())
+            .transaction(|db| async move {
+                for song in &songs {
+                    db.update_all_from_app_song(song.clone(), change_set::ChangeOrigin::Sync)
+                        .await?;
+                }
+                Ok(songs)
+            })
             .await
         {
-            Ok(_) => {
-                info!("updated song in database");
-                self.tx.send(Event::UpdateTags(song))?;
+            Ok(songs) => {
+                info!("updated {} songs in database", songs.len());
+                for song in songs {
+                    self.tx.send(Event::UpdateTags(song))?;
+                }
                 self.tx.send(Event::UpdateLocalDatabase)?;
             }
             Err(e) => {
-                error!("failed to update song in database: {}", e);
+                error!("failed to update songs in database: {}", e);
             }
         };
 
@@ -204,7 +247,12 @@ impl EventRunner {
 
     #[instrument(skip_all, fields(song.yt_id))]
     async fn delete_song_database(&self, song: AppSong) -> Result<EventLoopAction> {
-        // TODO: db_new
+        let Some(path) = song.path.clone() else {
+            error!("song has no file to delete");
+            self.notify_ui("Can't delete a song without a file".to_string());
+            return Ok(EventLoopAction::Continue);
+        };
+        let _lock = MusicDirLock::try_acquire(&self.config.music_dir, "deleting a song")?;
         match self
             .config
             .db_new
@@ -213,7 +261,14 @@ impl EventRunner {
         {
             Ok(_) => {
                 info!("deleted song from database");
-                std::fs::remove_file(song.path.clone().unwrap()).unwrap_or_default();
+                if let Err(e) = std::fs::remove_file(&path) {
+                    error!("can't delete {}: {}", path.display(), e);
+                    self.notify_ui(format!(
+                        "Deleted the record but not the file {}: {}",
+                        path.display(),
+                        e
+                    ));
+                }
                 self.tx.send(Event::UpdateLocalDatabase)?;
             }
             Err(e) => {
@@ -223,9 +278,42 @@ impl EventRunner {
         Ok(EventLoopAction::Continue)
     }
 
+    /// Shows what would be removed and asks before removing it
+    #[instrument(skip_all)]
+    async fn clean_orphans(&self) -> Result<EventLoopAction> {
+        let report = self.config.db_new.clean_orphans(true).await?;
+        let ttx = self.get_tx();
+        self.cb_sink
+            .send(Box::new(move |siv: &mut Cursive| {
+                if report.is_empty() {
+                    siv.add_layer(Dialog::text(report.summary()).dismiss_button("Close"));
+                } else {
+                    let text = format!("{}\nDelete these entries?", report.summary());
+                    let confirm = Dialog::text(text).dismiss_button("Cancel").button(
+                        "Delete",
+                        move |siv: &mut Cursive| {
+                            siv.pop_layer();
+                            ttx.send(Event::CleanOrphansConfirm).unwrap();
+                        },
+                    );
+                    siv.add_layer(confirm);
+                }
+            }))
+            .unwrap();
+        Ok(EventLoopAction::Continue)
+    }
+
+    #[instrument(skip_all)]
+    async fn clean_orphans_confirm(&self) -> Result<EventLoopAction> {
+        self.config.db_new.clean_orphans(false).await?;
+        self.tx.send(Event::UpdateLocalDatabase)?;
+        Ok(EventLoopAction::Continue)
+    }
+
     #[instrument(skip_all, fields(song.yt_id))]
     async fn change_filename(&self, song: AppSong) -> Result<EventLoopAction> {
         if let Some(npath) = song.npath {
+            let _lock = MusicDirLock::try_acquire(&self.config.music_dir, "renaming a song")?;
             std::fs::rename(song.path.as_ref().unwrap(), npath)?;
         } else {
             debug!("no path changes needed");
@@ -241,6 +329,7 @@ impl EventRunner {
             debug!("found playlists in config");
 
             let mut vid_vec = vec![];
+            let mut playlist_updates = vec![];
             for playlist_id in playlist_list {
                 self.notify_ui(format!("Syncing with playlist: {}", playlist_id));
                 debug!("playlist: {}", playlist_id);
@@ -251,22 +340,32 @@ impl EventRunner {
                 if let Ok(videos) = videos {
                     for vid in videos {
                         debug!("got {} - {}", vid.title, vid.channel.clone().unwrap());
-                        debug!(
-                            "found in database: {}",
-                            self.check_yt_duplicate(vid.id.clone())
-                        );
                         // check for dupes
-                        if let Some(song) = self.find_yt_duplicate(vid.id.clone()) {
+                        if let Some(song) = self.find_yt_duplicate(vid.id.clone()).await? {
                             debug!("yt id {} exists in database", vid.id.clone());
-                            // if playlist value is empty then update, else ignore
-                            if song.yt_playlist.is_none() {
+                            // add the playlist if the song isn't in it yet, else ignore
+                            let in_playlist = song.yt_playlist.as_ref().is_some_and(|playlists| {
+                                playlists
+                                    .iter()
+                                    .any(|p| p.youtube_playlist_id == playlist_id)
+                            });
+                            if !in_playlist {
                                 debug!(
-                                    "yt id {} has no playlist, updating value to {}",
+                                    "yt id {} is not in playlist, adding it to {}",
                                     vid.id.clone(),
                                     &playlist_id
                                 );
-                                let song = song.with_yt_playlist_id(Some(playlist_id.clone()));
-                                self.tx.send(Event::UpdateSongDatabase(song))?;
+                                // a song can be new in several playlists, keep all of them
+                                let mut song = playlist_updates
+                                    .iter()
+                                    .position(|update: &AppSong| update.id == song.id)
+                                    .map(|index| playlist_updates.swap_remove(index))
+                                    .unwrap_or(song);
+                                let song = song.add_yt_playlist_id(youtube_playlist_id::Model {
+                                    id: 0,
+                                    youtube_playlist_id: playlist_id.clone(),
+                                });
+                                playlist_updates.push(song);
                             } else {
                                 debug!("yt id {} has playlist {}", vid.id.clone(), playlist_id);
                             }
@@ -280,6 +379,9 @@ impl EventRunner {
                     debug!("fail");
                 };
             }
+            if !playlist_updates.is_empty() {
+                self.tx.send(Event::UpdateSongsDatabase(playlist_updates))?;
+            }
             let ttx = self.get_tx();
             self.cb_sink
                 .send(Box::new(|siv: &mut Cursive| {
@@ -400,11 +502,7 @@ impl EventRunner {
     #[instrument(skip_all)]
     async fn update_local_database(&mut self) -> Result<EventLoopAction> {
         // TODO: db_new
-        let song_list = self
-            .config
-            .db_new
-            .get_all_songs(self.config.music_dir.clone())
-            .await?;
+        let song_list = self.get_editor_songs().await?;
         self.state.song_list = Some(song_list);
         self.tx.send(Event::UpdateEditorSongSelectView)?;
         self.tx.send(Event::UpdateEditorMetadataSelectView(
@@ -416,11 +514,7 @@ impl EventRunner {
 
     #[instrument(skip_all)]
     async fn update_editor_song_select_view(&mut self) -> Result<EventLoopAction> {
-        let song_list = self
-            .config
-            .db_new
-            .get_all_songs(self.config.music_dir.clone())
-            .await?;
+        let song_list = self.get_editor_songs().await?;
         self.state.song_list = Some(song_list);
         let index = self.state.song_index.unwrap_or(0);
 
@@ -450,10 +544,21 @@ impl EventRunner {
         index: usize,
     ) -> Result<EventLoopAction> {
         self.state.song_index = Some(index);
-        let mut song_list = self.state.song_list.clone().unwrap();
-        let song = song_list.get_mut(index).unwrap();
+        // the label filter can leave the list empty
+        let Some(song) = self
+            .state
+            .song_list
+            .as_ref()
+            .and_then(|song_list| song_list.get(index))
+            .cloned()
+        else {
+            return Ok(EventLoopAction::Continue);
+        };
         self.state.current_selected_song = Some(song.clone());
-        let song = song.clone();
+        let versions = match song.id {
+            Some(song_id) => self.config.db_new.describe_song_versions(song_id).await?,
+            None => vec![],
+        };
         self.cb_sink
             .send(Box::new(move |siv: &mut Cursive| {
                 //siv.call_on_name("ar", callback)
@@ -494,6 +599,24 @@ impl EventRunner {
                         }
                     },
                 );
+                siv.call_on_name(
+                    "metadata_label_select_view",
+                    |view: &mut SelectView<String>| {
+                        view.clear();
+                        for label in song1.label.iter().flatten() {
+                            view.add_item(label.name.clone(), label.name.clone());
+                        }
+                    },
+                );
+                siv.call_on_name(
+                    "metadata_versions_select_view",
+                    |view: &mut SelectView<i32>| {
+                        view.clear();
+                        for (song_id, description) in versions {
+                            view.add_item(description, song_id);
+                        }
+                    },
+                );
                 siv.call_on_name("select_metadata", |view: &mut SelectView<String>| {
                     view.clear();
                     let title = song.get_title_string();
@@ -538,7 +661,10 @@ impl EventRunner {
             .with_albums(metadata.album.unwrap_or("Unknown".to_string()))
             .with_artists_string(metadata.artist.unwrap_or("Unknown".to_string()))
             .with_genre(genre)
-            .with_yt_id(Some(metadata.id))
+            .with_yt_id(Some(metadata.id.clone()))
+            .with_source(source_of_video(&metadata.video))
+            .with_source_id(Some(metadata.id))
+            .with_source_url(metadata.video.webpage_url.clone())
             .with_tb_url(metadata.video.thumbnail)
             .compute_new_filename();
 
@@ -550,7 +676,7 @@ impl EventRunner {
     #[instrument(skip_all)]
     async fn on_download_video_select(&self, video: SingleVideo) -> Result<EventLoopAction> {
         // Show popup to confirm
-        let is_existing = self.check_yt_duplicate(video.id.clone());
+        let is_existing = self.check_yt_duplicate(video.id.clone()).await?;
         let title = video.title.clone();
         let channel = video
             .channel
@@ -610,7 +736,10 @@ impl EventRunner {
 
         let mut song = AppSong::new()
             .with_music_dir(Some(self.config.music_dir.clone()))
-            .with_yt_id(Some(metadata.id))
+            .with_yt_id(Some(metadata.id.clone()))
+            .with_source(source_of_video(&metadata.video))
+            .with_source_id(Some(metadata.id))
+            .with_source_url(metadata.video.webpage_url.clone())
             .with_title(metadata.title)
             .with_artists_string(metadata.artist.unwrap_or("Unknown".to_string()))
             .with_albums(metadata.album.unwrap_or("Unknown".to_string()))
@@ -626,7 +755,6 @@ impl EventRunner {
     }
 
     async fn metadata_editor_add_artist(&self, artist: String) -> Result<EventLoopAction> {
-        let artist_id = self.config.db_new.insert_artist(artist).await?;
         let song_id = self
             .state
             .current_selected_song
@@ -634,11 +762,16 @@ impl EventRunner {
             .expect("current_selected_song is not empty")
             .id
             .expect("id is not empty");
-
-        let _song_artist = self
-            .config
+        self.config
             .db_new
-            .insert_song_artist(artist_id, song_id)
+            .transaction(|db| async move {
+                let artist_id = db.insert_artist(artist).await?;
+                let before = db.snapshot_song(song_id).await?;
+                db.insert_song_artist(artist_id, song_id).await?;
+                db.record_song_changes(before, change_set::ChangeOrigin::Tui)
+                    .await?;
+                Ok(())
+            })
             .await?;
         self.tx.send(Event::UpdateLocalDatabase)?;
         Ok(EventLoopAction::Continue)
@@ -650,26 +783,28 @@ impl EventRunner {
         old: String,
         new: String,
     ) -> Result<EventLoopAction> {
-        let artist_id = self.config.db_new.insert_artist(new).await?;
-
-        let old_artist_id = self.config.db_new.insert_artist(old).await?;
-
-        if old_artist_id != artist_id {
-            let song_id = self
-                .state
-                .current_selected_song
-                .clone()
-                .expect("current_selected_song is not empty")
-                .id
-                .expect("id is not empty");
-            let _song_artist = self
-                .config
-                .db_new
-                .insert_song_artist(artist_id, song_id)
-                .await?;
-
-            self.config.db_new.delete_song_artist(old_artist_id).await?;
-        }
+        let song_id = self
+            .state
+            .current_selected_song
+            .clone()
+            .expect("current_selected_song is not empty")
+            .id
+            .expect("id is not empty");
+        self.config
+            .db_new
+            .transaction(|db| async move {
+                let artist_id = db.insert_artist(new).await?;
+                let old_artist_id = db.insert_artist(old).await?;
+                if old_artist_id != artist_id {
+                    let before = db.snapshot_song(song_id).await?;
+                    db.insert_song_artist(artist_id, song_id).await?;
+                    db.remove_song_artist(song_id, old_artist_id).await?;
+                    db.record_song_changes(before, change_set::ChangeOrigin::Tui)
+                        .await?;
+                }
+                Ok(())
+            })
+            .await?;
         self.tx.send(Event::UpdateLocalDatabase)?;
         Ok(EventLoopAction::Continue)
     }
@@ -677,36 +812,46 @@ impl EventRunner {
     #[tracing::instrument(skip_all)]
     async fn metadata_editor_edit_album(
         &self,
-        old: String,
+        old_album_id: i32,
         new: String,
     ) -> Result<EventLoopAction> {
-        let album_id = self.config.db_new.insert_album(new).await?;
-
-        let old_album_id = self.config.db_new.insert_album(old).await?;
-
-        if old_album_id != album_id {
-            let song_id = self
-                .state
-                .current_selected_song
-                .clone()
-                .expect("current_selected_song is not empty")
-                .id
-                .expect("id is not empty");
-            let _song_album = self
-                .config
-                .db_new
-                .insert_song_album(album_id, song_id)
-                .await?;
-
-            self.config.db_new.delete_song_album(old_album_id).await?;
-        }
+        let song_id = self
+            .state
+            .current_selected_song
+            .clone()
+            .expect("current_selected_song is not empty")
+            .id
+            .expect("id is not empty");
+        self.config
+            .db_new
+            .transaction(|db| async move {
+                // albums of different album artists may share the name, so the linked one
+                // is looked up by the id the editor selected
+                if !db
+                    .get_song_albums(song_id)
+                    .await?
+                    .iter()
+                    .any(|album| album.id == old_album_id)
+                {
+                    return Err(DatabaseError::NoAlbumFound);
+                }
+                let album_id = db.insert_album(new).await?;
+                if old_album_id != album_id {
+                    let before = db.snapshot_song(song_id).await?;
+                    db.insert_song_album(album_id, song_id).await?;
+                    db.remove_song_album(song_id, old_album_id).await?;
+                    db.record_song_changes(before, change_set::ChangeOrigin::Tui)
+                        .await?;
+                }
+                Ok(())
+            })
+            .await?;
         self.tx.send(Event::UpdateLocalDatabase)?;
         Ok(EventLoopAction::Continue)
     }
 
     #[tracing::instrument(skip_all)]
     async fn metadata_editor_add_album(&self, album: String) -> Result<EventLoopAction> {
-        let album_id = self.config.db_new.insert_album(album).await?;
         let song_id = self
             .state
             .current_selected_song
@@ -714,16 +859,79 @@ impl EventRunner {
             .expect("current_selected_song is not empty")
             .id
             .expect("id is not empty");
+        self.config
+            .db_new
+            .transaction(|db| async move {
+                let album_id = db.insert_album(album).await?;
+                let before = db.snapshot_song(song_id).await?;
+                db.insert_song_album(album_id, song_id).await?;
+                db.record_song_changes(before, change_set::ChangeOrigin::Tui)
+                    .await?;
+                Ok(())
+            })
+            .await?;
+        self.tx.send(Event::UpdateLocalDatabase)?;
+        Ok(EventLoopAction::Continue)
+    }
 
-        let _song_album = self
-            .config
+    #[instrument(skip(self))]
+    async fn metadata_editor_add_label(&self, label: String) -> Result<EventLoopAction> {
+        let song_id = self
+            .state
+            .current_selected_song
+            .clone()
+            .expect("current_selected_song is not empty")
+            .id
+            .expect("id is not empty");
+        self.config
+            .db_new
+            .add_song_label(song_id, label, self.label_music_dir())
+            .await?;
+        self.tx.send(Event::UpdateLocalDatabase)?;
+        Ok(EventLoopAction::Continue)
+    }
+
+    #[instrument(skip(self))]
+    async fn metadata_editor_remove_label(&self, label: String) -> Result<EventLoopAction> {
+        let song_id = self
+            .state
+            .current_selected_song
+            .clone()
+            .expect("current_selected_song is not empty")
+            .id
+            .expect("id is not empty");
+        self.config
             .db_new
-            .insert_song_album(album_id, song_id)
+            .remove_song_label(song_id, &label, self.label_music_dir())
             .await?;
         self.tx.send(Event::UpdateLocalDatabase)?;
         Ok(EventLoopAction::Continue)
     }
 
+    #[instrument(skip(self))]
+    async fn filter_editor_by_label(&mut self, label: Option<String>) -> Result<EventLoopAction> {
+        self.state.label_filter = label.filter(|label| !label.trim().is_empty());
+        self.state.song_index = Some(0);
+        self.tx.send(Event::UpdateLocalDatabase)?;
+        match self.state.label_filter.as_ref() {
+            Some(label) => self.notify_ui(format!("showing songs labelled {}", label)),
+            None => self.notify_ui("showing all songs".to_string()),
+        }
+        Ok(EventLoopAction::Continue)
+    }
+
+    #[instrument(skip(self))]
+    async fn filter_editor_by_search(&mut self, search: Option<String>) -> Result<EventLoopAction> {
+        self.state.search = search.filter(|search| !search.trim().is_empty());
+        self.state.song_index = Some(0);
+        self.tx.send(Event::UpdateLocalDatabase)?;
+        match self.state.search.as_ref() {
+            Some(search) => self.notify_ui(format!("showing songs matching {}", search)),
+            None => self.notify_ui("showing all songs".to_string()),
+        }
+        Ok(EventLoopAction::Continue)
+    }
+
     #[instrument(skip_all)]
     async fn quit_event_loop(&self) -> Result<EventLoopAction> {
         Ok(EventLoopAction::Quit)
@@ -740,10 +948,12 @@ impl EventRunner {
             Event::InsertTags(song) => self.insert_tags(song).await,
             Event::UpdateTags(song) => self.update_tags(song).await,
             Event::InsertSongDatabase(song) => self.insert_song_database(song).await,
-            Event::UpdateSongDatabase(song) => self.update_song_database(song).await,
+            Event::UpdateSongsDatabase(songs) => self.update_songs_database(songs).await,
             Event::DeleteSongDatabase(song) => self.delete_song_database(song).await,
             Event::ChangeFilename(song) => self.change_filename(song).await,
             Event::SyncWithYoutube => self.sync_with_youtube().await,
+            Event::CleanOrphans => self.clean_orphans().await,
+            Event::CleanOrphansConfirm => self.clean_orphans_confirm().await,
             Event::VerifyAllSongIntegrity() => self.verify_all_song_integrity().await,
             Event::DownloadAllMissingFromDatabase => self.download_all_missing_from_db().await,
             Event::UpdateLocalDatabase => self.update_local_database().await,
@@ -763,10 +973,16 @@ impl EventRunner {
             Event::MetadataEditorEditArtist((old, new)) => {
                 self.metadata_editor_edit_artist(old, new).await
             }
-            Event::MetadataEditorEditAlbum((old, new)) => {
-                self.metadata_editor_edit_album(old, new).await
+            Event::MetadataEditorEditAlbum((old_album_id, new)) => {
+                self.metadata_editor_edit_album(old_album_id, new).await
             }
             Event::MetadataEditorAddAlbum(album) => self.metadata_editor_add_album(album).await,
+            Event::MetadataEditorAddLabel(label) => self.metadata_editor_add_label(label).await,
+            Event::MetadataEditorRemoveLabel(label) => {
+                self.metadata_editor_remove_label(label).await
+            }
+            Event::FilterEditorByLabel(label) => self.filter_editor_by_label(label).await,
+            Event::FilterEditorBySearch(search) => self.filter_editor_by_search(search).await,
             Event::QuitEventLoop => self.quit_event_loop().await,
         }?;
         Ok(action)
@@ -784,47 +1000,59 @@ impl EventRunner {
             .unwrap();
     }
 
-    fn check_yt_duplicate(&self, id: String) -> bool {
-        debug!("got id: {}", id);
-        if let Some(song_list) = &self.state.song_list {
-            for s in song_list {
-                if let Some(ytid) = s.get_yt_id() {
-                    if id == ytid {
-                        debug!(
-                            "comparing ytid:{} to id: {} = result {}",
-                            ytid,
-                            id,
-                            id == ytid
-                        );
-                        return id == ytid;
-                    };
-                }
-            }
-            false
-        } else {
-            false
+    /// Songs listed in the editor, narrowed down by the search and the label filter
+    async fn get_editor_songs(&self) -> Result<Vec<AppSong>> {
+        let mut query = SongQuery::new();
+        if let Some(label) = self.state.label_filter.as_ref() {
+            query = query.with_label(label.clone());
+        }
+        let db = &self.config.db_new;
+        let Some(search) = self.state.search.as_ref() else {
+            return Ok(db
+                .query_songs(&query, self.config.music_dir.clone())
+                .await?);
+        };
+
+        let mut songs = db
+            .search(
+                search,
+                Some(DEFAULT_SEARCH_LIMIT),
+                self.config.music_dir.clone(),
+            )
+            .await?;
+        if self.state.label_filter.is_some() {
+            let labelled = db
+                .query_songs(&query, self.config.music_dir.clone())
+                .await?
+                .into_iter()
+                .filter_map(|song| song.id)
+                .collect::<Vec<_>>();
+            songs.retain(|song| song.id.is_some_and(|id| labelled.contains(&id)));
         }
+        Ok(songs)
+    }
+
+    /// Music dir to rewrite the label tags in, `None` unless `label_tags` is set
+    fn label_music_dir(&self) -> Option<std::path::PathBuf> {
+        self.config
+            .label_tags
+            .then(|| self.config.music_dir.clone())
     }
-    fn find_yt_duplicate(&self, id: String) -> Option<AppSong> {
+
+    async fn check_yt_duplicate(&self, id: String) -> Result<bool> {
         debug!("got id: {}", id);
-        if let Some(song_list) = &self.state.song_list {
-            for s in song_list {
-                if let Some(ytid) = s.get_yt_id() {
-                    if id == ytid {
-                        debug!(
-                            "comparing ytid:{} to id: {} = result {}",
-                            ytid,
-                            id,
-                            id == ytid
-                        );
-                        return Some(s.clone());
-                    };
-                }
-            }
-            None
-        } else {
-            None
-        }
+        let query = SongQuery::new().with_youtube_id(id);
+        Ok(self.config.db_new.count_songs(&query).await? > 0)
+    }
+    async fn find_yt_duplicate(&self, id: String) -> Result<Option<AppSong>> {
+        debug!("got id: {}", id);
+        let query = SongQuery::new().with_youtube_id(id).with_limit(1);
+        Ok(self
+            .config
+            .db_new
+            .query_songs(&query, self.config.music_dir.clone())
+            .await?
+            .pop())
     }
 }
 
@@ -833,13 +1061,15 @@ pub enum Event {
     YoutubeDownload(AppSong),
     InsertTags(AppSong),
     InsertSongDatabase(AppSong),
-    UpdateSongDatabase(AppSong),
+    UpdateSongsDatabase(Vec<AppSong>),
     UpdateTags(AppSong),
     DeleteSongDatabase(AppSong),
     VerifyAllSongIntegrity(),
     DownloadAllMissingFromDatabase,
     ChangeFilename(AppSong),
     SyncWithYoutube,
+    CleanOrphans,
+    CleanOrphansConfirm,
     QuitEventLoop,
 
     UpdateLocalDatabase,
@@ -853,8 +1083,15 @@ pub enum Event {
     MetadataEditorAddArtist(String),
     /// (old, new)
     MetadataEditorEditArtist((String, String)),
-    MetadataEditorEditAlbum((String, String)),
+    /// (old album id, new)
+    MetadataEditorEditAlbum((i32, String)),
     MetadataEditorAddAlbum(String),
+    MetadataEditorAddLabel(String),
+    MetadataEditorRemoveLabel(String),
+    /// `None` lists every song again
+    FilterEditorByLabel(Option<String>),
+    /// `None` lists every song again
+    FilterEditorBySearch(Option<String>),
 }
 
 pub struct DownloadMetadataInput {
@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use clap::Parser;
 use eyre::Result;
 use tracing::{error, info};
 use tracing_appender::non_blocking::WorkerGuard;
@@ -12,13 +13,22 @@ mod event_runner;
 mod metadata;
 mod tui;
 
+#[derive(Debug, Parser)]
+#[command(name = "muziktui")]
+struct Cli {
+    /// Library from the config to open
+    #[arg(short, long)]
+    library: Option<String>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Cli::parse();
     let mut _guards = start_tui_logger(PathBuf::from("/tmp"));
-    tui_command().await
+    tui_command(args.library).await
 }
-async fn tui_command() -> Result<()> {
-    match tui::run_tui().await {
+async fn tui_command(library: Option<String>) -> Result<()> {
+    match tui::run_tui(library).await {
         Ok(_) => (),
         Err(e) => {
             error!("Fatal error: {}", e);
@@ -11,9 +11,10 @@ use crate::{config::ReadConfig, download, editor, event_runner};
 
 use crate::event_runner::Event;
 
-pub async fn run_tui() -> Result<()> {
+pub async fn run_tui(library: Option<String>) -> Result<()> {
     let mut siv = Cursive::new();
-    let conf = ReadConfig::read_config(None).await?;
+    let conf = ReadConfig::read_config(None, library).await?;
+    let title = format!("muziktui - {}", conf.library);
     let mut ev_man = event_runner::EventRunner::new(siv.cb_sink().clone(), conf).await;
     let tx = ev_man.get_tx();
     let ev_loop = tokio::spawn(async move {
@@ -45,6 +46,9 @@ pub async fn run_tui() -> Result<()> {
     let verify_tx = tx.clone();
     let missing_tx = tx.clone();
     let sync_tx = tx.clone();
+    let clean_tx = tx.clone();
+    let label_tx = tx.clone();
+    let search_tx = tx.clone();
 
     let tab_panel_tx = tx.clone();
     let mut tab_panel = TabPanel::new();
@@ -64,6 +68,13 @@ pub async fn run_tui() -> Result<()> {
                     .unwrap()
             })
             .on_event('S', move |_| sync_tx.send(Event::SyncWithYoutube).unwrap())
+            .on_event('C', move |_| clean_tx.send(Event::CleanOrphans).unwrap())
+            .on_event('L', move |s| {
+                editor::on_label_filter_command(s, label_tx.clone())
+            })
+            .on_event('/', move |s| {
+                editor::on_search_command(s, search_tx.clone())
+            })
             .with_name("Editor"),
     );
     tab_panel.add_tab(download::draw_download_tab(&mut siv, tab_panel_tx).with_name("Download"));
@@ -89,7 +100,7 @@ pub async fn run_tui() -> Result<()> {
                 .unwrap();
             }),
     )
-    .title("muziktui");
+    .title(title);
     siv.add_fullscreen_layer(panel.full_screen());
 
     let quit_tx = tx;